@@ -0,0 +1,252 @@
+//! Chart time for rendering and judgement. The clock runs on frame deltas and is pulled towards the
+//! playing song's position, which the audio thread only updates once per buffer.
+//!
+//! Chart times are seconds since the start of the audio, the chart's own offset is already part of
+//! them through its `TimingMap`. The clock only applies the user's latency offset.
+
+use eclale_audio::{StaticSoundHandle, Tween};
+use eclale_chart::Time;
+
+#[derive(Clone, Debug)]
+pub(crate) struct GameClockSettings {
+    /// Share of the drift from the song removed per second. Must stay below
+    /// `1 / resync_threshold` so corrections never move the clock backwards.
+    pub(crate) correction_rate: f64,
+
+    /// Drift in seconds above which the clock jumps to the song instead of easing towards it, e.g.
+    /// after a stall.
+    pub(crate) resync_threshold: f64,
+}
+
+impl Default for GameClockSettings {
+    fn default() -> Self {
+        Self {
+            correction_rate: 4.0,
+            resync_threshold: 0.2,
+        }
+    }
+}
+
+/// Song the clock follows. Implemented by the audio handle, tests replace it with a fake.
+pub(crate) trait SongPosition {
+    /// Seconds since the start of the audio, only updated once per audio buffer.
+    fn position(&self) -> f64;
+
+    fn stop(&mut self);
+}
+
+impl SongPosition for StaticSoundHandle {
+    fn position(&self) -> f64 {
+        StaticSoundHandle::position(self)
+    }
+
+    fn stop(&mut self) {
+        StaticSoundHandle::stop(self, Tween::default());
+    }
+}
+
+struct SongSync<S> {
+    handle: S,
+    /// Position last reported by the handle, `None` until the song has started.
+    reported: Option<f64>,
+    /// Seconds since `reported` last changed.
+    since_report: f64,
+}
+
+pub(crate) struct GameClock<S = StaticSoundHandle> {
+    settings: GameClockSettings,
+    song: Option<SongSync<S>>,
+    /// Seconds since the start of the audio.
+    position: f64,
+    /// Subtracted from the song position to get chart time.
+    user_offset: f64,
+    /// Set by `stop`, the clock no longer advances.
+    is_stopped: bool,
+}
+
+impl<S: SongPosition> GameClock<S> {
+    /// `user_offset` compensates for output latency, positive values make notes come later.
+    pub(crate) fn new(song: Option<S>, user_offset: f32, settings: GameClockSettings) -> Self {
+        Self {
+            settings,
+            song: song.map(|handle| SongSync {
+                handle,
+                reported: None,
+                since_report: 0.0,
+            }),
+            position: 0.0,
+            user_offset: f64::from(user_offset),
+            is_stopped: false,
+        }
+    }
+
+    /// Stops the song and freezes the clock at its current time.
+    pub(crate) fn stop(&mut self) {
+        if let Some(mut song) = self.song.take() {
+            song.handle.stop();
+        }
+        self.is_stopped = true;
+    }
+
+    pub(crate) fn time(&self) -> Time {
+        Time((self.position - self.user_offset) as f32)
+    }
+
+    /// Advances the clock by `dt` seconds of wall time and returns the new chart time. Without a
+    /// song the clock runs on `dt` alone, a stopped clock does not advance.
+    pub(crate) fn update(&mut self, dt: f32) -> Time {
+        if self.is_stopped {
+            return self.time();
+        }
+        let dt = f64::from(dt);
+
+        let Some(song) = &mut self.song else {
+            self.position += dt;
+            return self.time();
+        };
+
+        let position = song.handle.position();
+        match song.reported {
+            Some(reported) if reported == position => song.since_report += dt,
+            // Hold the clock until the audio thread starts playing.
+            None if position <= 0.0 => return self.time(),
+            _ => {
+                song.reported = Some(position);
+                song.since_report = 0.0;
+            }
+        }
+
+        // The handle lags behind by up to one buffer, extrapolate from its last update.
+        let estimate = position + song.since_report;
+        self.position += dt;
+        let drift = estimate - self.position;
+        if drift.abs() > self.settings.resync_threshold {
+            self.position = estimate;
+        } else {
+            self.position += drift * (self.settings.correction_rate * dt).min(1.0);
+        }
+
+        self.time()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+
+    /// Song whose reported position is set by the test.
+    #[derive(Clone, Default)]
+    struct FakeSong {
+        position: Rc<Cell<f64>>,
+        is_stopped: Rc<Cell<bool>>,
+    }
+
+    impl SongPosition for FakeSong {
+        fn position(&self) -> f64 {
+            self.position.get()
+        }
+
+        fn stop(&mut self) {
+            self.is_stopped.set(true);
+        }
+    }
+
+    fn clock() -> (GameClock<FakeSong>, FakeSong) {
+        let song = FakeSong::default();
+        let clock = GameClock::new(Some(song.clone()), 0.0, GameClockSettings::default());
+        (clock, song)
+    }
+
+    fn assert_time(time: Time, expected: f32) {
+        assert!(
+            (time.0 - expected).abs() < 1e-5,
+            "{:?} != {}",
+            time,
+            expected
+        );
+    }
+
+    #[test]
+    fn clock_waits_for_the_song_then_follows_it() {
+        let (mut clock, song) = clock();
+        assert_time(clock.update(0.1), 0.0);
+
+        song.position.set(0.5);
+        assert_time(clock.update(0.5), 0.5);
+        song.position.set(0.6);
+        assert_time(clock.update(0.1), 0.6);
+    }
+
+    #[test]
+    fn clock_extrapolates_between_reports() {
+        let (mut clock, song) = clock();
+        song.position.set(1.0);
+        assert_time(clock.update(1.0), 1.0);
+
+        // Frames are shorter than audio buffers, the position is reported once for three frames.
+        for frame in 1..=3 {
+            assert_time(clock.update(0.01), 1.0 + 0.01 * frame as f32);
+        }
+        // The next report agrees with the extrapolation and is taken as is.
+        song.position.set(1.04);
+        assert_time(clock.update(0.01), 1.04);
+    }
+
+    #[test]
+    fn small_drift_is_eased_out() {
+        let (mut clock, song) = clock();
+        song.position.set(0.1);
+        assert_time(clock.update(0.1), 0.1);
+
+        // The song runs ahead of the frame clock by 0.05s, 40% of it is removed in 0.1s.
+        song.position.set(0.25);
+        assert_time(clock.update(0.1), 0.22);
+        song.position.set(0.35);
+        let time = clock.update(0.1);
+        assert!(time.0 > 0.32 && time.0 < 0.35, "{:?}", time);
+    }
+
+    #[test]
+    fn large_drift_resyncs() {
+        let (mut clock, song) = clock();
+        song.position.set(0.1);
+        clock.update(0.1);
+
+        // The song jumps ahead, e.g. after a stall.
+        song.position.set(2.0);
+        assert_time(clock.update(0.1), 2.0);
+        // And back, e.g. after the audio thread restarted.
+        song.position.set(1.0);
+        assert_time(clock.update(0.1), 1.0);
+    }
+
+    #[test]
+    fn stopping_stops_the_song() {
+        let (mut clock, song) = clock();
+        song.position.set(0.5);
+        clock.update(0.5);
+
+        clock.stop();
+        assert!(song.is_stopped.get());
+        song.position.set(0.6);
+        assert_time(clock.update(0.1), 0.5);
+    }
+
+    #[test]
+    fn user_offset_delays_chart_time() {
+        let mut clock = GameClock::<FakeSong>::new(None, 0.25, GameClockSettings::default());
+        assert_eq!(clock.time(), Time(-0.25));
+        assert_eq!(clock.update(1.0), Time(0.75));
+    }
+
+    #[test]
+    fn stopped_clock_does_not_advance() {
+        let mut clock = GameClock::<FakeSong>::new(None, 0.0, GameClockSettings::default());
+        assert_eq!(clock.update(0.5), Time(0.5));
+
+        clock.stop();
+        assert_eq!(clock.update(0.5), Time(0.5));
+    }
+}
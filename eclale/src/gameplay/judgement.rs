@@ -0,0 +1,830 @@
+//! Judgement of player input against the notes of a chart. The engine does not know about windows
+//! or rendering, input is fed to it as events with the chart time they happened at.
+
+use std::collections::{HashMap, HashSet};
+
+use eclale_chart::{
+    index::ChartIndex, EvadeNoteSize, FlickDirection, HitNoteType, MovementTarget, NoteId, NoteRef,
+    Notes, Time, TrackPosition,
+};
+
+use super::player::Sweep;
+
+/// Ordered from worst to best.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) enum Judgement {
+    Miss,
+    Good,
+    Great,
+    Perfect,
+    Critical,
+}
+
+/// Half widths of the timing windows in seconds.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TimingWindows {
+    pub(crate) critical: f32,
+    pub(crate) perfect: f32,
+    pub(crate) great: f32,
+    pub(crate) good: f32,
+}
+
+impl Default for TimingWindows {
+    fn default() -> Self {
+        Self {
+            critical: 0.033,
+            perfect: 0.05,
+            great: 0.1,
+            good: 0.15,
+        }
+    }
+}
+
+impl TimingWindows {
+    /// Best judgement whose window contains `offset`, `None` if the input is outside of every
+    /// window.
+    pub(crate) fn judge(&self, offset: f32) -> Option<Judgement> {
+        let offset = offset.abs();
+        [
+            (self.critical, Judgement::Critical),
+            (self.perfect, Judgement::Perfect),
+            (self.great, Judgement::Great),
+            (self.good, Judgement::Good),
+        ]
+        .into_iter()
+        .find(|(window, _)| offset <= *window)
+        .map(|(_, judgement)| judgement)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct JudgementSettings {
+    pub(crate) windows: TimingWindows,
+
+    /// Seconds between hold ticks.
+    pub(crate) hold_tick_interval: f32,
+
+    /// Holds can be released this many seconds before their end without missing ticks. Releasing
+    /// a hold ends it, ticks that were not reached yet are missed.
+    pub(crate) hold_release_window: f32,
+
+    /// Distance in x within which the player collects contact notes.
+    pub(crate) contact_radius: f32,
+
+    /// Distance in x within which the player has to be to hit target notes and to keep the ticks
+    /// of target holds.
+    pub(crate) target_radius: f32,
+
+    /// Distance in x within which normal evade notes hit the player, large ones use twice the
+    /// radius.
+    pub(crate) evade_radius: f32,
+}
+
+impl Default for JudgementSettings {
+    fn default() -> Self {
+        Self {
+            windows: TimingWindows::default(),
+            hold_tick_interval: 0.125,
+            hold_release_window: 0.1,
+            contact_radius: 0.5,
+            target_radius: 0.5,
+            evade_radius: 0.25,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum InputKind {
+    Press(HitNoteType),
+    Release(HitNoteType),
+    Flick(FlickDirection),
+    /// The player moved to x.
+    Move(f32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct InputEvent {
+    pub(crate) time: Time,
+    pub(crate) kind: InputKind,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct HoldTicks {
+    pub(crate) hit: u32,
+    pub(crate) total: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct NoteResult {
+    pub(crate) note: NoteRef,
+    pub(crate) id: NoteId,
+
+    /// Time the note was judged at.
+    pub(crate) time: Time,
+
+    /// Evade notes are `Critical` when dodged and `Miss` when they hit the player.
+    pub(crate) judgement: Judgement,
+
+    /// Input time minus note time in seconds, `None` for misses and notes resolved from the
+    /// player position.
+    pub(crate) offset: Option<f32>,
+
+    /// Set for holds and target holds.
+    pub(crate) ticks: Option<HoldTicks>,
+}
+
+/// Hold or target hold whose start was hit.
+#[derive(Clone, Debug)]
+struct ActiveHold {
+    note: NoteRef,
+    /// Lane of the press that started the hold, releasing it ends the hold.
+    lane: HitNoteType,
+    start_judgement: Judgement,
+    offset: f32,
+    ticks: HoldTicks,
+    ticks_left: u32,
+    next_tick: f32,
+    end: f32,
+}
+
+pub(crate) struct JudgementEngine {
+    notes: Notes,
+    index: ChartIndex,
+    settings: JudgementSettings,
+
+    /// Everything before this time is resolved.
+    time: Time,
+
+    judged: HashSet<NoteRef>,
+    active_holds: Vec<ActiveHold>,
+    /// Number of inputs holding each lane.
+    held_lanes: HashMap<HitNoteType, u32>,
+    player_x: f32,
+    /// Range the player moved through since the last update, contact and evade notes collide
+    /// with all of it.
+    sweep: Sweep,
+
+    /// Indices of player targeted evade notes sorted by the time their movement starts.
+    player_targeted_evades: Vec<usize>,
+    /// Number of `player_targeted_evades` whose target is resolved.
+    resolved_evade_targets: usize,
+    /// Ends of player targeted evade notes resolved against the player's x when they started
+    /// moving.
+    evade_targets: HashMap<usize, TrackPosition>,
+
+    results: Vec<NoteResult>,
+}
+
+impl JudgementEngine {
+    pub(crate) fn new(notes: &Notes, settings: JudgementSettings) -> Self {
+        let mut player_targeted_evades = notes
+            .evades
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| matches!(n.movement.target, MovementTarget::Player { .. }))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        player_targeted_evades.sort_by(|a, b| {
+            notes.evades[*a]
+                .movement
+                .trigger_time
+                .0
+                .total_cmp(&notes.evades[*b].movement.trigger_time.0)
+        });
+
+        Self {
+            notes: notes.clone(),
+            index: ChartIndex::new(notes),
+            settings,
+            time: Time(f32::NEG_INFINITY),
+            judged: HashSet::new(),
+            active_holds: Vec::new(),
+            held_lanes: HashMap::new(),
+            player_x: 0.0,
+            sweep: Sweep::point(0.0),
+            player_targeted_evades,
+            resolved_evade_targets: 0,
+            evade_targets: HashMap::new(),
+            results: Vec::new(),
+        }
+    }
+
+    /// Results since the last call, in the order the notes were judged.
+    pub(crate) fn take_results(&mut self) -> Vec<NoteResult> {
+        std::mem::take(&mut self.results)
+    }
+
+    /// End of the evade note at `index` as it is judged. Player targeted notes follow the player
+    /// until their movement starts, the renderer draws them towards the same end.
+    pub(crate) fn evade_end(&self, index: usize) -> TrackPosition {
+        self.evade_targets
+            .get(&index)
+            .copied()
+            .unwrap_or_else(|| self.notes.evades[index].movement.resolve_end(self.player_x))
+    }
+
+    /// Resolves everything up to `time`. Events have to be processed in time order, events
+    /// earlier than the last update are treated as happening at the time of the update.
+    pub(crate) fn process(&mut self, event: InputEvent) {
+        // The player moves continuously, it sweeps from its last position to x until the event.
+        if let InputKind::Move(x) = event.kind {
+            self.resolve_evade_targets(event.time, x);
+            self.sweep = self.sweep.extend(x);
+            self.player_x = x;
+            self.update(event.time);
+            return;
+        }
+
+        self.update(event.time);
+        let time = self.time;
+
+        match event.kind {
+            InputKind::Press(lane) => {
+                *self.held_lanes.entry(lane).or_default() += 1;
+                self.press(lane, time);
+            }
+            InputKind::Release(lane) => {
+                if let Some(count) = self.held_lanes.get_mut(&lane) {
+                    *count = count.saturating_sub(1);
+                }
+                if !self.is_held(lane) {
+                    self.release_holds(lane, time);
+                }
+            }
+            InputKind::Flick(direction) => self.flick(direction, time),
+            InputKind::Move(_) => {}
+        }
+    }
+
+    /// Resolves misses, hold ticks, contact notes and evade notes up to `time`.
+    pub(crate) fn update(&mut self, time: Time) {
+        if time.0 <= self.time.0 {
+            return;
+        }
+        let (from, to) = (self.time, time);
+
+        self.resolve_evade_targets(to, self.player_x);
+        self.resolve_misses(from, to);
+        self.resolve_contacts(from, to);
+        self.resolve_evades(from, to);
+        self.resolve_holds(to);
+
+        self.sweep = Sweep::point(self.player_x);
+        self.time = time;
+    }
+
+    fn is_held(&self, lane: HitNoteType) -> bool {
+        self.held_lanes.get(&lane).is_some_and(|count| *count > 0)
+    }
+
+    fn note_id(&self, note: NoteRef) -> NoteId {
+        self.notes.get(note).map(|n| n.id()).unwrap_or_default()
+    }
+
+    fn push_result(
+        &mut self,
+        note: NoteRef,
+        time: Time,
+        judgement: Judgement,
+        offset: Option<f32>,
+        ticks: Option<HoldTicks>,
+    ) {
+        self.judged.insert(note);
+        self.results.push(NoteResult {
+            note,
+            id: self.note_id(note),
+            time,
+            judgement,
+            offset,
+            ticks,
+        });
+    }
+
+    /// Presses hit the next note of their lane, or a target the player is on when the lane has no
+    /// note in reach.
+    fn press(&mut self, lane: HitNoteType, time: Time) {
+        // The lane is already held by the hold in progress, other presses of it do nothing.
+        if self.active_holds.iter().any(|h| h.lane == lane) {
+            return;
+        }
+
+        if !self.press_lane(lane, time) {
+            self.press_target(lane, time);
+        }
+    }
+
+    /// Judges the next hit or hold of `lane`, returns whether a note was in reach.
+    fn press_lane(&mut self, lane: HitNoteType, time: Time) -> bool {
+        let windows = self.settings.windows;
+        let Some(next) = self
+            .index
+            .next_in_lane(lane, Time(time.0 - windows.good), |n| {
+                self.judged.contains(&n)
+            })
+        else {
+            return false;
+        };
+        let offset = time.0 - next.time.0;
+        let Some(judgement) = windows.judge(offset) else {
+            return false;
+        };
+
+        self.hit(next.note, lane, time, judgement, offset);
+        true
+    }
+
+    /// Judges the target or target hold closest in time that starts within reach of the player.
+    fn press_target(&mut self, lane: HitNoteType, time: Time) {
+        let windows = self.settings.windows;
+        let (start, end) = (Time(time.0 - windows.good), Time(time.0 + windows.good));
+        let candidate = self
+            .index
+            .targets(start, end)
+            .iter()
+            .chain(self.index.target_holds(start, end))
+            .filter(|n| !self.judged.contains(&n.note))
+            .filter(|n| {
+                self.target_x(n.note)
+                    .is_some_and(|x| (x - self.player_x).abs() <= self.settings.target_radius)
+            })
+            .min_by(|a, b| {
+                (a.time.0 - time.0)
+                    .abs()
+                    .total_cmp(&(b.time.0 - time.0).abs())
+            })
+            .copied();
+
+        if let Some(note) = candidate {
+            let offset = time.0 - note.time.0;
+            if let Some(judgement) = windows.judge(offset) {
+                self.hit(note.note, lane, time, judgement, offset);
+            }
+        }
+    }
+
+    /// x the player has to be at to hit a target or the start of a target hold.
+    fn target_x(&self, note: NoteRef) -> Option<f32> {
+        match note {
+            NoteRef::Target(index) => Some(self.notes.targets[index].position.x),
+            NoteRef::TargetHold(index) => Some(self.notes.target_holds[index].points.first()?.x),
+            _ => None,
+        }
+    }
+
+    /// Records a hit, holds and target holds become active instead.
+    fn hit(
+        &mut self,
+        note: NoteRef,
+        lane: HitNoteType,
+        time: Time,
+        judgement: Judgement,
+        offset: f32,
+    ) {
+        match note {
+            NoteRef::Hold(_) | NoteRef::TargetHold(_) => {
+                self.judged.insert(note);
+                self.start_hold(note, lane, judgement, offset);
+            }
+            note => self.push_result(note, time, judgement, Some(offset), None),
+        }
+    }
+
+    fn hold_points(&self, note: NoteRef) -> &[TrackPosition] {
+        match note {
+            NoteRef::Hold(index) => &self.notes.holds[index].points,
+            NoteRef::TargetHold(index) => &self.notes.target_holds[index].points,
+            _ => &[],
+        }
+    }
+
+    /// Start and end time of a hold or target hold and its number of ticks. Ticks are placed every
+    /// tick interval after the start, up to the release window before the end.
+    fn hold_span(&self, note: NoteRef) -> Option<(f32, f32, u32)> {
+        let points = self.hold_points(note);
+        let (start, end) = (points.first()?.time.0, points.last()?.time.0);
+
+        let interval = self.settings.hold_tick_interval;
+        let length = end - self.settings.hold_release_window - start;
+        let total = if interval > 0.0 && length > 0.0 {
+            (length / interval) as u32
+        } else {
+            0
+        };
+        Some((start, end, total))
+    }
+
+    fn start_hold(&mut self, note: NoteRef, lane: HitNoteType, judgement: Judgement, offset: f32) {
+        let Some((start, end, total)) = self.hold_span(note) else {
+            return;
+        };
+
+        self.active_holds.push(ActiveHold {
+            note,
+            lane,
+            start_judgement: judgement,
+            offset,
+            ticks: HoldTicks { hit: 0, total },
+            ticks_left: total,
+            next_tick: start + self.settings.hold_tick_interval,
+            end,
+        });
+    }
+
+    fn flick(&mut self, direction: FlickDirection, time: Time) {
+        let windows = self.settings.windows;
+        let candidate = self
+            .index
+            .flicks(Time(time.0 - windows.good), Time(time.0 + windows.good))
+            .iter()
+            .find(|n| match n.note {
+                NoteRef::Flick(i) => {
+                    !self.judged.contains(&n.note) && self.notes.flicks[i].direction == direction
+                }
+                _ => false,
+            })
+            .copied();
+
+        if let Some(note) = candidate {
+            let offset = time.0 - note.time.0;
+            if let Some(judgement) = windows.judge(offset) {
+                self.push_result(note.note, time, judgement, Some(offset), None);
+            }
+        }
+    }
+
+    /// Records where the player was when player targeted evade notes started moving. The player
+    /// moves linearly from its x at the last update to `to_x` at `to`.
+    fn resolve_evade_targets(&mut self, to: Time, to_x: f32) {
+        let (from, from_x) = (self.time.0, self.player_x);
+        while let Some(&index) = self.player_targeted_evades.get(self.resolved_evade_targets) {
+            let movement = &self.notes.evades[index].movement;
+            let trigger_time = movement.trigger_time.0;
+            if trigger_time >= to.0 {
+                break;
+            }
+            let span = to.0 - from;
+            let x = if span.is_finite() && span > 0.0 {
+                let progress = ((trigger_time - from) / span).clamp(0.0, 1.0);
+                from_x + (to_x - from_x) * progress
+            } else {
+                to_x
+            };
+            self.evade_targets.insert(index, movement.resolve_end(x));
+            self.resolved_evade_targets += 1;
+        }
+    }
+
+    /// Notes that can no longer be hit in [`from`, `to`) are missed.
+    fn resolve_misses(&mut self, from: Time, to: Time) {
+        let good = self.settings.windows.good;
+        let (from, to) = (Time(from.0 - good), Time(to.0 - good));
+
+        let missed = self
+            .index
+            .hits(from, to)
+            .iter()
+            .chain(self.index.holds(from, to))
+            .chain(self.index.flicks(from, to))
+            .chain(self.index.targets(from, to))
+            .chain(self.index.target_holds(from, to))
+            .filter(|n| !self.judged.contains(&n.note))
+            .copied()
+            .collect::<Vec<_>>();
+
+        for note in missed {
+            let judged_at = Time(note.time.0 + good);
+            let ticks = match note.note {
+                NoteRef::Hold(_) | NoteRef::TargetHold(_) => self
+                    .hold_span(note.note)
+                    .map(|(_, _, total)| HoldTicks { hit: 0, total }),
+                _ => None,
+            };
+            self.push_result(note.note, judged_at, Judgement::Miss, None, ticks);
+        }
+    }
+
+    fn resolve_contacts(&mut self, from: Time, to: Time) {
+        let contacts = self.index.contacts(from, to).to_vec();
+        for note in contacts {
+            let NoteRef::Contact(index) = note.note else {
+                continue;
+            };
+            let x = self.notes.contacts[index].position.x;
+            let judgement = if self.sweep.hits(x, self.settings.contact_radius) {
+                Judgement::Critical
+            } else {
+                Judgement::Miss
+            };
+            self.push_result(note.note, note.time, judgement, None, None);
+        }
+    }
+
+    fn resolve_evades(&mut self, from: Time, to: Time) {
+        let evades = self.index.evades(from, to).to_vec();
+        for note in evades {
+            let NoteRef::Evade(index) = note.note else {
+                continue;
+            };
+            let x = self.evade_end(index).x;
+            let radius = match self.notes.evades[index].size {
+                EvadeNoteSize::Normal => self.settings.evade_radius,
+                EvadeNoteSize::Large => self.settings.evade_radius * 2.0,
+            };
+            let judgement = if self.sweep.hits(x, radius) {
+                Judgement::Miss
+            } else {
+                Judgement::Critical
+            };
+            self.push_result(note.note, note.time, judgement, None, None);
+        }
+    }
+
+    /// Counts ticks of active holds and finishes holds that ended before `to`. Ticks of target
+    /// holds are only hit while the player is on the hold.
+    fn resolve_holds(&mut self, to: Time) {
+        let interval = self.settings.hold_tick_interval;
+        let target_radius = self.settings.target_radius;
+        let (notes, held_lanes, player_x) = (&self.notes, &self.held_lanes, self.player_x);
+
+        for hold in &mut self.active_holds {
+            let is_held = held_lanes.get(&hold.lane).is_some_and(|count| *count > 0);
+            while hold.ticks_left > 0 && hold.next_tick < to.0 {
+                let is_on_hold = match hold.note {
+                    NoteRef::TargetHold(index) => notes.target_holds[index]
+                        .sample_at(Time(hold.next_tick))
+                        .is_some_and(|p| (p.x - player_x).abs() <= target_radius),
+                    _ => true,
+                };
+                if is_held && is_on_hold {
+                    hold.ticks.hit += 1;
+                }
+                hold.ticks_left -= 1;
+                hold.next_tick += interval;
+            }
+        }
+
+        let (finished, active) = std::mem::take(&mut self.active_holds)
+            .into_iter()
+            .partition::<Vec<_>, _>(|h| h.end < to.0);
+        self.active_holds = active;
+
+        for hold in finished {
+            let end = Time(hold.end);
+            self.finish_hold(hold, end);
+        }
+    }
+
+    /// Ends the holds of `lane` at `time`, ticks after `time` are missed.
+    fn release_holds(&mut self, lane: HitNoteType, time: Time) {
+        let (released, active) = std::mem::take(&mut self.active_holds)
+            .into_iter()
+            .partition::<Vec<_>, _>(|h| h.lane == lane);
+        self.active_holds = active;
+
+        for hold in released {
+            self.finish_hold(hold, time);
+        }
+    }
+
+    fn finish_hold(&mut self, hold: ActiveHold, time: Time) {
+        // Holds keep the judgement of their start when every tick was held.
+        let judgement = if hold.ticks.hit == hold.ticks.total {
+            hold.start_judgement
+        } else if hold.ticks.hit > 0 {
+            Judgement::Good.min(hold.start_judgement)
+        } else {
+            Judgement::Miss
+        };
+        self.push_result(
+            hold.note,
+            time,
+            judgement,
+            Some(hold.offset),
+            Some(hold.ticks),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eclale_chart::{
+        EvadeNote, EvadeNoteType, HitNote, HoldNote, NoteMovement, TargetHoldNote, TargetNote,
+    };
+
+    use super::*;
+
+    fn position(time: f32, x: f32) -> TrackPosition {
+        TrackPosition {
+            time: Time(time),
+            x,
+            ..Default::default()
+        }
+    }
+
+    fn player_targeted_evade(trigger_time: f32, time: f32) -> EvadeNote {
+        EvadeNote {
+            id: NoteId(0),
+            ty: EvadeNoteType::Circle,
+            size: EvadeNoteSize::Normal,
+            speed: 1.0,
+            movement: NoteMovement {
+                start: position(trigger_time, 0.0),
+                end: position(time, 0.0),
+                target: MovementTarget::Player { x_offset: 0.0 },
+                trigger_time: Time(trigger_time),
+                duration: time - trigger_time,
+            },
+            animations: Vec::new(),
+        }
+    }
+
+    fn engine(mut notes: Notes) -> JudgementEngine {
+        notes.assign_ids();
+        JudgementEngine::new(&notes, JudgementSettings::default())
+    }
+
+    fn input(engine: &mut JudgementEngine, time: f32, kind: InputKind) {
+        engine.process(InputEvent {
+            time: Time(time),
+            kind,
+        });
+    }
+
+    fn move_to(engine: &mut JudgementEngine, time: f32, x: f32) {
+        input(engine, time, InputKind::Move(x));
+    }
+
+    fn hit_note(time: f32) -> HitNote {
+        HitNote {
+            id: NoteId(0),
+            ty: HitNoteType::Left,
+            is_critical: false,
+            position: position(time, 0.0),
+        }
+    }
+
+    fn hold_note(start: f32, end: f32) -> HoldNote {
+        HoldNote {
+            id: NoteId(0),
+            ty: HitNoteType::Left,
+            is_critical: false,
+            points: vec![position(start, 0.0), position(end, 0.0)],
+        }
+    }
+
+    #[test]
+    fn presses_are_judged_by_their_offset() {
+        let mut engine = engine(Notes {
+            hits: vec![hit_note(1.0), hit_note(2.0)],
+            ..Default::default()
+        });
+
+        input(&mut engine, 1.02, InputKind::Press(HitNoteType::Left));
+        input(&mut engine, 1.03, InputKind::Release(HitNoteType::Left));
+        input(&mut engine, 2.08, InputKind::Press(HitNoteType::Left));
+
+        let results = engine.take_results();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].judgement, Judgement::Critical);
+        assert!((results[0].offset.unwrap() - 0.02).abs() < 1e-4);
+        assert_eq!(results[1].judgement, Judgement::Great);
+    }
+
+    #[test]
+    fn notes_out_of_reach_are_missed() {
+        let mut engine = engine(Notes {
+            hits: vec![hit_note(1.0)],
+            ..Default::default()
+        });
+
+        engine.update(Time(1.1));
+        assert!(engine.take_results().is_empty());
+        engine.update(Time(1.2));
+
+        let results = engine.take_results();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].judgement, Judgement::Miss);
+        assert_eq!(results[0].offset, None);
+    }
+
+    #[test]
+    fn targets_need_the_player_on_them() {
+        let target = |x| TargetNote {
+            id: NoteId(0),
+            position: position(1.0, x),
+        };
+        let mut engine = engine(Notes {
+            targets: vec![target(1.0), target(-1.0)],
+            ..Default::default()
+        });
+
+        // Pressing away from every target does nothing.
+        move_to(&mut engine, 0.5, 0.0);
+        input(&mut engine, 0.99, InputKind::Press(HitNoteType::Center));
+        input(&mut engine, 0.995, InputKind::Release(HitNoteType::Center));
+        assert!(engine.take_results().is_empty());
+
+        move_to(&mut engine, 1.0, 1.0);
+        input(&mut engine, 1.01, InputKind::Press(HitNoteType::Center));
+        engine.update(Time(2.0));
+
+        let results = engine.take_results();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].note, NoteRef::Target(0));
+        assert_eq!(results[0].judgement, Judgement::Critical);
+        assert_eq!(results[1].note, NoteRef::Target(1));
+        assert_eq!(results[1].judgement, Judgement::Miss);
+    }
+
+    #[test]
+    fn target_hold_ticks_need_the_player_on_the_hold() {
+        let mut engine = engine(Notes {
+            target_holds: vec![TargetHoldNote {
+                id: NoteId(0),
+                points: vec![position(1.0, 0.0), position(2.0, 0.0)],
+            }],
+            ..Default::default()
+        });
+
+        input(&mut engine, 1.0, InputKind::Press(HitNoteType::Right));
+        move_to(&mut engine, 1.4, 0.0);
+        // Ticks after 1.4 are resolved with the player away from the hold.
+        move_to(&mut engine, 1.45, 2.0);
+        engine.update(Time(2.5));
+
+        let results = engine.take_results();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].note, NoteRef::TargetHold(0));
+        assert_eq!(results[0].ticks, Some(HoldTicks { hit: 3, total: 7 }));
+        assert_eq!(results[0].judgement, Judgement::Good);
+    }
+
+    #[test]
+    fn releasing_a_hold_early_ends_it() {
+        let mut engine = engine(Notes {
+            holds: vec![hold_note(1.0, 2.0)],
+            ..Default::default()
+        });
+
+        input(&mut engine, 1.0, InputKind::Press(HitNoteType::Left));
+        input(&mut engine, 1.3, InputKind::Release(HitNoteType::Left));
+
+        let results = engine.take_results();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].time, Time(1.3));
+        assert_eq!(results[0].ticks, Some(HoldTicks { hit: 2, total: 7 }));
+        assert_eq!(results[0].judgement, Judgement::Good);
+
+        // Pressing again does not grab the hold back.
+        input(&mut engine, 1.5, InputKind::Press(HitNoteType::Left));
+        engine.update(Time(3.0));
+        assert!(engine.take_results().is_empty());
+    }
+
+    #[test]
+    fn releasing_a_hold_in_the_release_window_keeps_its_judgement() {
+        let mut engine = engine(Notes {
+            holds: vec![hold_note(1.0, 2.0)],
+            ..Default::default()
+        });
+
+        input(&mut engine, 1.0, InputKind::Press(HitNoteType::Left));
+        input(&mut engine, 1.95, InputKind::Release(HitNoteType::Left));
+
+        let results = engine.take_results();
+        assert_eq!(results[0].ticks, Some(HoldTicks { hit: 7, total: 7 }));
+        assert_eq!(results[0].judgement, Judgement::Critical);
+    }
+
+    #[test]
+    fn player_targeted_evade_keeps_the_x_at_trigger_time() {
+        let mut engine = engine(Notes {
+            evades: vec![player_targeted_evade(1.0, 2.0)],
+            ..Default::default()
+        });
+
+        move_to(&mut engine, 0.5, 1.0);
+        assert_eq!(engine.evade_end(0).x, 1.0);
+
+        // The player passes x 1.5 when the movement starts, the target stays there.
+        move_to(&mut engine, 1.5, 2.0);
+        assert_eq!(engine.evade_end(0).x, 1.5);
+        move_to(&mut engine, 2.5, 2.0);
+
+        let results = engine.take_results();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].judgement, Judgement::Critical);
+    }
+
+    #[test]
+    fn player_targeted_evade_hits_a_player_that_stays() {
+        let mut engine = engine(Notes {
+            evades: vec![player_targeted_evade(1.0, 2.0)],
+            ..Default::default()
+        });
+
+        move_to(&mut engine, 0.5, -1.0);
+        move_to(&mut engine, 2.5, -1.0);
+        assert_eq!(engine.take_results()[0].judgement, Judgement::Miss);
+    }
+}
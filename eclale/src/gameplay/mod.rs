@@ -0,0 +1,4 @@
+pub(crate) mod clock;
+pub(crate) mod judgement;
+pub(crate) mod player;
+pub(crate) mod score;
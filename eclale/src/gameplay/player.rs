@@ -0,0 +1,209 @@
+//! Player avatar standing on the platform at the hit bar. The player only moves along x, driven by
+//! the keyboard or by the mouse as a lever.
+
+use eclale_chart::{Time, Track};
+
+#[derive(Clone, Debug)]
+pub(crate) struct PlayerSettings {
+    /// x units per second the keyboard moves the player by.
+    pub(crate) keyboard_speed: f32,
+
+    /// The lever maps [-1, 1] onto [-lever_range, lever_range].
+    pub(crate) lever_range: f32,
+}
+
+impl Default for PlayerSettings {
+    fn default() -> Self {
+        Self {
+            keyboard_speed: 4.0,
+            lever_range: 1.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MoveDirection {
+    Left,
+    Right,
+}
+
+/// x range the player covered since the last collision test. Notes are tested against the whole
+/// range so fast movement or long frames cannot skip over them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Sweep {
+    pub(crate) min: f32,
+    pub(crate) max: f32,
+}
+
+impl Sweep {
+    pub(crate) fn point(x: f32) -> Self {
+        Self { min: x, max: x }
+    }
+
+    pub(crate) fn extend(self, x: f32) -> Self {
+        Self {
+            min: self.min.min(x),
+            max: self.max.max(x),
+        }
+    }
+
+    /// Whether an object at `x` with a hit radius of `radius` touches the swept range.
+    pub(crate) fn hits(&self, x: f32, radius: f32) -> bool {
+        x + radius >= self.min && x - radius <= self.max
+    }
+}
+
+/// x range of the platform at `time` closest to `x`, `None` if there is no platform. Ties go to
+/// the platform closest to `target`.
+fn platform_bounds(track: &Track, time: Time, x: f32, target: f32) -> Option<(f32, f32)> {
+    let distance = |x: f32, (min, max): (f32, f32)| (min - x).max(x - max).max(0.0);
+    track
+        .platforms
+        .iter()
+        .filter_map(|p| p.sample_at(time))
+        .map(|(left, right)| (left.x.min(right.x), left.x.max(right.x)))
+        .min_by(|a, b| {
+            distance(x, *a)
+                .total_cmp(&distance(x, *b))
+                .then(distance(target, *a).total_cmp(&distance(target, *b)))
+        })
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Player {
+    settings: PlayerSettings,
+    x: f32,
+    left_held: bool,
+    right_held: bool,
+    /// Where the mouse lever points, the keyboard takes over when it is used.
+    lever_target: Option<f32>,
+}
+
+impl Player {
+    pub(crate) fn new(settings: PlayerSettings) -> Self {
+        Self {
+            settings,
+            x: 0.0,
+            left_held: false,
+            right_held: false,
+            lever_target: None,
+        }
+    }
+
+    pub(crate) fn x(&self) -> f32 {
+        self.x
+    }
+
+    pub(crate) fn set_direction_held(&mut self, direction: MoveDirection, held: bool) {
+        match direction {
+            MoveDirection::Left => self.left_held = held,
+            MoveDirection::Right => self.right_held = held,
+        }
+        if held {
+            self.lever_target = None;
+        }
+    }
+
+    /// `position` goes from -1 at the left end of the lever to 1 at the right end.
+    pub(crate) fn set_lever(&mut self, position: f32) {
+        self.lever_target = Some(position.clamp(-1.0, 1.0) * self.settings.lever_range);
+    }
+
+    /// Moves the player by `dt` seconds of input and clamps it to the platform it stands on at
+    /// `time`, so it cannot cross gaps between platforms.
+    pub(crate) fn update(&mut self, dt: f32, time: Time, track: &Track) -> f32 {
+        let x = match self.lever_target {
+            Some(target) => target,
+            None => {
+                let direction = match (self.left_held, self.right_held) {
+                    (true, false) => -1.0,
+                    (false, true) => 1.0,
+                    _ => 0.0,
+                };
+                self.x + direction * self.settings.keyboard_speed * dt
+            }
+        };
+
+        self.x = match platform_bounds(track, time, self.x, x) {
+            Some((min, max)) => x.clamp(min, max),
+            None => x,
+        };
+        self.x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eclale_chart::parse::ecl::create_chart_from_ecl_source;
+
+    use super::*;
+
+    const CHART: &str = "\
+<header>
+default_tempo=120
+default_time_signature=4/4
+
+<chart_body>
+[PRS] (1) |-1;1|
+";
+
+    fn track() -> Track {
+        create_chart_from_ecl_source(CHART).unwrap().data.track
+    }
+
+    #[test]
+    fn player_stays_on_its_platform() {
+        let source = CHART.replace(
+            "[PRS] (1) |-1;1|",
+            "[PR] (1;5) |-3;-0.5|\n[PR] (1;5) |0.5;3|",
+        );
+        let track = create_chart_from_ecl_source(&source).unwrap().data.track;
+        let mut player = Player::new(PlayerSettings::default());
+
+        // Both platforms are as far from the gap, the one the lever points to wins.
+        player.set_lever(1.0);
+        assert_eq!(player.update(0.0, Time(0.5), &track), 1.5);
+
+        // The gap is not crossed, neither with the lever nor with the keyboard.
+        player.set_lever(-1.0);
+        assert_eq!(player.update(0.0, Time(0.5), &track), 0.5);
+        player.set_direction_held(MoveDirection::Left, true);
+        assert_eq!(player.update(0.25, Time(0.5), &track), 0.5);
+    }
+
+    #[test]
+    fn sweeps_cover_every_position_passed() {
+        let sweep = Sweep::point(0.0).extend(2.0).extend(1.0);
+        assert_eq!(sweep, Sweep { min: 0.0, max: 2.0 });
+        assert!(sweep.hits(1.5, 0.0));
+        assert!(sweep.hits(-0.2, 0.25));
+        assert!(!sweep.hits(2.5, 0.25));
+    }
+
+    #[test]
+    fn keyboard_moves_the_player_at_its_speed() {
+        let mut player = Player::new(PlayerSettings::default());
+        player.set_direction_held(MoveDirection::Left, true);
+        assert_eq!(player.update(0.1, Time(0.0), &Track::default()), -0.4);
+
+        // Opposite directions cancel out.
+        player.set_direction_held(MoveDirection::Right, true);
+        assert_eq!(player.update(0.1, Time(0.0), &Track::default()), -0.4);
+    }
+
+    #[test]
+    fn player_is_clamped_to_the_platforms() {
+        let track = track();
+        let mut player = Player::new(PlayerSettings::default());
+        player.set_direction_held(MoveDirection::Right, true);
+        assert_eq!(player.update(1.0, Time(0.5), &track), 1.0);
+
+        // The lever takes over until a key is pressed again.
+        player.set_lever(-0.5);
+        assert_eq!(player.update(0.0, Time(0.5), &track), -0.75);
+        player.set_lever(-5.0);
+        assert_eq!(player.update(0.0, Time(0.5), &track), -1.0);
+        player.set_direction_held(MoveDirection::Right, true);
+        assert_eq!(player.update(0.25, Time(0.5), &track), 0.0);
+    }
+}
@@ -0,0 +1,347 @@
+//! Score, combo and life gauge built from note results. The weights and gauge rules are read from
+//! a `key=value` settings file so they can be tuned without rebuilding.
+
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use eclale_chart::{NoteRef, Notes};
+
+use super::judgement::{Judgement, NoteResult};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ScoreSettings {
+    /// Technical score of a run where every note is critical.
+    pub(crate) max_technical_score: u32,
+
+    /// Share of a critical note's score each judgement is worth, from 0.0 to 1.0.
+    pub(crate) critical_weight: f32,
+    pub(crate) perfect_weight: f32,
+    pub(crate) great_weight: f32,
+    pub(crate) good_weight: f32,
+
+    pub(crate) max_life: f32,
+    /// Life lost when a hit, hold, flick, target or target hold note is missed.
+    pub(crate) miss_damage: f32,
+    /// Life lost when an evade note hits the player.
+    pub(crate) bullet_damage: f32,
+    /// Life lost when a contact note is not collected.
+    pub(crate) bell_miss_damage: f32,
+}
+
+impl Default for ScoreSettings {
+    fn default() -> Self {
+        Self {
+            max_technical_score: 1_010_000,
+            critical_weight: 1.0,
+            perfect_weight: 0.99,
+            great_weight: 0.9,
+            good_weight: 0.7,
+            max_life: 100.0,
+            miss_damage: 2.0,
+            bullet_damage: 10.0,
+            bell_miss_damage: 0.0,
+        }
+    }
+}
+
+impl ScoreSettings {
+    /// Lines are `key=value`, empty lines and lines starting with `#` are skipped. Keys that are
+    /// not set keep their default value.
+    pub(crate) fn parse(source: &str) -> Result<Self> {
+        let mut settings = Self::default();
+
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let line_number = index + 1;
+            let (key, value) = line
+                .split_once('=')
+                .with_context(|| format!("line {}: expected `key=value`", line_number))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            let float = || {
+                value
+                    .parse::<f32>()
+                    .with_context(|| format!("line {}: invalid value for `{}`", line_number, key))
+            };
+            match key {
+                "max_technical_score" => {
+                    settings.max_technical_score = value.parse().with_context(|| {
+                        format!("line {}: invalid value for `{}`", line_number, key)
+                    })?
+                }
+                "critical_weight" => settings.critical_weight = float()?,
+                "perfect_weight" => settings.perfect_weight = float()?,
+                "great_weight" => settings.great_weight = float()?,
+                "good_weight" => settings.good_weight = float()?,
+                "max_life" => settings.max_life = float()?,
+                "miss_damage" => settings.miss_damage = float()?,
+                "bullet_damage" => settings.bullet_damage = float()?,
+                "bell_miss_damage" => settings.bell_miss_damage = float()?,
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "line {}: unknown setting `{}`",
+                        line_number,
+                        key
+                    ))
+                }
+            }
+        }
+
+        Ok(settings)
+    }
+
+    pub(crate) fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("failed to read score settings {}", path.display()))?;
+        Self::parse(&source).with_context(|| format!("invalid score settings {}", path.display()))
+    }
+
+    fn weight(&self, judgement: Judgement) -> f32 {
+        match judgement {
+            Judgement::Critical => self.critical_weight,
+            Judgement::Perfect => self.perfect_weight,
+            Judgement::Great => self.great_weight,
+            Judgement::Good => self.good_weight,
+            Judgement::Miss => 0.0,
+        }
+    }
+}
+
+/// Running totals of a run, cheap to copy for the HUD every frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ScoreTotals {
+    pub(crate) technical_score: u32,
+    pub(crate) combo: u32,
+    pub(crate) max_combo: u32,
+
+    /// No hit, hold, flick or target note was missed so far.
+    pub(crate) is_full_combo: bool,
+    /// Every hit, hold, flick and target note so far is perfect or critical.
+    pub(crate) is_all_perfect: bool,
+
+    pub(crate) bells_collected: u32,
+    pub(crate) bells_total: u32,
+    pub(crate) bullets_hit: u32,
+
+    pub(crate) life: f32,
+    /// Set once life runs out, the run stays failed.
+    pub(crate) is_failed: bool,
+}
+
+pub(crate) struct Score {
+    settings: ScoreSettings,
+    /// Number of hit, hold, flick, target and target hold notes in the chart.
+    technical_note_count: u32,
+    /// Sum of the weights of judged technical notes.
+    earned_weight: f64,
+    totals: ScoreTotals,
+}
+
+impl Score {
+    pub(crate) fn new(notes: &Notes, settings: ScoreSettings) -> Self {
+        let technical_note_count = (notes.hits.len()
+            + notes.holds.len()
+            + notes.flicks.len()
+            + notes.targets.len()
+            + notes.target_holds.len()) as u32;
+
+        Self {
+            totals: ScoreTotals {
+                technical_score: 0,
+                combo: 0,
+                max_combo: 0,
+                is_full_combo: true,
+                is_all_perfect: true,
+                bells_collected: 0,
+                bells_total: notes.contacts.len() as u32,
+                bullets_hit: 0,
+                life: settings.max_life,
+                is_failed: false,
+            },
+            settings,
+            technical_note_count,
+            earned_weight: 0.0,
+        }
+    }
+
+    pub(crate) fn totals(&self) -> ScoreTotals {
+        self.totals
+    }
+
+    pub(crate) fn apply(&mut self, result: &NoteResult) {
+        let is_miss = result.judgement == Judgement::Miss;
+
+        let damage = match result.note {
+            NoteRef::Hit(_)
+            | NoteRef::Hold(_)
+            | NoteRef::Flick(_)
+            | NoteRef::Target(_)
+            | NoteRef::TargetHold(_) => {
+                self.apply_technical(result.judgement);
+                if is_miss {
+                    self.settings.miss_damage
+                } else {
+                    0.0
+                }
+            }
+            NoteRef::Contact(_) => {
+                if is_miss {
+                    self.settings.bell_miss_damage
+                } else {
+                    self.totals.bells_collected += 1;
+                    0.0
+                }
+            }
+            NoteRef::Evade(_) => {
+                if is_miss {
+                    self.totals.bullets_hit += 1;
+                    self.settings.bullet_damage
+                } else {
+                    0.0
+                }
+            }
+        };
+
+        self.totals.life = (self.totals.life - damage).max(0.0);
+        if self.totals.life <= 0.0 {
+            self.totals.is_failed = true;
+        }
+    }
+
+    fn apply_technical(&mut self, judgement: Judgement) {
+        let totals = &mut self.totals;
+        if judgement == Judgement::Miss {
+            totals.combo = 0;
+            totals.is_full_combo = false;
+        } else {
+            totals.combo += 1;
+            totals.max_combo = totals.max_combo.max(totals.combo);
+        }
+        if judgement < Judgement::Perfect {
+            totals.is_all_perfect = false;
+        }
+
+        self.earned_weight += f64::from(self.settings.weight(judgement));
+        let max_weight =
+            f64::from(self.technical_note_count) * f64::from(self.settings.critical_weight);
+        if max_weight > 0.0 {
+            let share = (self.earned_weight / max_weight).min(1.0);
+            totals.technical_score =
+                (share * f64::from(self.settings.max_technical_score)).round() as u32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eclale_chart::{ContactNote, ContactNoteType, HitNote, HitNoteType, NoteId, Time};
+
+    use super::*;
+
+    fn notes(hits: usize, contacts: usize) -> Notes {
+        let hit = HitNote {
+            id: NoteId(0),
+            ty: HitNoteType::Left,
+            is_critical: false,
+            position: Default::default(),
+        };
+        let contact = ContactNote {
+            id: NoteId(0),
+            ty: ContactNoteType::Primary,
+            position: Default::default(),
+        };
+        Notes {
+            hits: vec![hit; hits],
+            contacts: vec![contact; contacts],
+            ..Default::default()
+        }
+    }
+
+    fn result(note: NoteRef, judgement: Judgement) -> NoteResult {
+        NoteResult {
+            note,
+            id: NoteId(0),
+            time: Time(0.0),
+            judgement,
+            offset: None,
+            ticks: None,
+        }
+    }
+
+    #[test]
+    fn parses_settings() {
+        let settings =
+            ScoreSettings::parse("# comment\n\nmiss_damage = 5\nmax_technical_score=1000").unwrap();
+        assert_eq!(settings.miss_damage, 5.0);
+        assert_eq!(settings.max_technical_score, 1000);
+        assert_eq!(
+            settings.bullet_damage,
+            ScoreSettings::default().bullet_damage
+        );
+    }
+
+    #[test]
+    fn invalid_settings_name_the_line() {
+        let error = ScoreSettings::parse("max_life=100\nfoo=1").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: unknown setting `foo`");
+
+        let error = ScoreSettings::parse("miss_damage=abc").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: invalid value for `miss_damage`");
+
+        assert!(ScoreSettings::parse("max_life").is_err());
+    }
+
+    #[test]
+    fn technical_score_is_weighted_by_judgement() {
+        let mut score = Score::new(&notes(2, 0), ScoreSettings::default());
+        score.apply(&result(NoteRef::Hit(0), Judgement::Critical));
+        assert_eq!(score.totals().technical_score, 505_000);
+
+        score.apply(&result(NoteRef::Hit(1), Judgement::Good));
+        let totals = score.totals();
+        assert_eq!(totals.technical_score, 858_500);
+        assert_eq!(totals.max_combo, 2);
+        assert!(totals.is_full_combo);
+        assert!(!totals.is_all_perfect);
+    }
+
+    #[test]
+    fn misses_break_the_combo_and_cost_life() {
+        let mut score = Score::new(&notes(3, 1), ScoreSettings::default());
+        score.apply(&result(NoteRef::Hit(0), Judgement::Perfect));
+        score.apply(&result(NoteRef::Hit(1), Judgement::Miss));
+        score.apply(&result(NoteRef::Contact(0), Judgement::Critical));
+
+        let totals = score.totals();
+        assert_eq!(totals.combo, 0);
+        assert_eq!(totals.max_combo, 1);
+        assert!(!totals.is_full_combo);
+        assert_eq!(totals.bells_collected, 1);
+        assert_eq!(totals.life, 98.0);
+    }
+
+    #[test]
+    fn runs_stay_failed() {
+        let settings = ScoreSettings {
+            max_life: 15.0,
+            ..Default::default()
+        };
+        let mut score = Score::new(&notes(1, 0), settings);
+        score.apply(&result(NoteRef::Evade(0), Judgement::Miss));
+        assert!(!score.totals().is_failed);
+        score.apply(&result(NoteRef::Evade(1), Judgement::Miss));
+
+        let totals = score.totals();
+        assert_eq!(totals.bullets_hit, 2);
+        assert_eq!(totals.life, 0.0);
+        assert!(totals.is_failed);
+
+        score.apply(&result(NoteRef::Hit(0), Judgement::Critical));
+        assert!(score.totals().is_failed);
+    }
+}
@@ -8,6 +8,7 @@ use winit::{
     dpi,
     event::{ElementState, Event, KeyEvent, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
+    keyboard::{KeyCode, PhysicalKey},
     raw_window_handle::{
         HasDisplayHandle, HasRawDisplayHandle, HasRawWindowHandle, HasWindowHandle,
     },
@@ -15,15 +16,65 @@ use winit::{
 };
 
 use eclale_audio::AudioSystem;
-use eclale_chart::parse::ogkr::create_chart_from_ogkr_file;
+use eclale_chart::{
+    parse::load_chart,
+    validate::{has_errors, validate, Severity},
+    FlickDirection, HitNoteType,
+};
 
+use gameplay::{
+    clock::{GameClock, GameClockSettings},
+    judgement::{InputEvent, InputKind, JudgementEngine, JudgementSettings},
+    player::{MoveDirection, Player, PlayerSettings},
+    score::{Score, ScoreSettings},
+};
 use renderer::{
     track_description::{TrackDescription, TrackSettings},
     track_renderer::TrackRenderer,
 };
 
+mod gameplay;
 mod renderer;
 
+const SCORE_SETTINGS_PATH: &str = "config/score.cfg";
+
+/// Keyboard layout of the lanes, both hands can hit the three colored lanes.
+fn lane_for_key(key: KeyCode) -> Option<HitNoteType> {
+    match key {
+        KeyCode::KeyA => Some(HitNoteType::WallLeft),
+        KeyCode::KeyS | KeyCode::KeyJ => Some(HitNoteType::Left),
+        KeyCode::KeyD | KeyCode::KeyK => Some(HitNoteType::Center),
+        KeyCode::KeyF | KeyCode::KeyL => Some(HitNoteType::Right),
+        KeyCode::Semicolon => Some(HitNoteType::WallRight),
+        _ => None,
+    }
+}
+
+fn flick_for_key(key: KeyCode) -> Option<FlickDirection> {
+    match key {
+        KeyCode::KeyQ => Some(FlickDirection::Left),
+        KeyCode::KeyE => Some(FlickDirection::Right),
+        _ => None,
+    }
+}
+
+fn move_direction_for_key(key: KeyCode) -> Option<MoveDirection> {
+    match key {
+        KeyCode::ArrowLeft => Some(MoveDirection::Left),
+        KeyCode::ArrowRight => Some(MoveDirection::Right),
+        _ => None,
+    }
+}
+
+fn input_kind(key: KeyCode, state: ElementState) -> Option<InputKind> {
+    match state {
+        ElementState::Pressed => lane_for_key(key)
+            .map(InputKind::Press)
+            .or_else(|| flick_for_key(key).map(InputKind::Flick)),
+        ElementState::Released => lane_for_key(key).map(InputKind::Release),
+    }
+}
+
 fn main() -> Result<()> {
     let env = env_logger::Env::default()
         .filter_or("MY_LOG_LEVEL", "debug")
@@ -31,40 +82,75 @@ fn main() -> Result<()> {
     env_logger::init_from_env(env);
 
     let args = env::args().collect::<Vec<_>>();
-    if args.len() != 2 {
-        eprintln!("Usage: {} <chart_file>", args[0]);
+    let user_offset_ms = args.get(2).map(|offset| offset.parse::<f32>());
+    if !(2..=3).contains(&args.len()) || matches!(user_offset_ms, Some(Err(_))) {
+        eprintln!("Usage: {} <chart_file> [user_offset_ms]", args[0]);
         std::process::exit(1);
     }
+    let user_offset = user_offset_ms.and_then(Result::ok).unwrap_or(0.0) / 1000.0;
 
     // Parse chart file.
     let chart_file_path = &args[1];
-    let chart_parent_dir = Path::new(chart_file_path).parent().unwrap_or(Path::new(""));
-    let chart = create_chart_from_ogkr_file(chart_file_path)?;
+    let chart = match load_chart(chart_file_path) {
+        Ok(chart) => chart,
+        Err(e) => {
+            log::error!("Failed to load chart: {}", e);
+            std::process::exit(1);
+        }
+    };
 
     log::info!("Successfully parsed chart file {}", chart_file_path);
     log::info!(
-        "Number of hit notes {}, bells {}, platforms {}, lane types {}",
+        "Title \"{}\", artist \"{}\", chart designer \"{}\", difficulty \"{}\", level {:?}",
+        chart.header.title,
+        chart.header.artist,
+        chart.header.chart_designer,
+        chart.header.difficulty,
+        chart.header.level
+    );
+
+    let issues = validate(&chart);
+    for issue in issues.iter() {
+        match issue.severity() {
+            Severity::Warning => log::warn!("Chart warning: {}", issue),
+            Severity::Error => log::error!("Chart error: {}", issue),
+        }
+    }
+    if has_errors(&issues) {
+        log::error!("Refusing to play chart {} with errors", chart_file_path);
+        std::process::exit(1);
+    }
+    log::info!(
+        "Number of hit notes {}, bells {}, platforms {}, lanes {}",
         chart.data.notes.hits.len(),
         chart.data.notes.contacts.len(),
         chart.data.track.platforms.len(),
         chart.data.track.lanes.len()
     );
 
-    let chart_speed = 1.0;
     let runner_speed = 15.0;
 
     let render_track_settings = TrackSettings { runner_speed };
     let render_track_description = TrackDescription::from_chart(&chart, render_track_settings);
 
     // Load audio.
-    // let mut audio_system = AudioSystem::new()?;
-    // let audio_file_path = chart_parent_dir.join(&chart.header.audio_filename);
-    // log::info!(
-    //     "Audio file path: {}",
-    //     &audio_file_path.clone().to_str().unwrap()
-    // );
-    // let sound_index =
-    //     audio_system.load_static_sound_from_file(audio_file_path.to_str().unwrap())?;
+    let mut audio_system = AudioSystem::new()?;
+    let sound_index = match &chart.metadata.audio_path {
+        Some(audio_file_path) => {
+            log::info!("Audio file path: {}", audio_file_path.display());
+            match audio_system.load_static_sound_from_file(&audio_file_path.to_string_lossy()) {
+                Ok(sound_index) => Some(sound_index),
+                Err(e) => {
+                    log::warn!("Failed to load audio, playing without it: {:#}", e);
+                    None
+                }
+            }
+        }
+        None => {
+            log::warn!("Chart has no audio file, playing without it");
+            None
+        }
+    };
 
     // Initialize window.
     let event_loop = EventLoop::new()?;
@@ -85,13 +171,29 @@ fn main() -> Result<()> {
 
     // let screen_dimensions = Vector2::new(1920.0, 1080.0);
 
-    // Play audio.
-    // let sound_handle = audio_system.play_static_sound(sound_index)?;
+    let z_position_calculator = chart.utils.z_position_calculator.clone();
 
-    let mut last_audio_position = 0.0;
-    let mut last_render_time = Instant::now();
+    let mut judgement_engine =
+        JudgementEngine::new(&chart.data.notes, JudgementSettings::default());
+    let mut player = Player::new(PlayerSettings::default());
 
-    let mut current_runner_position = 0.0;
+    let score_settings = if Path::new(SCORE_SETTINGS_PATH).exists() {
+        match ScoreSettings::load(SCORE_SETTINGS_PATH) {
+            Ok(settings) => settings,
+            Err(e) => {
+                log::error!("Failed to load score settings: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        log::info!(
+            "{} not found, using default score settings",
+            SCORE_SETTINGS_PATH
+        );
+        ScoreSettings::default()
+    };
+    let mut score = Score::new(&chart.data.notes, score_settings);
+    let mut displayed_totals = None;
 
     let eye = Point3::new(0.0, -1.3, -2.5);
     let target = Point3::new(0.0, 2.0, 2.5);
@@ -108,7 +210,13 @@ fn main() -> Result<()> {
             // XXX: Use view and projection matrices that fit accordingly to the vulkan coord system. (?)
             * Matrix4::new_nonuniform_scaling(&Vector3::new(-1.0, 1.0, 1.0));
 
-    let mut elapsed_time = 0.0;
+    // Play audio, the clock follows it from here on. The chart's offset is already part of its
+    // times, the clock only applies the user offset.
+    let sound_handle = sound_index
+        .map(|sound_index| audio_system.play_static_sound(sound_index))
+        .transpose()?;
+    let mut clock = GameClock::new(sound_handle, user_offset, GameClockSettings::default());
+    let mut last_render_time = Instant::now();
 
     event_loop.run(move |event, eltw| {
         eltw.set_control_flow(ControlFlow::Poll);
@@ -119,21 +227,108 @@ fn main() -> Result<()> {
                     eltw.exit();
                 }
                 WindowEvent::Resized(_) => {}
+                WindowEvent::KeyboardInput {
+                    event:
+                        KeyEvent {
+                            physical_key: PhysicalKey::Code(key),
+                            state,
+                            repeat: false,
+                            ..
+                        },
+                    ..
+                } if !score.totals().is_failed => {
+                    // XXX TODO: Timestamp input with the time it happened instead of the last frame.
+                    if let Some(direction) = move_direction_for_key(key) {
+                        player.set_direction_held(direction, state == ElementState::Pressed);
+                    } else if let Some(kind) = input_kind(key, state) {
+                        judgement_engine.process(InputEvent {
+                            time: clock.time(),
+                            kind,
+                        });
+                    }
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    let width = window.inner_size().width.max(1) as f64;
+                    player.set_lever((position.x / width * 2.0 - 1.0) as f32);
+                }
                 WindowEvent::RedrawRequested => {
                     let now = Instant::now();
                     let dt = now - last_render_time;
                     last_render_time = now;
 
-                    elapsed_time += dt.as_secs_f32() * chart_speed;
+                    // A failed run is frozen at the time it failed, the clock is stopped and
+                    // nothing is judged anymore.
+                    let is_failed = score.totals().is_failed;
+                    let time = clock.update(dt.as_secs_f32());
+
+                    // Runner follows the scroll speed curve, judgement still uses the chart time.
+                    let current_runner_position = z_position_calculator.z_at(time) * runner_speed;
+
+                    let player_x = if is_failed {
+                        player.x()
+                    } else {
+                        player.update(dt.as_secs_f32(), time, &chart.data.track)
+                    };
+                    if !is_failed {
+                        judgement_engine.process(InputEvent {
+                            time,
+                            kind: InputKind::Move(player_x),
+                        });
+                    }
+                    for result in judgement_engine.take_results() {
+                        log::debug!(
+                            "Note {} ({:?}) {:?} at {}s, offset {:?}, ticks {:?}",
+                            result.id.0,
+                            result.note,
+                            result.judgement,
+                            result.time.0,
+                            result.offset,
+                            result.ticks
+                        );
+                        score.apply(&result);
+                    }
 
-                    // // let current_audio_position = sound_handle.position() as f32;
-                    // // let _audio_dt = current_audio_position - last_audio_position;
-                    // // last_audio_position = current_audio_position;
+                    let totals = score.totals();
+                    if totals.is_failed && !is_failed {
+                        clock.stop();
+                        log::info!(
+                            "Run failed at {:.2}s with a technical score of {}, max combo {}",
+                            time.0,
+                            totals.technical_score,
+                            totals.max_combo
+                        );
+                    }
 
-                    current_runner_position += dt.as_secs_f32() * runner_speed * chart_speed;
+                    // XXX TODO: Draw a proper HUD, the window title shows the totals for now.
+                    if displayed_totals != Some(totals) {
+                        window.set_title(&format!(
+                            "eclale - {} | combo {} (max {}) | bells {}/{} | life {:.0}{}",
+                            totals.technical_score,
+                            totals.combo,
+                            totals.max_combo,
+                            totals.bells_collected,
+                            totals.bells_total,
+                            totals.life,
+                            if totals.is_failed {
+                                " | FAILED"
+                            } else if totals.is_all_perfect {
+                                " | AP"
+                            } else if totals.is_full_combo {
+                                " | FC"
+                            } else {
+                                ""
+                            }
+                        ));
+                        displayed_totals = Some(totals);
+                    }
 
                     track_renderer.update_view_projection(view_projection);
-                    track_renderer.update_runner_position(current_runner_position, elapsed_time);
+                    track_renderer.update_runner_position(
+                        current_runner_position,
+                        time.0,
+                        |index| judgement_engine.evade_end(index),
+                    );
+                    track_renderer.update_player_position(player_x);
                     track_renderer.render().unwrap();
                 }
                 _ => (),
@@ -1,8 +1,7 @@
-use std::collections::HashMap;
-
 use eclale_chart::{
-    Chart, ChartData, ContactNote, EvadeNote, FlickNote, HitNote, HitNoteType, HoldNote, Lane,
-    LaneType, TrackPosition,
+    animation::AnimationLibrary, Chart, ChartData, EdgeSegment, EvadeNote, EvadeNoteSize,
+    HitNoteType, LaneType, Lanes, NoteStyleKey, NoteStyles, Platform, PlatformEdge, Time,
+    TrackPosition,
 };
 use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
 
@@ -10,6 +9,9 @@ use eclale_graphics::geometry::{plane::Plane, Mesh};
 
 use super::track_renderer::HIT_X_LENGTH;
 
+/// Number of lines bezier platform edges are tessellated into.
+const PLATFORM_BEZIER_SUBDIVISIONS: usize = 32;
+
 #[derive(Clone, Debug)]
 pub(crate) struct TrackSettings {
     pub(crate) runner_speed: f32,
@@ -19,62 +21,51 @@ pub(crate) struct TrackSettings {
 pub(crate) struct ObjectInstance {
     pub(crate) z_position: f32,
     pub(crate) x_position: f32,
+    /// Height above the platform.
+    pub(crate) y_position: f32,
     pub(crate) base_color: Vector4<f32>,
     pub(crate) apply_runner_transform: bool,
 }
 
-fn get_base_color_hit(hit_type: HitNoteType) -> Vector4<f32> {
-    match hit_type {
-        // XXX TODO: Have proper type enums.
-        HitNoteType(0) | HitNoteType(10) => Vector4::new(1.0, 0.0, 1.0, 1.0),
-        HitNoteType(1) | HitNoteType(11) => Vector4::new(0.5, 0.0, 0.5, 1.0),
-        HitNoteType(2) | HitNoteType(12) => Vector4::new(1.0, 0.0, 0.0, 1.0),
-        HitNoteType(3) | HitNoteType(13) => Vector4::new(0.0, 1.0, 0.0, 1.0),
-        HitNoteType(4) | HitNoteType(14) => Vector4::new(0.0, 0.0, 1.0, 1.0),
-        _ => {
-            log::warn!("Encountered unknown hit note type {:?}", hit_type);
-            Vector4::new(1.0, 1.0, 1.0, 1.0)
-        }
+/// Colors of notes that the chart does not style.
+// XXX TODO: Differentiate critical notes.
+fn get_default_note_color(key: NoteStyleKey) -> Vector4<f32> {
+    match key {
+        NoteStyleKey::Hit(HitNoteType::WallLeft) => Vector4::new(1.0, 0.0, 1.0, 1.0),
+        NoteStyleKey::Hit(HitNoteType::WallRight) => Vector4::new(0.5, 0.0, 0.5, 1.0),
+        NoteStyleKey::Hit(HitNoteType::Left) => Vector4::new(1.0, 0.0, 0.0, 1.0),
+        NoteStyleKey::Hit(HitNoteType::Center) => Vector4::new(0.0, 1.0, 0.0, 1.0),
+        NoteStyleKey::Hit(HitNoteType::Right) => Vector4::new(0.0, 0.0, 1.0, 1.0),
+        NoteStyleKey::Target => Vector4::new(1.0, 0.5, 0.0, 1.0),
+        NoteStyleKey::Flick => Vector4::new(0.8, 0.8, 0.1, 1.0),
+        NoteStyleKey::Evade(..) => Vector4::new(0.6, 0.2, 0.9, 1.0),
+        NoteStyleKey::Contact(_) => Vector4::new(1.0, 1.0, 0.0, 1.0),
     }
 }
 
-fn get_base_color_lane(lane_type: LaneType) -> Vector4<f32> {
+/// Lanes are colored after the notes that belong to them.
+fn lane_note_type(lane_type: LaneType) -> Option<HitNoteType> {
     match lane_type {
-        // XXX TODO: Have proper type enums.
-        LaneType(1) | LaneType(12) => Vector4::new(1.0, 0.0, 0.0, 1.0),
-        LaneType(2) | LaneType(13) => Vector4::new(0.0, 1.0, 0.0, 1.0),
-        LaneType(3) | LaneType(14) => Vector4::new(0.0, 0.0, 1.0, 1.0),
-        _ => {
-            log::warn!("Encountered unknown lane note type {:?}", lane_type);
-            Vector4::new(1.0, 1.0, 1.0, 1.0)
-        }
+        LaneType::WallLeft => Some(HitNoteType::WallLeft),
+        LaneType::WallRight => Some(HitNoteType::WallRight),
+        LaneType::Left => Some(HitNoteType::Left),
+        LaneType::Center => Some(HitNoteType::Center),
+        LaneType::Right => Some(HitNoteType::Right),
+        LaneType::Enemy => None,
     }
 }
 
 impl ObjectInstance {
-    fn from_chart_hit(note: &HitNote, apply_runner_transform: bool) -> Self {
-        Self {
-            z_position: note.position.z,
-            x_position: note.position.x,
-            base_color: get_base_color_hit(note.ty),
-            apply_runner_transform,
-        }
-    }
-
-    fn from_chart_contact(note: &ContactNote, apply_runner_transform: bool) -> Self {
+    fn from_chart_position(
+        position: &TrackPosition,
+        base_color: Vector4<f32>,
+        apply_runner_transform: bool,
+    ) -> Self {
         Self {
-            z_position: note.position.z,
-            x_position: note.position.x,
-            base_color: Vector4::new(1.0, 1.0, 0.0, 1.0),
-            apply_runner_transform,
-        }
-    }
-
-    fn from_chart_flick(note: &FlickNote, apply_runner_transform: bool) -> Self {
-        Self {
-            z_position: note.position.z,
-            x_position: note.position.x,
-            base_color: Vector4::new(0.8, 0.8, 0.1, 1.0),
+            z_position: position.z,
+            x_position: position.x,
+            y_position: position.y,
+            base_color,
             apply_runner_transform,
         }
     }
@@ -99,29 +90,33 @@ pub(crate) struct LanesDescription {
 
 #[derive(Clone, Debug)]
 pub(crate) struct EvadeObjectInstance {
-    /// Start position in the XZ grid.
-    pub(crate) start_position: Vector2<f32>,
-
-    /// End position in the XZ grid.
-    pub(crate) end_position: Vector2<f32>,
-
+    pub(crate) note: EvadeNote,
     pub(crate) base_color: Vector4<f32>,
-
-    // Time to start note movement in seconds.
-    pub(crate) trigger_time: f32,
-
-    // Duration of movement in seconds.
-    pub(crate) duration: f32,
 }
 
 impl EvadeObjectInstance {
-    fn from_chart_evade(note: &EvadeNote) -> Self {
+    fn from_chart_evade(note: &EvadeNote, base_color: Vector4<f32>) -> Self {
         Self {
-            start_position: Vector2::new(note.movement.start.x, note.movement.start.z),
-            end_position: Vector2::new(note.movement.end.x, note.movement.end.z),
-            base_color: Vector4::new(0.6, 0.2, 0.9, 1.0),
-            trigger_time: note.movement.trigger_time.0,
-            duration: note.movement.duration,
+            note: note.clone(),
+            base_color,
+        }
+    }
+
+    /// Model matrix of the note at `time` in seconds moving towards `end`, with its z position
+    /// affected by the runner speed. Large bullets are drawn at twice the size, matching their
+    /// judgement radius.
+    pub(crate) fn transform_at(
+        &self,
+        animations: &AnimationLibrary,
+        end: &TrackPosition,
+        time: f32,
+        runner_speed: f32,
+    ) -> Matrix4<f32> {
+        let mut transform = Matrix4::from(self.note.pose_at(animations, end, Time(time)).columns);
+        transform[(2, 3)] *= runner_speed;
+        match self.note.size {
+            EvadeNoteSize::Normal => transform,
+            EvadeNoteSize::Large => transform * Matrix4::new_scaling(2.0),
         }
     }
 }
@@ -131,76 +126,190 @@ pub(crate) struct TrackDescription {
     pub(crate) notes_hit: Vec<ObjectInstance>,
     pub(crate) notes_contact: Vec<ObjectInstance>,
     pub(crate) notes_flick: Vec<ObjectInstance>,
+    pub(crate) notes_target: Vec<ObjectInstance>,
 
     pub(crate) notes_evade: Vec<EvadeObjectInstance>,
+    pub(crate) animations: AnimationLibrary,
 
     pub(crate) settings: TrackSettings,
 
     pub(crate) platform_instances: Vec<ObjectInstance>,
-    pub(crate) platform_mesh: Mesh,
+    /// One mesh per platform, all are drawn with `platform_instances`.
+    pub(crate) platform_meshes: Vec<Mesh>,
 
     pub(crate) hold_notes: HoldNotesDescription,
+    pub(crate) target_hold_notes: HoldNotesDescription,
 
     pub(crate) lanes: LanesDescription,
 }
 
 struct TrackDescriptionCreator {
     settings: TrackSettings,
+    note_styles: NoteStyles,
 }
 
 impl TrackDescriptionCreator {
-    fn new(settings: TrackSettings) -> Self {
-        Self { settings }
+    fn new(settings: TrackSettings, note_styles: NoteStyles) -> Self {
+        Self {
+            settings,
+            note_styles,
+        }
     }
 
-    fn apply_runner_speed(&self, notes: Vec<ObjectInstance>) -> Vec<ObjectInstance> {
-        notes
-            .into_iter()
-            .map(|mut n| {
-                n.z_position = n.z_position * self.settings.runner_speed;
-                n
-            })
-            .collect()
+    /// Color set by the chart, the default palette otherwise.
+    fn note_color(&self, key: NoteStyleKey) -> Vector4<f32> {
+        self.note_styles.color(key).map_or_else(
+            || get_default_note_color(key),
+            |c| Vector4::new(c.r, c.g, c.b, 1.0),
+        )
     }
 
-    fn apply_runner_speed_on_evade_notes(
-        &self,
-        notes: Vec<EvadeObjectInstance>,
-    ) -> Vec<EvadeObjectInstance> {
+    fn lane_color(&self, lane_type: LaneType) -> Vector4<f32> {
+        lane_note_type(lane_type).map_or(Vector4::new(0.5, 0.5, 0.5, 1.0), |ty| {
+            self.note_color(NoteStyleKey::Hit(ty))
+        })
+    }
+
+    fn apply_runner_speed(&self, notes: Vec<ObjectInstance>) -> Vec<ObjectInstance> {
         notes
             .into_iter()
             .map(|mut n| {
-                // y is z here as Vector2 is used to represent position in the zx plane..
-                n.start_position.y = n.start_position.y * self.settings.runner_speed;
-                n.end_position.y = n.end_position.y * self.settings.runner_speed;
+                n.z_position = n.z_position * self.settings.runner_speed;
                 n
             })
             .collect()
     }
 
-    fn track_position_to_xz_vertices(
-        &self,
-        track_positions: &[TrackPosition],
-    ) -> Vec<Vector3<f32>> {
+    fn track_position_to_vertices(&self, track_positions: &[TrackPosition]) -> Vec<Vector3<f32>> {
         track_positions
             .iter()
-            .map(|p| Vector3::new(p.x, 0.0, p.z * self.settings.runner_speed))
+            .map(|p| Vector3::new(p.x, p.y, p.z * self.settings.runner_speed))
             .collect()
     }
 
-    fn create_platform_mesh(&self, platform: &eclale_chart::Platform) -> Mesh {
-        let walls_left_vertices = self.track_position_to_xz_vertices(&platform.points_left);
-        let walls_right_vertices = self.track_position_to_xz_vertices(&platform.points_right);
+    /// Position in the XZ grid.
+    fn track_position_to_xz(&self, track_position: &TrackPosition) -> Vector2<f32> {
+        Vector2::new(
+            track_position.x,
+            track_position.z * self.settings.runner_speed,
+        )
+    }
 
-        let mesh =
-            Plane::triangulate_from_two_sides(walls_left_vertices, walls_right_vertices).to_mesh();
+    /// Platforms with a single segment per edge use the matching plane builder, others are
+    /// triangulated from their flattened edges.
+    fn create_platform_mesh(&self, platform: &Platform) -> Option<Mesh> {
+        let xz = |p: &TrackPosition| self.track_position_to_xz(p);
+        let xz_pair = |(c0, c1): &(TrackPosition, TrackPosition)| (xz(c0), xz(c1));
+        let subdivisions = PLATFORM_BEZIER_SUBDIVISIONS;
+
+        let left = platform.left_segments();
+        let right = platform.right_segments();
+        let plane = match (&platform.left, &platform.right, &left[..], &right[..]) {
+            (
+                PlatformEdge::Mirror { x_offset },
+                _,
+                _,
+                [EdgeSegment::CubicBezier {
+                    start,
+                    control_points,
+                    end,
+                }],
+            )
+            | (
+                _,
+                PlatformEdge::Mirror { x_offset },
+                [EdgeSegment::CubicBezier {
+                    start,
+                    control_points,
+                    end,
+                }],
+                _,
+            ) => Plane::double_sided_parallel_cubic_bezier(
+                xz(start),
+                xz(end),
+                xz_pair(control_points),
+                *x_offset,
+                subdivisions,
+            ),
+            (
+                _,
+                _,
+                [EdgeSegment::Straight {
+                    start: left_start,
+                    end: left_end,
+                }],
+                [EdgeSegment::Straight {
+                    start: right_start,
+                    end: right_end,
+                }],
+            ) => Plane::quad(xz(left_start), xz(right_start), xz(left_end), xz(right_end)),
+            (
+                _,
+                _,
+                [EdgeSegment::CubicBezier {
+                    start: curve_start,
+                    control_points,
+                    end: curve_end,
+                }],
+                [EdgeSegment::Straight { start, end }],
+            )
+            | (
+                _,
+                _,
+                [EdgeSegment::Straight { start, end }],
+                [EdgeSegment::CubicBezier {
+                    start: curve_start,
+                    control_points,
+                    end: curve_end,
+                }],
+            ) => Plane::single_sided_cubic_bezier(
+                xz(curve_start),
+                xz(curve_end),
+                xz_pair(control_points),
+                xz(start),
+                xz(end),
+                subdivisions,
+            ),
+            (
+                _,
+                _,
+                [EdgeSegment::CubicBezier {
+                    start: left_start,
+                    control_points: left_control_points,
+                    end: left_end,
+                }],
+                [EdgeSegment::CubicBezier {
+                    start: right_start,
+                    control_points: right_control_points,
+                    end: right_end,
+                }],
+            ) => Plane::double_sided_cubic_bezier(
+                xz(left_start),
+                xz(left_end),
+                xz_pair(left_control_points),
+                xz(right_start),
+                xz(right_end),
+                xz_pair(right_control_points),
+                subdivisions,
+            ),
+            _ => {
+                let walls_left_vertices = self.track_position_to_vertices(&platform.points_left());
+                let walls_right_vertices =
+                    self.track_position_to_vertices(&platform.points_right());
+                if walls_left_vertices.len() < 2 || walls_right_vertices.len() < 2 {
+                    log::warn!("Skipping platform with an edge of less than two points");
+                    return None;
+                }
+                Plane::triangulate_from_two_sides(walls_left_vertices, walls_right_vertices)
+            }
+        };
 
-        mesh
+        Some(plane.to_mesh())
     }
 
     fn create_plane_mesh_from_points(&self, points: &[TrackPosition], width: f32) -> Mesh {
         let left_points = self
-            .track_position_to_xz_vertices(points)
+            .track_position_to_vertices(points)
             .into_iter()
             .map(|mut point| {
                 point.x = point.x - (width / 2.0);
@@ -208,7 +317,7 @@ impl TrackDescriptionCreator {
             })
             .collect();
         let right_points = self
-            .track_position_to_xz_vertices(points)
+            .track_position_to_vertices(points)
             .into_iter()
             .map(|mut point| {
                 point.x = point.x + (width / 2.0);
@@ -218,40 +327,39 @@ impl TrackDescriptionCreator {
         Plane::triangulate_from_two_sides(left_points, right_points).to_mesh()
     }
 
-    fn create_lanes(&self, lanes: &HashMap<LaneType, Vec<Lane>>) -> LanesDescription {
-        let (vertices, indices, objects, objects_indices) = lanes.iter().fold(
-            (Vec::new(), Vec::new(), Vec::new(), Vec::new()),
-            |(mut vertices, mut indices, mut objects, mut objects_indices), (lane_type, lanes)| {
-                for lane in lanes {
-                    // If not enemy lane. XXX TODO: Have proper enum type.
-                    if *lane_type != LaneType(4) {
-                        let mesh = self.create_plane_mesh_from_points(&lane.points, 0.1);
-
-                        // Append object index to global object indices array.
-                        let current_object_index = objects.len();
-                        objects_indices.extend(
-                            std::iter::repeat(current_object_index).take(mesh.vertices.len()),
-                        );
-
-                        // Append to global vertex indices array.
-                        let current_index_offset = vertices.len() as u16;
-                        vertices.extend(mesh.vertices);
-
-                        for index in mesh.indices {
-                            indices.push(index + current_index_offset);
-                        }
-
-                        objects.push(ObjectInstance {
-                            base_color: get_base_color_lane(*lane_type),
-                            z_position: 0.0,
-                            x_position: 0.0,
-                            apply_runner_transform: true,
-                        });
+    // XXX TODO: Use the lane links to draw joins and fade out lanes without successors.
+    fn create_lanes(&self, lanes: &Lanes) -> LanesDescription {
+        // Enemy lanes are not rendered.
+        let (vertices, indices, objects, objects_indices) =
+            lanes.iter().filter(|lane| lane.ty != LaneType::Enemy).fold(
+                (Vec::new(), Vec::new(), Vec::new(), Vec::new()),
+                |(mut vertices, mut indices, mut objects, mut objects_indices), lane| {
+                    let mesh = self.create_plane_mesh_from_points(&lane.points, 0.1);
+
+                    // Append object index to global object indices array.
+                    let current_object_index = objects.len();
+                    objects_indices
+                        .extend(std::iter::repeat(current_object_index).take(mesh.vertices.len()));
+
+                    // Append to global vertex indices array.
+                    let current_index_offset = vertices.len() as u16;
+                    vertices.extend(mesh.vertices);
+
+                    for index in mesh.indices {
+                        indices.push(index + current_index_offset);
                     }
-                }
-                (vertices, indices, objects, objects_indices)
-            },
-        );
+
+                    objects.push(ObjectInstance {
+                        base_color: self.lane_color(lane.ty),
+                        z_position: 0.0,
+                        x_position: 0.0,
+                        y_position: 0.0,
+                        apply_runner_transform: true,
+                    });
+
+                    (vertices, indices, objects, objects_indices)
+                },
+            );
 
         assert_eq!(vertices.len(), objects_indices.len());
         assert_eq!(*objects_indices.last().unwrap(), objects.len() - 1);
@@ -266,15 +374,19 @@ impl TrackDescriptionCreator {
         }
     }
 
-    fn create_hold_note_mesh(&self, hold_note: &HoldNote) -> Mesh {
-        self.create_plane_mesh_from_points(&hold_note.points, HIT_X_LENGTH)
+    fn create_hold_note_mesh(&self, points: &[TrackPosition]) -> Mesh {
+        self.create_plane_mesh_from_points(points, HIT_X_LENGTH)
     }
 
-    fn create_hold_notes(&self, hold_notes: &[HoldNote]) -> HoldNotesDescription {
-        let (vertices, indices, objects, objects_indices) = hold_notes.iter().fold(
+    /// Holds as their points and color, used for both holds and target holds.
+    fn create_hold_notes<'a>(
+        &self,
+        hold_notes: impl Iterator<Item = (&'a [TrackPosition], Vector4<f32>)>,
+    ) -> HoldNotesDescription {
+        let (vertices, indices, objects, objects_indices) = hold_notes.fold(
             (Vec::new(), Vec::new(), Vec::new(), Vec::new()),
-            |(mut vertices, mut indices, mut objects, mut objects_indices), note| {
-                let mesh = self.create_hold_note_mesh(&note);
+            |(mut vertices, mut indices, mut objects, mut objects_indices), (points, color)| {
+                let mesh = self.create_hold_note_mesh(points);
 
                 // Append object index to global object indices array.
                 let current_object_index = objects.len();
@@ -290,10 +402,11 @@ impl TrackDescriptionCreator {
                 }
 
                 objects.push(ObjectInstance {
-                    base_color: get_base_color_hit(note.ty),
+                    base_color: color,
                     // Zero here because raw vertces are already properly transformed.
                     z_position: 0.0,
                     x_position: 0.0,
+                    y_position: 0.0,
                     apply_runner_transform: true,
                 });
 
@@ -302,7 +415,10 @@ impl TrackDescriptionCreator {
         );
 
         assert_eq!(vertices.len(), objects_indices.len());
-        assert_eq!(*objects_indices.last().unwrap(), objects.len() - 1);
+        // Charts without target holds are common.
+        if let Some(last_object_index) = objects_indices.last() {
+            assert_eq!(*last_object_index, objects.len() - 1);
+        }
 
         let mesh = Mesh { vertices, indices };
         let mesh = mesh.transform(&Matrix4::new_translation(&Vector3::new(0.0, -0.005, 0.0)));
@@ -316,40 +432,68 @@ impl TrackDescriptionCreator {
 
     // Created positions are affected by the runner speed.
     fn create(self, chart: &ChartData) -> TrackDescription {
-        let notes_hit = chart
-            .notes
+        let notes = &chart.notes;
+        let notes_hit = notes
             .hits
             .iter()
-            .map(|n| ObjectInstance::from_chart_hit(n, true))
+            .map(|n| {
+                let color = self.note_color(NoteStyleKey::Hit(n.ty));
+                ObjectInstance::from_chart_position(&n.position, color, true)
+            })
             .collect();
-        let notes_contact = chart
-            .notes
+        let notes_contact = notes
             .contacts
             .iter()
-            .map(|n| ObjectInstance::from_chart_contact(n, true))
+            .map(|n| {
+                let color = self.note_color(NoteStyleKey::Contact(n.ty));
+                ObjectInstance::from_chart_position(&n.position, color, true)
+            })
             .collect();
-        let notes_flick = chart
-            .notes
+        let flick_color = self.note_color(NoteStyleKey::Flick);
+        let notes_flick = notes
             .flicks
             .iter()
-            .map(|n| ObjectInstance::from_chart_flick(n, true))
+            .map(|n| ObjectInstance::from_chart_position(&n.position, flick_color, true))
             .collect();
-        let notes_evade = chart
-            .notes
+        let target_color = self.note_color(NoteStyleKey::Target);
+        let notes_target = notes
+            .targets
+            .iter()
+            .map(|n| ObjectInstance::from_chart_position(&n.position, target_color, true))
+            .collect();
+        let notes_evade = notes
             .evades
             .iter()
-            .map(|n| EvadeObjectInstance::from_chart_evade(n))
+            .map(|n| {
+                let color = self.note_color(NoteStyleKey::Evade(n.ty, n.size));
+                EvadeObjectInstance::from_chart_evade(n, color)
+            })
             .collect();
 
         let notes_hit = self.apply_runner_speed(notes_hit);
         let notes_contact = self.apply_runner_speed(notes_contact);
         let notes_flick = self.apply_runner_speed(notes_flick);
-        let notes_evade = self.apply_runner_speed_on_evade_notes(notes_evade);
+        let notes_target = self.apply_runner_speed(notes_target);
 
-        // XXX TODO: Handle case where more than one platform instance exists.
-        let platform_mesh = self.create_platform_mesh(&chart.track.platforms[0]);
+        let platform_meshes = chart
+            .track
+            .platforms
+            .iter()
+            .filter_map(|p| self.create_platform_mesh(p))
+            .collect();
 
-        let hold_notes = self.create_hold_notes(&chart.notes.holds);
+        let hold_notes = self.create_hold_notes(notes.holds.iter().map(|n| {
+            (
+                n.points.as_slice(),
+                self.note_color(NoteStyleKey::Hit(n.ty)),
+            )
+        }));
+        let target_hold_notes = self.create_hold_notes(
+            notes
+                .target_holds
+                .iter()
+                .map(|n| (n.points.as_slice(), target_color)),
+        );
 
         let lanes = self.create_lanes(&chart.track.lanes);
 
@@ -357,21 +501,25 @@ impl TrackDescriptionCreator {
             notes_hit,
             notes_contact,
             notes_flick,
+            notes_target,
 
             notes_evade,
+            animations: chart.animations.clone(),
 
             settings: self.settings,
 
-            platform_mesh,
+            platform_meshes,
             // XXX TODO: Properly fill this.
             platform_instances: vec![ObjectInstance {
                 z_position: 0.0,
                 x_position: 0.0,
+                y_position: 0.0,
                 base_color: Vector4::new(0.0, 0.0, 0.0, 1.0),
                 apply_runner_transform: true,
             }],
 
             hold_notes,
+            target_hold_notes,
             lanes,
         }
     }
@@ -379,6 +527,74 @@ impl TrackDescriptionCreator {
 
 impl TrackDescription {
     pub(crate) fn from_chart(chart: &Chart, settings: TrackSettings) -> Self {
-        TrackDescriptionCreator::new(settings).create(&chart.data)
+        TrackDescriptionCreator::new(settings, chart.note_styles.clone()).create(&chart.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eclale_chart::parse::ecl::create_chart_from_ecl_source;
+
+    use super::*;
+
+    const CHART: &str = "\
+<header>
+default_tempo=120
+default_time_signature=4/4
+
+<notes>
+[target]
+color=00FF80
+
+<chart_body>
+[B1] (1) |0|
+[T] (2) |0.5|
+[HT] (3;4) |0|
+";
+
+    fn target_color() -> Vector4<f32> {
+        Vector4::new(0.0, 1.0, 128.0 / 255.0, 1.0)
+    }
+
+    fn creator(note_styles: NoteStyles) -> TrackDescriptionCreator {
+        TrackDescriptionCreator::new(TrackSettings { runner_speed: 2.0 }, note_styles)
+    }
+
+    #[test]
+    fn chart_colors_override_the_default_palette() {
+        let chart = create_chart_from_ecl_source(CHART).unwrap();
+        let creator = creator(chart.note_styles.clone());
+
+        assert_eq!(creator.note_color(NoteStyleKey::Target), target_color());
+        let left = NoteStyleKey::Hit(HitNoteType::Left);
+        assert_eq!(creator.note_color(left), get_default_note_color(left));
+        assert_eq!(
+            creator.lane_color(LaneType::Left),
+            get_default_note_color(left)
+        );
+    }
+
+    #[test]
+    fn targets_and_target_holds_use_the_target_color() {
+        let chart = create_chart_from_ecl_source(CHART).unwrap();
+        let description = creator(chart.note_styles.clone()).create(&chart.data);
+
+        let targets = &description.notes_target;
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].base_color, target_color());
+        assert_eq!(targets[0].x_position, 0.5);
+        assert_eq!(
+            targets[0].z_position,
+            chart.data.notes.targets[0].position.z * 2.0
+        );
+
+        let target_holds = &description.target_hold_notes;
+        assert_eq!(target_holds.objects.len(), 1);
+        assert_eq!(target_holds.objects[0].base_color, target_color());
+        assert_eq!(
+            target_holds.mesh.vertices.len(),
+            target_holds.objects_indices.len()
+        );
+        assert!(description.hold_notes.objects.is_empty());
     }
 }
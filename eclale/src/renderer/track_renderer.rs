@@ -1,6 +1,7 @@
 use anyhow::{anyhow, Result};
 
 use bytemuck::{Pod, Zeroable};
+use eclale_chart::TrackPosition;
 use nalgebra::{
     Isometry3, Matrix4, Orthographic3, Perspective3, Point3, Vector2, Vector3, Vector4,
 };
@@ -53,7 +54,7 @@ impl ObjectInstanceGpuData {
         Self {
             transform: Matrix4::new_translation(&Vector3::new(
                 instance.x_position,
-                0.0,
+                instance.y_position,
                 instance.z_position,
             )),
             base_color: instance.base_color,
@@ -71,24 +72,39 @@ impl ObjectInstanceGpuData {
         instances.iter().map(Self::from_object_instance).collect()
     }
 
-    fn from_evade_object_instance(instance: &EvadeObjectInstance) -> Self {
+    fn from_evade_object_instance(
+        instance: &EvadeObjectInstance,
+        description: &TrackDescription,
+    ) -> Self {
         Self {
-            // Use initial position.
-            transform: Matrix4::new_translation(&Vector3::new(
-                instance.start_position.x,
-                0.0,
-                instance.start_position.y,
-            )),
+            // Use initial pose, player targeted ends are not known yet.
+            transform: instance.transform_at(
+                &description.animations,
+                &instance.note.movement.end,
+                f32::MIN,
+                description.settings.runner_speed,
+            ),
             base_color: instance.base_color,
             apply_runner_transform: 1,
             ..Default::default()
         }
     }
 
-    fn from_evade_object_instances(instances: &[EvadeObjectInstance]) -> Vec<Self> {
-        instances
+    /// The player stays at the hit bar, it does not move with the runner.
+    fn from_player_position(x: f32) -> Self {
+        Self {
+            transform: Matrix4::new_translation(&Vector3::new(x, 0.0, 0.0)),
+            base_color: Vector4::new(0.9, 0.9, 0.9, 1.0),
+            apply_runner_transform: 0,
+            ..Default::default()
+        }
+    }
+
+    fn from_evade_object_instances(description: &TrackDescription) -> Vec<Self> {
+        description
+            .notes_evade
             .iter()
-            .map(Self::from_evade_object_instance)
+            .map(|n| Self::from_evade_object_instance(n, description))
             .collect()
     }
 
@@ -107,11 +123,15 @@ impl ObjectInstanceGpuData {
 pub(crate) const HIT_Z_LENGTH: f32 = 0.1;
 pub(crate) const HIT_X_LENGTH: f32 = 0.25;
 
+const PLAYER_RADIUS: f32 = 0.1;
+
 struct RenderMeshes {
     hit: Mesh,
     contact: Mesh,
     evade: Mesh,
     flick: Mesh,
+    target: Mesh,
+    player: Mesh,
 }
 
 impl RenderMeshes {
@@ -132,11 +152,23 @@ impl RenderMeshes {
 
         let flick = Polyhedron::cuboid(0.9, 0.05, HIT_Z_LENGTH).into();
 
+        // Targets stand above the track so they read as something to run into.
+        let target =
+            Mesh::from(Polyhedron::octahedron(HIT_X_LENGTH, HIT_Z_LENGTH * 2.0)).transform(
+                &Matrix4::new_translation(&Vector3::new(0.0, HIT_X_LENGTH, -HIT_Z_LENGTH)),
+            );
+
+        let player = Mesh::from(Polyhedron::icosahedron(PLAYER_RADIUS)).transform(
+            &Matrix4::new_translation(&Vector3::new(0.0, PLAYER_RADIUS, 0.0)),
+        );
+
         Self {
             hit,
             contact,
             evade,
             flick,
+            target,
+            player,
         }
     }
 
@@ -155,6 +187,14 @@ impl RenderMeshes {
     fn flick(&self) -> Mesh {
         self.flick.clone()
     }
+
+    fn target(&self) -> Mesh {
+        self.target.clone()
+    }
+
+    fn player(&self) -> Mesh {
+        self.player.clone()
+    }
 }
 
 struct RenderPipelines;
@@ -176,6 +216,23 @@ impl RenderPipelines {
         }
     }
 
+    /// Same instance data as `instanced`, shaded so targets stand out from hit notes.
+    fn instanced_target() -> RenderPipelineDescription {
+        RenderPipelineDescription {
+            rendering_type: RenderingType::Instanced,
+            shader_modules: vec![
+                ShaderModuleDescriptor {
+                    source_file_name: String::from("shaders/object_target_instanced.vs.glsl"),
+                    shader_stage: ShaderStage::Vertex,
+                },
+                ShaderModuleDescriptor {
+                    source_file_name: String::from("shaders/object_target_instanced.fs.glsl"),
+                    shader_stage: ShaderStage::Fragment,
+                },
+            ],
+        }
+    }
+
     // Multiple objects and one vertex stream for all objects' vertex data.
     fn mosv_planes_smooth() -> RenderPipelineDescription {
         RenderPipelineDescription {
@@ -282,6 +339,8 @@ impl RenderDescriptionCreator {
         let pipeline_index_instanced = self.add_pipeline(RenderPipelines::instanced());
         let pipeline_index_mosv_planes = self.add_pipeline(RenderPipelines::mosv_planes_smooth());
         let pipeline_index_mosv_lines = self.add_pipeline(RenderPipelines::mosv_lines_smooth());
+        let pipeline_index_instanced_target =
+            self.add_pipeline(RenderPipelines::instanced_target());
 
         self.add_objects_instanced_draw_data(
             &ObjectInstanceGpuData::from_object_instances(&self.description.notes_hit),
@@ -298,22 +357,38 @@ impl RenderDescriptionCreator {
             self.meshes.flick(),
             pipeline_index_instanced,
         );
+        self.add_objects_instanced_draw_data(
+            &ObjectInstanceGpuData::from_object_instances(&self.description.notes_target),
+            self.meshes.target(),
+            pipeline_index_instanced_target,
+        );
 
         let evade_notes_renderer_index = self.instanced_draw_data.len();
         let evade_notes_object_instances =
-            ObjectInstanceGpuData::from_evade_object_instances(&self.description.notes_evade);
+            ObjectInstanceGpuData::from_evade_object_instances(&self.description);
         self.add_objects_instanced_draw_data(
             &evade_notes_object_instances,
             self.meshes.evade(),
             pipeline_index_instanced,
         );
 
+        let player_renderer_index = self.instanced_draw_data.len();
         self.add_objects_instanced_draw_data(
-            &ObjectInstanceGpuData::from_object_instances(&self.description.platform_instances),
-            self.description.platform_mesh.clone(),
+            &[ObjectInstanceGpuData::from_player_position(0.0)],
+            self.meshes.player(),
             pipeline_index_instanced,
         );
 
+        let platform_object_instances =
+            ObjectInstanceGpuData::from_object_instances(&self.description.platform_instances);
+        for platform_mesh in self.description.platform_meshes.clone() {
+            self.add_objects_instanced_draw_data(
+                &platform_object_instances,
+                platform_mesh,
+                pipeline_index_instanced,
+            );
+        }
+
         self.add_mosv_draw_data(
             pipeline_index_mosv_planes,
             self.description.hold_notes.mesh.clone(),
@@ -321,6 +396,17 @@ impl RenderDescriptionCreator {
             &self.description.hold_notes.objects_indices.clone(),
         );
 
+        if !self.description.target_hold_notes.objects.is_empty() {
+            self.add_mosv_draw_data(
+                pipeline_index_mosv_planes,
+                self.description.target_hold_notes.mesh.clone(),
+                &ObjectInstanceGpuData::from_object_instances(
+                    &self.description.target_hold_notes.objects,
+                ),
+                &self.description.target_hold_notes.objects_indices.clone(),
+            );
+        }
+
         self.add_mosv_draw_data(
             pipeline_index_mosv_lines,
             self.description.lanes.mesh.clone(),
@@ -341,6 +427,7 @@ impl RenderDescriptionCreator {
                     evade_notes_instances: self.description.notes_evade,
                     renderer_index: evade_notes_renderer_index,
                 },
+                player_renderer_index,
             },
         )
     }
@@ -349,6 +436,7 @@ impl RenderDescriptionCreator {
 #[derive(Clone)]
 struct RenderDescriptionMetadata {
     evade_notes_data: EvadeNotesRendererData,
+    player_renderer_index: usize,
 }
 
 #[derive(Clone)]
@@ -365,6 +453,7 @@ pub(crate) struct TrackRenderer {
     scene_uniform: SceneUniformGpuData,
 
     evade_notes_data: EvadeNotesRendererData,
+    player_renderer_index: usize,
 }
 
 impl TrackRenderer {
@@ -376,6 +465,7 @@ impl TrackRenderer {
         let (render_description, render_description_metadata) =
             RenderDescriptionCreator::new(track_description.clone()).create();
         let evade_notes_data = render_description_metadata.evade_notes_data;
+        let player_renderer_index = render_description_metadata.player_renderer_index;
 
         let renderer = Renderer::new(window_handle, display_handle, render_description.clone())?;
 
@@ -385,6 +475,7 @@ impl TrackRenderer {
             render_description,
             scene_uniform: SceneUniformGpuData::default(),
             evade_notes_data,
+            player_renderer_index,
         })
     }
 
@@ -401,38 +492,39 @@ impl TrackRenderer {
         self.scene_uniform.view_projection = view_projection;
     }
 
-    pub(crate) fn update_runner_position(&mut self, runner_position: f32, time: f32) {
+    /// `evade_end` gives the resolved end of the evade note at an index of the chart's evade notes,
+    /// so player targeted notes are drawn towards the same x they are judged at.
+    pub(crate) fn update_runner_position(
+        &mut self,
+        runner_position: f32,
+        time: f32,
+        evade_end: impl Fn(usize) -> TrackPosition,
+    ) {
         self.scene_uniform.runner_transform =
             Matrix4::new_translation(&Vector3::new(0.0, 0.0, -runner_position));
 
-        self.update_evade_notes(time);
+        self.update_evade_notes(time, evade_end);
     }
 
-    fn update_evade_notes(&mut self, current_time: f32) {
+    fn update_evade_notes(
+        &mut self,
+        current_time: f32,
+        evade_end: impl Fn(usize) -> TrackPosition,
+    ) {
         // XXX TODO: Properly optimize evade note position update, only check and change what is necessary.
         // XXX TODO: Move this logic outside of renderer to a more general game logic processing
         // handler.
-        for (i, note) in self
+        let animations = &self.track_description.animations;
+        let runner_speed = self.track_description.settings.runner_speed;
+        for (index, (gpu_instance, note)) in self
             .evade_notes_data
-            .evade_notes_instances
-            .iter()
+            .gpu_object_instances
+            .iter_mut()
+            .zip(self.evade_notes_data.evade_notes_instances.iter())
             .enumerate()
         {
-            let current_position = if current_time < note.trigger_time {
-                // Note has not started yet; return the start position/
-                note.start_position
-            } else if current_time > note.trigger_time + note.duration {
-                // Note has already ended; return the end position/
-                note.end_position
-            } else {
-                // Note is moving; calculate normalized time `t`.
-                let t = (current_time - note.trigger_time) / note.duration;
-                // Interpolate position.
-                note.start_position + t * (note.end_position - note.start_position)
-            };
-
-            self.evade_notes_data.gpu_object_instances[i].transform =
-                Matrix4::new_translation(&Vector3::new(current_position.x, 0.0, current_position.y))
+            gpu_instance.transform =
+                note.transform_at(animations, &evade_end(index), current_time, runner_speed);
         }
 
         let gpu_data =
@@ -448,6 +540,18 @@ impl TrackRenderer {
             .unwrap();
     }
 
+    pub(crate) fn update_player_position(&mut self, x: f32) {
+        let instance = ObjectInstanceGpuData::from_player_position(x);
+
+        // XXX TODO: remove the unwrap here.
+        self.renderer
+            .update_instanced_renderer_instance_gpu_data(
+                self.player_renderer_index,
+                bytemuck::bytes_of(&instance),
+            )
+            .unwrap();
+    }
+
     pub(crate) fn swapchain_extent(&self) -> Vector2<u32> {
         self.renderer.swapchain_extent()
     }
@@ -2,9 +2,11 @@ use anyhow::Result;
 
 use kira::{
     manager::{backend::DefaultBackend, AudioManager, AudioManagerSettings},
-    sound::static_sound::{StaticSoundData, StaticSoundHandle},
+    sound::static_sound::StaticSoundData,
 };
 
+pub use kira::{sound::static_sound::StaticSoundHandle, tween::Tween};
+
 pub struct AudioSystem {
     audio_manager: AudioManager,
     static_sounds: Vec<StaticSoundData>,
@@ -0,0 +1,390 @@
+//! Keyframed object animations. Animations are declared once per chart in an `AnimationLibrary`
+//! and notes reference them by id as an ordered stack that is played back to back, ending at the
+//! note's hit time.
+
+use std::ops::{Add, Mul, Sub};
+
+use crate::Time;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub const ZERO: Self = Self::new(0.0, 0.0, 0.0);
+    pub const ONE: Self = Self::new(1.0, 1.0, 1.0);
+
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn lerp(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+
+    /// Component-wise division.
+    fn divide(self, other: Self) -> Self {
+        Self::new(self.x / other.x, self.y / other.y, self.z / other.z)
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Self;
+
+    fn mul(self, scalar: f32) -> Self {
+        Self::new(self.x * scalar, self.y * scalar, self.z * scalar)
+    }
+}
+
+/// Affine transform of an object, stored as a column-major 4x4 matrix so it can be handed to the
+/// GPU as is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub columns: [[f32; 4]; 4],
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        columns: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut transform = Self::IDENTITY;
+        transform.columns[3] = [offset.x, offset.y, offset.z, 1.0];
+        transform
+    }
+
+    pub fn scale(factors: Vec3) -> Self {
+        let mut transform = Self::IDENTITY;
+        transform.columns[0][0] = factors.x;
+        transform.columns[1][1] = factors.y;
+        transform.columns[2][2] = factors.z;
+        transform
+    }
+
+    /// Rotation by euler angles in radians, around x first, then y, then z.
+    pub fn rotation(angles: Vec3) -> Self {
+        let (sx, cx) = angles.x.sin_cos();
+        let (sy, cy) = angles.y.sin_cos();
+        let (sz, cz) = angles.z.sin_cos();
+
+        // Columns of Rz * Ry * Rx.
+        Self {
+            columns: [
+                [cz * cy, sz * cy, -sy, 0.0],
+                [cz * sy * sx - sz * cx, sz * sy * sx + cz * cx, cy * sx, 0.0],
+                [cz * sy * cx + sz * sx, sz * sy * cx - cz * sx, cy * cx, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// Inverse of `rotation(angles)`, rotation matrices are orthogonal.
+    fn inverse_rotation(angles: Vec3) -> Self {
+        let rotation = Self::rotation(angles);
+        let mut transform = Self::IDENTITY;
+        for (column, row) in (0..3).flat_map(|c| (0..3).map(move |r| (c, r))) {
+            transform.columns[column][row] = rotation.columns[row][column];
+        }
+        transform
+    }
+
+    /// Translation part of the transform, i.e. where the object's origin ends up.
+    pub fn position(&self) -> Vec3 {
+        let [x, y, z, _] = self.columns[3];
+        Vec3::new(x, y, z)
+    }
+}
+
+impl Mul for Transform {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut columns = [[0.0; 4]; 4];
+        for (column, values) in columns.iter_mut().enumerate() {
+            for (row, value) in values.iter_mut().enumerate() {
+                *value = (0..4)
+                    .map(|k| self.columns[k][row] * other.columns[column][k])
+                    .sum();
+            }
+        }
+        Self { columns }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnimationKind {
+    /// Moves the object by `to - from`.
+    Translation { from: Vec3, to: Vec3 },
+    /// Rotates the object by `angles` in radians around `center`, relative to the object.
+    Rotation { angles: Vec3, center: Vec3 },
+    /// Scales the object by `to / from`, components of both must not be zero.
+    Scale { from: Vec3, to: Vec3 },
+}
+
+/// Values are interpolated linearly over the duration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animation {
+    pub name: String,
+    pub kind: AnimationKind,
+    /// Seconds.
+    pub duration: f32,
+}
+
+impl Animation {
+    /// Change applied by rotations and scales after `progress` of the animation, in [0, 1].
+    fn local_delta(&self, progress: f32) -> Transform {
+        match self.kind {
+            AnimationKind::Translation { .. } => Transform::IDENTITY,
+            AnimationKind::Rotation { angles, center } => {
+                Transform::translation(center)
+                    * Transform::rotation(angles * progress)
+                    * Transform::translation(center * -1.0)
+            }
+            AnimationKind::Scale { from, to } => {
+                Transform::scale(from.lerp(to, progress).divide(from))
+            }
+        }
+    }
+
+    /// `pose` after `progress` of the animation, in [0, 1]. Translations move the object in the
+    /// world, rotations and scales are applied in the object's frame so they do not change the
+    /// direction it moves in.
+    pub fn apply(&self, pose: Transform, progress: f32) -> Transform {
+        let progress = progress.clamp(0.0, 1.0);
+        match self.kind {
+            AnimationKind::Translation { from, to } => {
+                Transform::translation((to - from) * progress) * pose
+            }
+            AnimationKind::Rotation { .. } | AnimationKind::Scale { .. } => {
+                pose * self.local_delta(progress)
+            }
+        }
+    }
+
+    /// Inverse of `apply(pose, 1.0)`.
+    pub fn undo(&self, pose: Transform) -> Transform {
+        match self.kind {
+            AnimationKind::Translation { from, to } => Transform::translation(from - to) * pose,
+            AnimationKind::Rotation { angles, center } => {
+                pose * Transform::translation(center)
+                    * Transform::inverse_rotation(angles)
+                    * Transform::translation(center * -1.0)
+            }
+            AnimationKind::Scale { from, to } => pose * Transform::scale(from.divide(to)),
+        }
+    }
+}
+
+/// Index into an `AnimationLibrary`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AnimationId(pub u32);
+
+/// Every animation of a chart, in declaration order.
+#[derive(Clone, Debug, Default)]
+pub struct AnimationLibrary {
+    animations: Vec<Animation>,
+}
+
+impl AnimationLibrary {
+    pub fn add(&mut self, animation: Animation) -> AnimationId {
+        self.animations.push(animation);
+        AnimationId((self.animations.len() - 1) as u32)
+    }
+
+    pub fn get(&self, id: AnimationId) -> Option<&Animation> {
+        self.animations.get(id.0 as usize)
+    }
+
+    pub fn find(&self, name: &str) -> Option<AnimationId> {
+        self.animations
+            .iter()
+            .position(|a| a.name == name)
+            .map(|i| AnimationId(i as u32))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (AnimationId, &Animation)> {
+        self.animations
+            .iter()
+            .enumerate()
+            .map(|(i, a)| (AnimationId(i as u32), a))
+    }
+
+    pub fn len(&self) -> usize {
+        self.animations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.animations.is_empty()
+    }
+
+    /// Unknown ids are skipped.
+    fn resolve<'a>(&'a self, stack: &'a [AnimationId]) -> impl Iterator<Item = &'a Animation> {
+        stack.iter().filter_map(|id| self.get(*id))
+    }
+
+    /// Total duration of `stack` in seconds.
+    pub fn duration(&self, stack: &[AnimationId]) -> f32 {
+        self.resolve(stack)
+            .fold(0.0, |duration, a| duration + a.duration)
+    }
+
+    /// Pose before `stack` starts, found by undoing every animation from the `end` pose in reverse
+    /// order.
+    pub fn start_pose(&self, stack: &[AnimationId], end: Transform) -> Transform {
+        self.resolve(stack)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .fold(end, |pose, animation| animation.undo(pose))
+    }
+
+    /// Pose at `time` of an object that plays `stack` and reaches the `end` pose at `end_time`.
+    pub fn pose_at(
+        &self,
+        stack: &[AnimationId],
+        end: Transform,
+        end_time: Time,
+        time: Time,
+    ) -> Transform {
+        if time.0 >= end_time.0 {
+            return end;
+        }
+
+        let mut pose = self.start_pose(stack, end);
+        let mut animation_start = end_time.0 - self.duration(stack);
+        for animation in self.resolve(stack) {
+            if time.0 < animation_start {
+                break;
+            }
+
+            let progress = if animation.duration > 0.0 {
+                (time.0 - animation_start) / animation.duration
+            } else {
+                1.0
+            };
+            pose = animation.apply(pose, progress);
+            animation_start += animation.duration;
+        }
+        pose
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_transform_close(a: Transform, b: Transform) {
+        let close = a
+            .columns
+            .iter()
+            .flatten()
+            .zip(b.columns.iter().flatten())
+            .all(|(a, b)| (a - b).abs() < 1e-4);
+        assert!(close, "{:?} != {:?}", a, b);
+    }
+
+    fn animation(name: &str, kind: AnimationKind) -> Animation {
+        Animation {
+            name: name.to_string(),
+            kind,
+            duration: 0.5,
+        }
+    }
+
+    fn library() -> (AnimationLibrary, Vec<AnimationId>) {
+        let mut library = AnimationLibrary::default();
+        let stack = vec![
+            library.add(animation(
+                "approach",
+                AnimationKind::Translation {
+                    from: Vec3::new(0.0, 0.0, 2.0),
+                    to: Vec3::ZERO,
+                },
+            )),
+            library.add(animation(
+                "grow",
+                AnimationKind::Scale {
+                    from: Vec3::new(1.0, 1.0, 1.0),
+                    to: Vec3::new(2.0, 2.0, 2.0),
+                },
+            )),
+        ];
+        (library, stack)
+    }
+
+    #[test]
+    fn undo_reverts_apply() {
+        let pose = Transform::translation(Vec3::new(1.0, 2.0, 3.0));
+        let kinds = [
+            AnimationKind::Translation {
+                from: Vec3::new(1.0, 0.0, 0.0),
+                to: Vec3::new(0.0, 1.0, 0.0),
+            },
+            AnimationKind::Rotation {
+                angles: Vec3::new(0.3, 1.2, -0.5),
+                center: Vec3::new(0.5, 0.0, 0.0),
+            },
+            AnimationKind::Scale {
+                from: Vec3::new(1.0, 2.0, 1.0),
+                to: Vec3::new(3.0, 1.0, 0.5),
+            },
+        ];
+        for kind in kinds {
+            let animation = animation("a", kind);
+            assert_transform_close(animation.undo(animation.apply(pose, 1.0)), pose);
+        }
+    }
+
+    #[test]
+    fn stacks_play_in_order_and_end_at_the_end_pose() {
+        let (library, stack) = library();
+        let end = Transform::translation(Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(library.duration(&stack), 1.0);
+        assert_eq!(library.find("grow"), Some(stack[1]));
+
+        let pose_at = |time| library.pose_at(&stack, end, Time(2.0), Time(time));
+        assert_eq!(pose_at(0.0).position(), Vec3::new(1.0, 0.0, 2.0));
+        assert_eq!(pose_at(1.25).position(), Vec3::new(1.0, 0.0, 1.0));
+        // Scaled by half of the scale animation.
+        assert_eq!(pose_at(1.75).columns[0][0], 0.75);
+        assert_eq!(pose_at(2.0), end);
+    }
+
+    #[test]
+    fn unknown_ids_are_skipped() {
+        let (library, mut stack) = library();
+        stack.push(AnimationId(10));
+        assert_eq!(library.duration(&stack), 1.0);
+    }
+}
@@ -0,0 +1,234 @@
+use std::fmt;
+
+use thiserror::Error;
+
+/// Location in a chart source file. Parts that are not known for a chart format are `None`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: Option<String>,
+    /// Starts at 1.
+    pub line: Option<usize>,
+    /// Starts at 1.
+    pub column: Option<usize>,
+}
+
+impl SourceLocation {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self {
+            file: None,
+            line: Some(line),
+            column: Some(column),
+        }
+    }
+
+    pub fn file(file: &str) -> Self {
+        Self {
+            file: Some(file.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.file.as_deref().unwrap_or("<chart>"))?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error produced when a chart cannot be imported or written.
+#[derive(Debug, Error)]
+pub enum ChartError {
+    #[error("{location}: failed to read or write chart file: {source}")]
+    Io {
+        location: SourceLocation,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("{location}: chart is not valid UTF-8: {source}")]
+    Encoding {
+        location: SourceLocation,
+        #[source]
+        source: std::str::Utf8Error,
+    },
+
+    /// No importer recognizes the chart file.
+    #[error("{location}: unknown chart format")]
+    UnknownFormat { location: SourceLocation },
+
+    /// Malformed line or value that does not follow the chart format.
+    #[error("{location}: {message}")]
+    Syntax {
+        location: SourceLocation,
+        message: String,
+    },
+
+    #[error("{location}: invalid {expected} `{value}`")]
+    InvalidValue {
+        location: SourceLocation,
+        expected: &'static str,
+        value: String,
+    },
+
+    /// Name that is not part of the chart format, or references something that is not defined.
+    #[error("{location}: unknown {kind} `{name}`")]
+    UnknownName {
+        location: SourceLocation,
+        kind: &'static str,
+        name: String,
+    },
+
+    #[error("{location}: missing header field `{field}`")]
+    MissingHeaderField {
+        location: SourceLocation,
+        field: &'static str,
+    },
+
+    /// Measures start at 1.
+    #[error(
+        "{location}: measure {measure} does not exist, the chart has {num_measures} measure(s)"
+    )]
+    MeasureOutOfRange {
+        location: SourceLocation,
+        measure: usize,
+        num_measures: usize,
+    },
+
+    /// Measures start at 1.
+    #[error("{location}: beat {beat} is outside of measure {measure}")]
+    BeatOutOfRange {
+        location: SourceLocation,
+        measure: usize,
+        beat: f32,
+    },
+
+    #[error("{location}: dangling lane id {lane_id}")]
+    DanglingLaneId {
+        location: SourceLocation,
+        lane_id: String,
+    },
+
+    #[error("{location}: dangling bullet palette id {palette_id}")]
+    DanglingBulletPalette {
+        location: SourceLocation,
+        palette_id: String,
+    },
+
+    /// Bullet fired by the enemy while there is no enemy lane. Time is in seconds.
+    #[error("{location}: no enemy lane at {time}s to fire the bullet from")]
+    MissingEnemyLane { location: SourceLocation, time: f32 },
+
+    #[error("{location}: notes cannot be placed on lane type {lane_type}")]
+    UnsupportedNoteLane {
+        location: SourceLocation,
+        lane_type: String,
+    },
+
+    /// Error reported by the OGKR lexer, parser or analysis.
+    #[error("{location}: invalid OGKR chart: {message}")]
+    Ogkr {
+        location: SourceLocation,
+        message: String,
+    },
+
+    /// Chart content that cannot be written in the output format.
+    #[error("{location}: cannot write chart: {message}")]
+    Write {
+        location: SourceLocation,
+        message: String,
+    },
+}
+
+impl ChartError {
+    pub fn location(&self) -> &SourceLocation {
+        match self {
+            Self::Io { location, .. }
+            | Self::Encoding { location, .. }
+            | Self::UnknownFormat { location }
+            | Self::Syntax { location, .. }
+            | Self::InvalidValue { location, .. }
+            | Self::UnknownName { location, .. }
+            | Self::MissingHeaderField { location, .. }
+            | Self::MeasureOutOfRange { location, .. }
+            | Self::BeatOutOfRange { location, .. }
+            | Self::DanglingLaneId { location, .. }
+            | Self::DanglingBulletPalette { location, .. }
+            | Self::MissingEnemyLane { location, .. }
+            | Self::UnsupportedNoteLane { location, .. }
+            | Self::Ogkr { location, .. }
+            | Self::Write { location, .. } => location,
+        }
+    }
+
+    fn location_mut(&mut self) -> &mut SourceLocation {
+        match self {
+            Self::Io { location, .. }
+            | Self::Encoding { location, .. }
+            | Self::UnknownFormat { location }
+            | Self::Syntax { location, .. }
+            | Self::InvalidValue { location, .. }
+            | Self::UnknownName { location, .. }
+            | Self::MissingHeaderField { location, .. }
+            | Self::MeasureOutOfRange { location, .. }
+            | Self::BeatOutOfRange { location, .. }
+            | Self::DanglingLaneId { location, .. }
+            | Self::DanglingBulletPalette { location, .. }
+            | Self::MissingEnemyLane { location, .. }
+            | Self::UnsupportedNoteLane { location, .. }
+            | Self::Ogkr { location, .. }
+            | Self::Write { location, .. } => location,
+        }
+    }
+
+    /// Error of the chart writers, they work on in-memory charts and have no location.
+    pub(crate) fn write(message: impl Into<String>) -> Self {
+        Self::Write {
+            location: SourceLocation::default(),
+            message: message.into(),
+        }
+    }
+
+    /// Sets the file of the error location, importers working on in-memory sources do not know it.
+    /// Errors that already point at another file, eg. a file next to the chart, keep it.
+    pub(crate) fn with_file(mut self, file: &str) -> Self {
+        self.location_mut()
+            .file
+            .get_or_insert_with(|| file.to_string());
+        self
+    }
+}
+
+/// Writers format into a `String`, which does not fail.
+impl From<std::fmt::Error> for ChartError {
+    fn from(error: std::fmt::Error) -> Self {
+        Self::write(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations_show_what_is_known() {
+        assert_eq!(SourceLocation::default().to_string(), "<chart>");
+        assert_eq!(SourceLocation::new(3, 7).to_string(), "<chart>:3:7");
+        assert_eq!(SourceLocation::file("a.ecl").to_string(), "a.ecl");
+    }
+
+    #[test]
+    fn with_file_keeps_the_line() {
+        let error = ChartError::UnknownFormat {
+            location: SourceLocation::new(2, 1),
+        }
+        .with_file("a.ecl");
+        assert_eq!(error.location().to_string(), "a.ecl:2:1");
+    }
+}
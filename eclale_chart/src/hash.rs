@@ -0,0 +1,355 @@
+//! Content hash of chart data. `std::hash` hashers are not guaranteed to be stable across builds,
+//! so the data is fed to a 64 bit FNV-1a hash field by field.
+
+use crate::{
+    animation::{AnimationKind, AnimationLibrary, Vec3},
+    ChartData, Composition, ContactNoteType, EdgeSegment, EvadeNoteSize, EvadeNoteType,
+    FlickDirection, HitNoteType, LaneType, MovementTarget, Notes, PlatformEdge, Track,
+    TrackPosition,
+};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// Tags of enum variants are part of stored hashes. They are spelled out instead of using the
+// declaration order, so reordering or adding variants keeps existing hashes. Never reuse a tag.
+
+fn lane_type_tag(ty: LaneType) -> u8 {
+    match ty {
+        LaneType::WallLeft => 0,
+        LaneType::WallRight => 1,
+        LaneType::Left => 2,
+        LaneType::Center => 3,
+        LaneType::Right => 4,
+        LaneType::Enemy => 5,
+    }
+}
+
+fn hit_note_type_tag(ty: HitNoteType) -> u8 {
+    match ty {
+        HitNoteType::WallLeft => 0,
+        HitNoteType::WallRight => 1,
+        HitNoteType::Left => 2,
+        HitNoteType::Center => 3,
+        HitNoteType::Right => 4,
+    }
+}
+
+fn contact_note_type_tag(ty: ContactNoteType) -> u8 {
+    match ty {
+        ContactNoteType::Primary => 0,
+        ContactNoteType::Secondary => 1,
+    }
+}
+
+fn evade_note_type_tag(ty: EvadeNoteType) -> u8 {
+    match ty {
+        EvadeNoteType::Circle => 0,
+        EvadeNoteType::Needle => 1,
+        EvadeNoteType::Square => 2,
+    }
+}
+
+fn evade_note_size_tag(size: EvadeNoteSize) -> u8 {
+    match size {
+        EvadeNoteSize::Normal => 0,
+        EvadeNoteSize::Large => 1,
+    }
+}
+
+fn flick_direction_tag(direction: FlickDirection) -> u8 {
+    match direction {
+        FlickDirection::Left => 0,
+        FlickDirection::Right => 1,
+    }
+}
+
+struct ContentHasher(u64);
+
+impl ContentHasher {
+    fn bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    /// Enum variants and flags.
+    fn tag(&mut self, tag: u8) {
+        self.bytes(&[tag]);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    /// Lengths are hashed so that moving elements between lists changes the hash.
+    fn len(&mut self, len: usize) {
+        self.bytes(&(len as u64).to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        // -0.0 and 0.0 are the same position.
+        let value = if value == 0.0 { 0.0 } else { value };
+        self.u32(value.to_bits());
+    }
+
+    fn position(&mut self, position: &TrackPosition) {
+        self.f32(position.time.0);
+        self.f32(position.z);
+        self.f32(position.x);
+        self.f32(position.y);
+    }
+
+    fn vec3(&mut self, value: Vec3) {
+        self.f32(value.x);
+        self.f32(value.y);
+        self.f32(value.z);
+    }
+
+    fn positions(&mut self, positions: &[TrackPosition]) {
+        self.len(positions.len());
+        for position in positions {
+            self.position(position);
+        }
+    }
+
+    fn edge(&mut self, edge: &PlatformEdge) {
+        match edge {
+            PlatformEdge::Segments(segments) => {
+                self.tag(0);
+                self.len(segments.len());
+                for segment in segments {
+                    match segment {
+                        EdgeSegment::Straight { start, end } => {
+                            self.tag(0);
+                            self.position(start);
+                            self.position(end);
+                        }
+                        EdgeSegment::CubicBezier {
+                            start,
+                            control_points,
+                            end,
+                        } => {
+                            self.tag(1);
+                            self.position(start);
+                            self.position(&control_points.0);
+                            self.position(&control_points.1);
+                            self.position(end);
+                        }
+                    }
+                }
+            }
+            PlatformEdge::Mirror { x_offset } => {
+                self.tag(1);
+                self.f32(*x_offset);
+            }
+        }
+    }
+
+    /// Lane links are derived from the points and are not hashed.
+    fn track(&mut self, track: &Track) {
+        self.len(track.platforms.len());
+        for platform in track.platforms.iter() {
+            self.edge(&platform.left);
+            self.edge(&platform.right);
+        }
+
+        self.len(track.lanes.len());
+        for lane in track.lanes.iter() {
+            self.tag(lane_type_tag(lane.ty));
+            self.positions(&lane.points);
+        }
+    }
+
+    /// Notes are hashed in id order so reordering the `Notes` vectors keeps the hash, the ids
+    /// themselves are not hashed.
+    fn notes(&mut self, notes: &Notes) {
+        let mut hits = notes.hits.iter().collect::<Vec<_>>();
+        hits.sort_by_key(|n| n.id);
+        self.len(hits.len());
+        for note in hits {
+            self.tag(hit_note_type_tag(note.ty));
+            self.tag(u8::from(note.is_critical));
+            self.position(&note.position);
+        }
+
+        let mut holds = notes.holds.iter().collect::<Vec<_>>();
+        holds.sort_by_key(|n| n.id);
+        self.len(holds.len());
+        for note in holds {
+            self.tag(hit_note_type_tag(note.ty));
+            self.tag(u8::from(note.is_critical));
+            self.positions(&note.points);
+        }
+
+        let mut contacts = notes.contacts.iter().collect::<Vec<_>>();
+        contacts.sort_by_key(|n| n.id);
+        self.len(contacts.len());
+        for note in contacts {
+            self.tag(contact_note_type_tag(note.ty));
+            self.position(&note.position);
+        }
+
+        let mut evades = notes.evades.iter().collect::<Vec<_>>();
+        evades.sort_by_key(|n| n.id);
+        self.len(evades.len());
+        for note in evades {
+            self.tag(evade_note_type_tag(note.ty));
+            self.tag(evade_note_size_tag(note.size));
+            self.f32(note.speed);
+
+            let movement = &note.movement;
+            self.position(&movement.start);
+            self.position(&movement.end);
+            match movement.target {
+                MovementTarget::Fixed => self.tag(0),
+                MovementTarget::Player { x_offset } => {
+                    self.tag(1);
+                    self.f32(x_offset);
+                }
+            }
+            self.f32(movement.trigger_time.0);
+            self.f32(movement.duration);
+
+            self.len(note.animations.len());
+            for id in note.animations.iter() {
+                self.u32(id.0);
+            }
+        }
+
+        let mut flicks = notes.flicks.iter().collect::<Vec<_>>();
+        flicks.sort_by_key(|n| n.id);
+        self.len(flicks.len());
+        for note in flicks {
+            self.tag(flick_direction_tag(note.direction));
+            self.position(&note.position);
+            self.f32(note.end_x);
+        }
+
+        let mut targets = notes.targets.iter().collect::<Vec<_>>();
+        targets.sort_by_key(|n| n.id);
+        self.len(targets.len());
+        for note in targets {
+            self.position(&note.position);
+        }
+
+        let mut target_holds = notes.target_holds.iter().collect::<Vec<_>>();
+        target_holds.sort_by_key(|n| n.id);
+        self.len(target_holds.len());
+        for note in target_holds {
+            self.positions(&note.points);
+        }
+    }
+
+    /// Animations are hashed in declaration order as notes refer to them by index, names are not
+    /// hashed.
+    fn animations(&mut self, animations: &AnimationLibrary) {
+        self.len(animations.len());
+        for (_, animation) in animations.iter() {
+            match animation.kind {
+                AnimationKind::Translation { from, to } => {
+                    self.tag(0);
+                    self.vec3(from);
+                    self.vec3(to);
+                }
+                AnimationKind::Rotation { angles, center } => {
+                    self.tag(1);
+                    self.vec3(angles);
+                    self.vec3(center);
+                }
+                AnimationKind::Scale { from, to } => {
+                    self.tag(2);
+                    self.vec3(from);
+                    self.vec3(to);
+                }
+            }
+            self.f32(animation.duration);
+        }
+    }
+
+    fn composition(&mut self, composition: &Composition) {
+        self.len(composition.bpm_changes.len());
+        for change in composition.bpm_changes.iter() {
+            self.f32(change.time.0);
+            self.u32(change.bpm);
+        }
+
+        self.len(composition.time_signature_changes.len());
+        for change in composition.time_signature_changes.iter() {
+            self.f32(change.time.0);
+            self.u32(change.time_signature.num_beats);
+            self.u32(change.time_signature.note_value);
+        }
+
+        self.len(composition.soflans.len());
+        for soflan in composition.soflans.iter() {
+            self.f32(soflan.time.0);
+            self.f32(soflan.duration);
+            self.f32(soflan.speed_multiplier);
+        }
+    }
+}
+
+pub(crate) fn content_hash(data: &ChartData) -> u64 {
+    let mut hasher = ContentHasher(FNV_OFFSET_BASIS);
+    hasher.track(&data.track);
+    hasher.notes(&data.notes);
+    hasher.animations(&data.animations);
+    hasher.composition(&data.composition);
+    hasher.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ecl::create_chart_from_ecl_source;
+
+    const CHART: &str = "\
+<header>
+title=Hash
+default_tempo=120
+default_time_signature=4/4
+
+<chart_body>
+[B1] (1) |0|
+[C1] (2) |0.5|
+[HB2] (3;1,1) |0|
+--
+[E1] (1) |0|
+";
+
+    #[test]
+    fn hasher_is_fnv_1a() {
+        let mut hasher = ContentHasher(FNV_OFFSET_BASIS);
+        hasher.bytes(b"a");
+        assert_eq!(hasher.0, 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn hash_only_depends_on_chart_data() {
+        let chart = create_chart_from_ecl_source(CHART).unwrap();
+        let renamed =
+            create_chart_from_ecl_source(&CHART.replace("title=Hash", "title=Other")).unwrap();
+        assert_eq!(chart.content_hash(), renamed.content_hash());
+    }
+
+    #[test]
+    fn hash_is_pinned() {
+        // Scores and replays are keyed by this hash, it must only change on purpose.
+        let chart = create_chart_from_ecl_source(CHART).unwrap();
+        assert_eq!(chart.content_hash(), 0xc2fa_ae37_7f0d_df7c);
+    }
+
+    #[test]
+    fn hash_changes_with_note_data() {
+        let chart = create_chart_from_ecl_source(CHART).unwrap();
+
+        let mut moved = chart.clone();
+        moved.data.notes.hits[0].position.x = 0.25;
+        assert_ne!(chart.content_hash(), moved.content_hash());
+
+        let mut negative_zero = chart.clone();
+        negative_zero.data.notes.hits[0].position.x = -0.0;
+        assert_eq!(chart.content_hash(), negative_zero.content_hash());
+    }
+}
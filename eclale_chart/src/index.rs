@@ -0,0 +1,344 @@
+//! Time sorted index of the notes of a chart.
+
+use std::collections::HashMap;
+
+use crate::{HitNoteType, NoteId, NoteRef, Notes, Time, TrackPosition};
+
+/// Note of a `ChartIndex` with the time it is sorted by.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IndexedNote {
+    pub time: Time,
+    pub note: NoteRef,
+}
+
+/// Holds or target holds sorted by start time.
+#[derive(Clone, Debug, Default)]
+struct Spans {
+    starts: Vec<IndexedNote>,
+    /// End time of every span of `starts`.
+    ends: Vec<Time>,
+    /// Longest span in seconds, bounds the search for spans overlapping a range.
+    max_length: f32,
+}
+
+impl Spans {
+    /// `ranges` are the index, start and end of every span.
+    fn new(ranges: &[(usize, Time, Time)], note: fn(usize) -> NoteRef) -> Self {
+        let mut ranges = ranges.to_vec();
+        ranges.sort_by(|a, b| a.1 .0.total_cmp(&b.1 .0));
+        let (starts, ends) = ranges
+            .iter()
+            .map(|(i, start, end)| {
+                let start = IndexedNote {
+                    time: *start,
+                    note: note(*i),
+                };
+                (start, *end)
+            })
+            .unzip();
+
+        Self {
+            starts,
+            ends,
+            max_length: ranges
+                .iter()
+                .map(|(_, start, end)| end.0 - start.0)
+                .fold(0.0, f32::max),
+        }
+    }
+
+    fn overlapping(&self, start: Time, end: Time) -> impl Iterator<Item = &IndexedNote> {
+        let from = self
+            .starts
+            .partition_point(|n| n.time.0 < start.0 - self.max_length);
+        let to = self.starts.partition_point(|n| n.time.0 < end.0).max(from);
+        self.starts[from..to]
+            .iter()
+            .zip(&self.ends[from..to])
+            .filter(move |(_, span_end)| span_end.0 >= start.0)
+            .map(|(note, _)| note)
+    }
+}
+
+/// Points of a hold as its index, start and end.
+fn hold_range(index: usize, points: &[TrackPosition]) -> Option<(usize, Time, Time)> {
+    Some((index, points.first()?.time, points.last()?.time))
+}
+
+/// Notes sorted by time, the `Notes` vectors themselves are not required to be sorted. Queries are
+/// binary searches, the index has to be rebuilt when the notes change.
+///
+/// Holds and target holds are indexed by their start time and evade notes by the time their
+/// movement ends. Notes with the same time keep their order in `Notes`.
+#[derive(Clone, Debug, Default)]
+pub struct ChartIndex {
+    hits: Vec<IndexedNote>,
+    holds: Spans,
+    contacts: Vec<IndexedNote>,
+    evades: Vec<IndexedNote>,
+    flicks: Vec<IndexedNote>,
+    targets: Vec<IndexedNote>,
+    target_holds: Spans,
+
+    /// Hits and holds of every lane.
+    lanes: HashMap<HitNoteType, Vec<IndexedNote>>,
+
+    ids: HashMap<NoteId, NoteRef>,
+}
+
+fn sorted(times: impl Iterator<Item = (Time, NoteRef)>) -> Vec<IndexedNote> {
+    let mut notes = times
+        .map(|(time, note)| IndexedNote { time, note })
+        .collect::<Vec<_>>();
+    notes.sort_by(|a, b| a.time.0.total_cmp(&b.time.0));
+    notes
+}
+
+/// Notes of sorted `notes` with a time in [`start`, `end`).
+fn in_range(notes: &[IndexedNote], start: Time, end: Time) -> &[IndexedNote] {
+    let from = notes.partition_point(|n| n.time.0 < start.0);
+    let to = notes.partition_point(|n| n.time.0 < end.0).max(from);
+    &notes[from..to]
+}
+
+impl ChartIndex {
+    pub fn new(notes: &Notes) -> Self {
+        let hold_ranges = notes
+            .holds
+            .iter()
+            .enumerate()
+            .filter_map(|(i, n)| hold_range(i, &n.points))
+            .collect::<Vec<_>>();
+        let target_hold_ranges = notes
+            .target_holds
+            .iter()
+            .enumerate()
+            .filter_map(|(i, n)| hold_range(i, &n.points))
+            .collect::<Vec<_>>();
+
+        let mut lanes: HashMap<HitNoteType, Vec<IndexedNote>> = HashMap::new();
+        let lane_notes = notes
+            .hits
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.ty, n.position.time, NoteRef::Hit(i)))
+            .chain(
+                hold_ranges
+                    .iter()
+                    .map(|(i, start, _)| (notes.holds[*i].ty, *start, NoteRef::Hold(*i))),
+            );
+        for (ty, time, note) in lane_notes {
+            lanes
+                .entry(ty)
+                .or_default()
+                .push(IndexedNote { time, note });
+        }
+        for lane in lanes.values_mut() {
+            lane.sort_by(|a, b| a.time.0.total_cmp(&b.time.0));
+        }
+
+        Self {
+            hits: sorted(
+                notes
+                    .hits
+                    .iter()
+                    .enumerate()
+                    .map(|(i, n)| (n.position.time, NoteRef::Hit(i))),
+            ),
+            holds: Spans::new(&hold_ranges, NoteRef::Hold),
+            contacts: sorted(
+                notes
+                    .contacts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, n)| (n.position.time, NoteRef::Contact(i))),
+            ),
+            evades: sorted(
+                notes
+                    .evades
+                    .iter()
+                    .enumerate()
+                    .map(|(i, n)| (n.movement.end.time, NoteRef::Evade(i))),
+            ),
+            flicks: sorted(
+                notes
+                    .flicks
+                    .iter()
+                    .enumerate()
+                    .map(|(i, n)| (n.position.time, NoteRef::Flick(i))),
+            ),
+            targets: sorted(
+                notes
+                    .targets
+                    .iter()
+                    .enumerate()
+                    .map(|(i, n)| (n.position.time, NoteRef::Target(i))),
+            ),
+            target_holds: Spans::new(&target_hold_ranges, NoteRef::TargetHold),
+            lanes,
+            ids: notes.iter().map(|(note, n)| (n.id(), note)).collect(),
+        }
+    }
+
+    /// Note with id `id`, look the note up with `Notes::get`.
+    pub fn find(&self, id: NoteId) -> Option<NoteRef> {
+        self.ids.get(&id).copied()
+    }
+
+    /// Hit notes with a time in [`start`, `end`).
+    pub fn hits(&self, start: Time, end: Time) -> &[IndexedNote] {
+        in_range(&self.hits, start, end)
+    }
+
+    /// Hold notes starting in [`start`, `end`).
+    pub fn holds(&self, start: Time, end: Time) -> &[IndexedNote] {
+        in_range(&self.holds.starts, start, end)
+    }
+
+    pub fn contacts(&self, start: Time, end: Time) -> &[IndexedNote] {
+        in_range(&self.contacts, start, end)
+    }
+
+    /// Evade notes whose movement ends in [`start`, `end`).
+    pub fn evades(&self, start: Time, end: Time) -> &[IndexedNote] {
+        in_range(&self.evades, start, end)
+    }
+
+    pub fn flicks(&self, start: Time, end: Time) -> &[IndexedNote] {
+        in_range(&self.flicks, start, end)
+    }
+
+    pub fn targets(&self, start: Time, end: Time) -> &[IndexedNote] {
+        in_range(&self.targets, start, end)
+    }
+
+    /// Target holds starting in [`start`, `end`).
+    pub fn target_holds(&self, start: Time, end: Time) -> &[IndexedNote] {
+        in_range(&self.target_holds.starts, start, end)
+    }
+
+    /// Every note with a time in [`start`, `end`), grouped by kind and sorted by time within a
+    /// kind.
+    pub fn notes(&self, start: Time, end: Time) -> impl Iterator<Item = &IndexedNote> {
+        [
+            &self.hits,
+            &self.holds.starts,
+            &self.contacts,
+            &self.evades,
+            &self.flicks,
+            &self.targets,
+            &self.target_holds.starts,
+        ]
+        .into_iter()
+        .flat_map(move |notes| in_range(notes, start, end))
+    }
+
+    /// Hold notes that are active at some point in [`start`, `end`), including holds that started
+    /// before `start`.
+    pub fn holds_overlapping(&self, start: Time, end: Time) -> impl Iterator<Item = &IndexedNote> {
+        self.holds.overlapping(start, end)
+    }
+
+    /// Target holds that are active at some point in [`start`, `end`).
+    pub fn target_holds_overlapping(
+        &self,
+        start: Time,
+        end: Time,
+    ) -> impl Iterator<Item = &IndexedNote> {
+        self.target_holds.overlapping(start, end)
+    }
+
+    /// Hits and holds of lane `ty` sorted by time.
+    pub fn lane(&self, ty: HitNoteType) -> &[IndexedNote] {
+        self.lanes.get(&ty).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// First hit or hold of lane `ty` at or after `time` for which `is_judged` is false. Notes are
+    /// found by binary search, only judged notes after `time` are skipped one by one.
+    pub fn next_in_lane(
+        &self,
+        ty: HitNoteType,
+        time: Time,
+        is_judged: impl Fn(NoteRef) -> bool,
+    ) -> Option<IndexedNote> {
+        let lane = self.lane(ty);
+        let from = lane.partition_point(|n| n.time.0 < time.0);
+        lane[from..].iter().find(|n| !is_judged(n.note)).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ecl::create_chart_from_ecl_source;
+
+    const CHART: &str = "\
+<header>
+default_tempo=120
+default_time_signature=4/4
+
+<chart_body>
+[B1] (3) |0|
+[B2] (2) |0|
+[HB1] (2;1,1) |0|
+[B1] (1) |0|
+--
+[C1] (1) |0|
+";
+
+    fn times(notes: &[IndexedNote]) -> Vec<f32> {
+        notes.iter().map(|n| n.time.0).collect()
+    }
+
+    fn chart_notes() -> Notes {
+        create_chart_from_ecl_source(CHART).unwrap().data.notes
+    }
+
+    #[test]
+    fn ranges_are_half_open() {
+        let index = ChartIndex::new(&chart_notes());
+        assert_eq!(times(index.hits(Time(0.0), Time(1.0))), vec![0.0, 0.5]);
+        assert_eq!(times(index.hits(Time(0.5), Time(1.5))), vec![0.5, 1.0]);
+        assert!(index.hits(Time(1.5), Time(1.0)).is_empty());
+        assert_eq!(index.notes(Time(0.0), Time(10.0)).count(), 5);
+    }
+
+    #[test]
+    fn holds_overlap_ranges_after_their_start() {
+        let index = ChartIndex::new(&chart_notes());
+        assert!(index.holds(Time(1.5), Time(1.6)).is_empty());
+
+        let overlapping = index
+            .holds_overlapping(Time(1.5), Time(1.6))
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(times(&overlapping), vec![0.5]);
+        assert_eq!(index.holds_overlapping(Time(2.5), Time(3.0)).count(), 0);
+    }
+
+    #[test]
+    fn next_in_lane_skips_judged_notes() {
+        let index = ChartIndex::new(&chart_notes());
+        assert_eq!(times(index.lane(HitNoteType::Left)), vec![0.0, 0.5, 1.0]);
+
+        let next = index
+            .next_in_lane(HitNoteType::Left, Time(0.25), |n| {
+                matches!(n, NoteRef::Hold(_))
+            })
+            .unwrap();
+        assert_eq!(next.time, Time(1.0));
+        assert!(index
+            .next_in_lane(HitNoteType::Left, Time(1.5), |_| false)
+            .is_none());
+    }
+
+    #[test]
+    fn notes_are_found_by_id() {
+        let notes = chart_notes();
+        let index = ChartIndex::new(&notes);
+        for (note, n) in notes.iter() {
+            assert_eq!(index.find(n.id()), Some(note));
+        }
+        assert_eq!(index.find(NoteId(100)), None);
+    }
+}
@@ -1,9 +1,23 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+};
 
+use animation::{AnimationId, AnimationLibrary, Transform, Vec3};
 use util::ZPositionCalculator;
 
+pub use error::{ChartError, SourceLocation};
+
+pub mod animation;
+mod error;
+mod hash;
+pub mod index;
 pub mod parse;
+pub mod stats;
+pub mod timing;
 mod util;
+pub mod validate;
+pub mod write;
 
 /// Time in seconds.
 #[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
@@ -14,54 +28,500 @@ pub struct TrackPosition {
     pub time: Time,
     pub z: f32,
     pub x: f32,
+    /// Height above the platform, 0.0 for objects on the platform.
+    pub y: f32,
+}
+
+/// Number of lines bezier platform edges are flattened into.
+const PLATFORM_EDGE_SUBDIVISIONS: usize = 16;
+
+/// Times and x positions closer than this are treated as the same when sampling or connecting
+/// lanes.
+const POSITION_EPSILON: f32 = 1e-3;
+
+/// Part of a platform edge.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeSegment {
+    Straight {
+        start: TrackPosition,
+        end: TrackPosition,
+    },
+    CubicBezier {
+        start: TrackPosition,
+        control_points: (TrackPosition, TrackPosition),
+        end: TrackPosition,
+    },
+}
+
+impl EdgeSegment {
+    pub fn start(&self) -> TrackPosition {
+        match self {
+            Self::Straight { start, .. } | Self::CubicBezier { start, .. } => *start,
+        }
+    }
+
+    pub fn end(&self) -> TrackPosition {
+        match self {
+            Self::Straight { end, .. } | Self::CubicBezier { end, .. } => *end,
+        }
+    }
+
+    /// Points along the segment, bezier curves are flattened into `subdivisions` lines.
+    pub fn points(&self, subdivisions: usize) -> Vec<TrackPosition> {
+        match *self {
+            Self::Straight { start, end } => vec![start, end],
+            Self::CubicBezier {
+                start,
+                control_points,
+                end,
+            } => util::cubic_bezier_track_positions(start, control_points, end, subdivisions),
+        }
+    }
+
+    /// Position of the segment at `time`, clamped to its end points. Bezier curves are sampled
+    /// exactly, not through their flattened points.
+    pub fn sample_at(&self, time: Time) -> TrackPosition {
+        match *self {
+            Self::Straight { start, end } => util::track_position_at(&[start, end], time),
+            Self::CubicBezier {
+                start,
+                control_points,
+                end,
+            } => util::cubic_bezier_track_position_at(start, control_points, end, time),
+        }
+    }
+
+    fn offset_x(self, x_offset: f32) -> Self {
+        let offset = |p: TrackPosition| TrackPosition {
+            x: p.x + x_offset,
+            ..p
+        };
+        match self {
+            Self::Straight { start, end } => Self::Straight {
+                start: offset(start),
+                end: offset(end),
+            },
+            Self::CubicBezier {
+                start,
+                control_points,
+                end,
+            } => Self::CubicBezier {
+                start: offset(start),
+                control_points: (offset(control_points.0), offset(control_points.1)),
+                end: offset(end),
+            },
+        }
+    }
 }
 
-/// Contains points(positions) sorted by time.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlatformEdge {
+    /// Sorted by time, every segment starts where the previous one ends.
+    Segments(Vec<EdgeSegment>),
+    /// The other edge of the platform moved by `x_offset`, both edges are parallel.
+    Mirror { x_offset: f32 },
+}
+
+impl PlatformEdge {
+    /// Straight segments between consecutive points.
+    pub fn from_points(points: &[TrackPosition]) -> Self {
+        Self::Segments(
+            points
+                .windows(2)
+                .map(|p| EdgeSegment::Straight {
+                    start: p[0],
+                    end: p[1],
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Segment of the track the player can stand on, a track has a platform for every span of time its
+/// walls are continuous.
 #[derive(Clone, Debug)]
 pub struct Platform {
-    pub points_left: Vec<TrackPosition>,
-    pub points_right: Vec<TrackPosition>,
+    pub left: PlatformEdge,
+    pub right: PlatformEdge,
+}
+
+impl Platform {
+    /// Segments `edge` is made of and the x offset to apply to them, `None` if both edges are
+    /// mirrored.
+    fn edge_source<'a>(
+        edge: &'a PlatformEdge,
+        other: &'a PlatformEdge,
+    ) -> Option<(&'a [EdgeSegment], f32)> {
+        match (edge, other) {
+            (PlatformEdge::Segments(segments), _) => Some((segments, 0.0)),
+            (PlatformEdge::Mirror { x_offset }, PlatformEdge::Segments(segments)) => {
+                Some((segments, *x_offset))
+            }
+            (PlatformEdge::Mirror { .. }, PlatformEdge::Mirror { .. }) => None,
+        }
+    }
+
+    /// Segments of `edge` with mirrored edges resolved. Empty if both edges are mirrored.
+    fn resolve_segments(edge: &PlatformEdge, other: &PlatformEdge) -> Vec<EdgeSegment> {
+        Self::edge_source(edge, other)
+            .map(|(segments, x_offset)| segments.iter().map(|s| s.offset_x(x_offset)).collect())
+            .unwrap_or_default()
+    }
+
+    fn sample_edge(edge: &PlatformEdge, other: &PlatformEdge, time: Time) -> Option<TrackPosition> {
+        let (segments, x_offset) = Self::edge_source(edge, other)?;
+        let (first, last) = (segments.first()?, segments.last()?);
+        if time.0 < first.start().time.0 - POSITION_EPSILON
+            || time.0 > last.end().time.0 + POSITION_EPSILON
+        {
+            return None;
+        }
+
+        let index = segments
+            .partition_point(|s| s.end().time.0 < time.0)
+            .min(segments.len() - 1);
+        let position = segments[index].sample_at(time);
+        Some(TrackPosition {
+            x: position.x + x_offset,
+            ..position
+        })
+    }
+
+    fn edge_range(
+        edge: &PlatformEdge,
+        other: &PlatformEdge,
+        start: Time,
+        end: Time,
+    ) -> Vec<TrackPosition> {
+        let Some((segments, x_offset)) = Self::edge_source(edge, other) else {
+            return Vec::new();
+        };
+
+        // Only segments overlapping the range are flattened.
+        let from = segments.partition_point(|s| s.end().time.0 < start.0);
+        let to = segments
+            .partition_point(|s| s.start().time.0 <= end.0)
+            .max(from);
+        let segments = segments[from..to]
+            .iter()
+            .map(|s| s.offset_x(x_offset))
+            .collect::<Vec<_>>();
+        util::polyline_range(&Self::flatten(&segments), start, end)
+    }
+
+    fn flatten(segments: &[EdgeSegment]) -> Vec<TrackPosition> {
+        let mut points: Vec<TrackPosition> = Vec::new();
+        for segment in segments {
+            let segment_points = segment.points(PLATFORM_EDGE_SUBDIVISIONS);
+            // Segments share their end points.
+            let skip = usize::from(points.last() == segment_points.first());
+            points.extend(segment_points.into_iter().skip(skip));
+        }
+        points
+    }
+
+    pub fn left_segments(&self) -> Vec<EdgeSegment> {
+        Self::resolve_segments(&self.left, &self.right)
+    }
+
+    pub fn right_segments(&self) -> Vec<EdgeSegment> {
+        Self::resolve_segments(&self.right, &self.left)
+    }
+
+    /// Left edge as points sorted by time, bezier segments are flattened.
+    pub fn points_left(&self) -> Vec<TrackPosition> {
+        Self::flatten(&self.left_segments())
+    }
+
+    /// Right edge as points sorted by time, bezier segments are flattened.
+    pub fn points_right(&self) -> Vec<TrackPosition> {
+        Self::flatten(&self.right_segments())
+    }
+
+    /// Times covered by both edges, `None` if the edges do not overlap.
+    pub fn time_range(&self) -> Option<(Time, Time)> {
+        let (left_start, left_end) = util::time_range(&self.points_left())?;
+        let (right_start, right_end) = util::time_range(&self.points_right())?;
+        let start = Time(left_start.0.max(right_start.0));
+        let end = Time(left_end.0.min(right_end.0));
+        (start <= end).then_some((start, end))
+    }
+
+    /// Left and right edge at `time`, `None` if either edge does not exist at `time`.
+    pub fn sample_at(&self, time: Time) -> Option<(TrackPosition, TrackPosition)> {
+        Some((
+            Self::sample_edge(&self.left, &self.right, time)?,
+            Self::sample_edge(&self.right, &self.left, time)?,
+        ))
+    }
+
+    /// Left and right edge between `start` and `end`, with interpolated points at both ends. An
+    /// edge is empty if it does not overlap the range.
+    pub fn range(&self, start: Time, end: Time) -> (Vec<TrackPosition>, Vec<TrackPosition>) {
+        (
+            Self::edge_range(&self.left, &self.right, start, end),
+            Self::edge_range(&self.right, &self.left, start, end),
+        )
+    }
 }
 
-/// Generic unsigned integer that can be identified differently based on chart type.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
-pub struct LaneType(pub u16);
+pub enum LaneType {
+    WallLeft,
+    WallRight,
+    Left,
+    Center,
+    Right,
+    Enemy,
+}
 
+/// Index of a lane segment in its `Lanes`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct LaneId(pub usize);
+
+/// Continuous segment of a lane. Contains points(positions) sorted by time.
 #[derive(Clone, Debug)]
 pub struct Lane {
+    pub id: LaneId,
+    pub ty: LaneType,
+    /// Name the chart gives the segment, eg. the OGKR lane id. Not hashed with the chart.
+    pub name: String,
     pub points: Vec<TrackPosition>,
+    /// Segments that continue into this one, several if lanes merge into it.
+    pub predecessors: Vec<LaneId>,
+    /// Segments this one continues into, several if it branches.
+    pub successors: Vec<LaneId>,
+}
+
+impl Lane {
+    pub fn start_time(&self) -> Option<Time> {
+        self.points.first().map(|p| p.time)
+    }
+
+    pub fn end_time(&self) -> Option<Time> {
+        self.points.last().map(|p| p.time)
+    }
+
+    pub fn covers(&self, time: Time) -> bool {
+        util::time_range(&self.points).is_some_and(|(start, end)| {
+            start.0 - POSITION_EPSILON <= time.0 && time.0 <= end.0 + POSITION_EPSILON
+        })
+    }
+
+    /// Position of the lane at `time`, `None` if the lane does not exist at `time`.
+    pub fn sample_at(&self, time: Time) -> Option<TrackPosition> {
+        util::sample_polyline(&self.points, time, POSITION_EPSILON)
+    }
+
+    /// x of the lane at `time`, `None` if the lane does not exist at `time`.
+    pub fn x_at(&self, time: Time) -> Option<f32> {
+        self.sample_at(time).map(|p| p.x)
+    }
+
+    /// Points between `start` and `end`, with interpolated points at both ends. Empty if the lane
+    /// does not overlap the range.
+    pub fn range(&self, start: Time, end: Time) -> Vec<TrackPosition> {
+        util::polyline_range(&self.points, start, end)
+    }
+
+    /// Whether `position` is on the lane.
+    fn contains(&self, position: &TrackPosition) -> bool {
+        self.x_at(position.time)
+            .is_some_and(|x| (x - position.x).abs() < POSITION_EPSILON)
+    }
+}
+
+/// Graph of lane segments. A segment is linked to another when it starts on it (continuation or
+/// branch) or ends on it (merge).
+#[derive(Clone, Debug, Default)]
+pub struct Lanes {
+    /// Indexed by `LaneId`.
+    lanes: Vec<Lane>,
+}
+
+impl Lanes {
+    /// Adds a segment, links are only updated by `connect`.
+    pub fn add(
+        &mut self,
+        ty: LaneType,
+        name: impl Into<String>,
+        points: Vec<TrackPosition>,
+    ) -> LaneId {
+        let id = LaneId(self.lanes.len());
+        self.lanes.push(Lane {
+            id,
+            ty,
+            name: name.into(),
+            points,
+            predecessors: Vec::new(),
+            successors: Vec::new(),
+        });
+        id
+    }
+
+    /// Links every segment to the segments of the same type it starts or ends on.
+    ///
+    /// Sweeps over the start and end points of all segments sorted by time. Only the segments
+    /// that exist at a point are checked, so segments are not compared with every other one.
+    pub fn connect(&mut self) {
+        for lane in self.lanes.iter_mut() {
+            lane.predecessors.clear();
+            lane.successors.clear();
+        }
+
+        // Starts come before ends at the same time, so segments that continue one another are
+        // both active.
+        let mut endpoints = self
+            .lanes
+            .iter()
+            .filter_map(|lane| {
+                let (start, end) = util::time_range(&lane.points)?;
+                Some([(start, false, lane), (end, true, lane)])
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+        endpoints.sort_by(|a, b| a.0 .0.total_cmp(&b.0 .0).then(a.1.cmp(&b.1)));
+
+        let mut links = BTreeSet::new();
+        let mut active: Vec<&Lane> = Vec::new();
+        for (time, is_end, lane) in endpoints {
+            // Segments stay active within the epsilon after their end.
+            active.retain(|l| {
+                l.end_time()
+                    .is_some_and(|end| end.0 + POSITION_EPSILON >= time.0)
+            });
+            let others = active.iter().filter(|l| l.id != lane.id && l.ty == lane.ty);
+
+            if is_end {
+                // `lane` merges into a segment that goes on after it.
+                let end = lane.points.last().unwrap();
+                for to in others {
+                    if to.end_time().unwrap().0 > time.0 + POSITION_EPSILON && to.contains(end) {
+                        links.insert((lane.id, to.id));
+                    }
+                }
+            } else {
+                // `lane` continues a segment or branches off it.
+                let start = lane.points.first().unwrap();
+                for from in others {
+                    if from.start_time().unwrap().0 < time.0 - POSITION_EPSILON
+                        && from.contains(start)
+                    {
+                        links.insert((from.id, lane.id));
+                    }
+                }
+                active.push(lane);
+            }
+        }
+
+        for (from, to) in links {
+            self.lanes[from.0].successors.push(to);
+            self.lanes[to.0].predecessors.push(from);
+        }
+    }
+
+    pub fn get(&self, id: LaneId) -> Option<&Lane> {
+        self.lanes.get(id.0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Lane> {
+        self.lanes.iter()
+    }
+
+    pub fn of_type(&self, ty: LaneType) -> impl Iterator<Item = &Lane> {
+        self.lanes.iter().filter(move |l| l.ty == ty)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lanes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lanes.is_empty()
+    }
+
+    /// Segments that exist at `time`.
+    pub fn at(&self, time: Time) -> impl Iterator<Item = &Lane> {
+        self.lanes.iter().filter(move |l| l.covers(time))
+    }
+
+    /// Segment closest to `x` at `time` that is at most `max_distance` away.
+    pub fn nearest(&self, x: f32, time: Time, max_distance: f32) -> Option<&Lane> {
+        self.lanes
+            .iter()
+            .filter_map(|l| Some((l, (l.x_at(time)? - x).abs())))
+            .filter(|(_, distance)| *distance <= max_distance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(l, _)| l)
+    }
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct Track {
     pub platforms: Vec<Platform>,
-    // XXX TODO: Have dedicated type Lanes and Lane.
-    pub lanes: HashMap<LaneType, Vec<Lane>>,
+    pub lanes: Lanes,
 }
 
-/// Generic unsigned integer that can be identified differently based on chart type.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub struct HitNoteType(pub u16);
+/// Identifier of a note that stays the same when the `Notes` vectors are reordered. Ids are unique
+/// within a chart and assigned at import by `Notes::assign_ids`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct NoteId(pub u32);
+
+/// Hit notes are color coded after the lane they belong to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum HitNoteType {
+    WallLeft,
+    WallRight,
+    Left,
+    Center,
+    Right,
+}
 
 #[derive(Clone, Debug)]
 pub struct HitNote {
+    pub id: NoteId,
     pub ty: HitNoteType,
+    pub is_critical: bool,
     pub position: TrackPosition,
 }
 
-/// Generic unsigned integer that can be identified differently based on chart type.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub struct ContactNoteType(pub u16);
+/// Color coded contact note. OGKR bells are always primary.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ContactNoteType {
+    Primary,
+    Secondary,
+}
 
 #[derive(Clone, Debug)]
 pub struct ContactNote {
+    pub id: NoteId,
     pub ty: ContactNoteType,
     pub position: TrackPosition,
 }
 
-/// Generic unsigned integer that can be identified differently based on chart type.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub struct EvadeNoteType(pub u16);
+/// Bullet kind.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum EvadeNoteType {
+    Circle,
+    Needle,
+    Square,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum EvadeNoteSize {
+    Normal,
+    Large,
+}
+
+/// Where a movement ends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MovementTarget {
+    /// Ends at `end`.
+    Fixed,
+    /// Ends at the player's x when the movement is triggered, moved by `x_offset`. Only the time,
+    /// z and y of `end` are used.
+    Player { x_offset: f32 },
+}
 
 /// Linear translation.
 #[derive(Clone, Debug)]
@@ -69,10 +529,11 @@ pub struct NoteMovement {
     /// Initial position of the object.
     pub start: TrackPosition,
 
-    /// Final position of the object.
-    /// XXX TODO: Support current player position as end position.
+    /// Final position of the object, see `target`.
     pub end: TrackPosition,
 
+    pub target: MovementTarget,
+
     /// Time when movement begins.
     pub trigger_time: Time,
 
@@ -82,15 +543,66 @@ pub struct NoteMovement {
 
 impl NoteMovement {
     pub fn is_static(&self) -> bool {
-        self.start == self.end
+        self.target == MovementTarget::Fixed && self.start == self.end
+    }
+
+    /// Final position with the target resolved against the player's x at trigger time.
+    pub fn resolve_end(&self, player_x: f32) -> TrackPosition {
+        match self.target {
+            MovementTarget::Fixed => self.end,
+            MovementTarget::Player { x_offset } => TrackPosition {
+                x: player_x + x_offset,
+                ..self.end
+            },
+        }
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct EvadeNote {
+    pub id: NoteId,
     pub ty: EvadeNoteType,
+    pub size: EvadeNoteSize,
+    /// Speed multiplier of the bullet, 1.0 is the normal speed.
+    pub speed: f32,
     pub movement: NoteMovement,
+    /// Played in order, the last animation ends at the hit time. `movement` spans the whole stack
+    /// when it is not empty.
+    pub animations: Vec<AnimationId>,
 }
+
+impl EvadeNote {
+    /// Pose at the hit time for the resolved end of the movement.
+    pub fn end_pose(&self, end: &TrackPosition) -> Transform {
+        Transform::translation(Vec3::new(end.x, end.y, end.z))
+    }
+
+    /// Pose at `time` with `end` as the final position, usually `movement.resolve_end` with the
+    /// player's x at trigger time. Notes without animations move linearly from the start to `end`.
+    pub fn pose_at(
+        &self,
+        animations: &AnimationLibrary,
+        end: &TrackPosition,
+        time: Time,
+    ) -> Transform {
+        if !self.animations.is_empty() {
+            return animations.pose_at(&self.animations, self.end_pose(end), end.time, time);
+        }
+
+        let movement = &self.movement;
+        let progress = if movement.duration > 0.0 {
+            ((time.0 - movement.trigger_time.0) / movement.duration).clamp(0.0, 1.0)
+        } else if time.0 < movement.trigger_time.0 {
+            0.0
+        } else {
+            1.0
+        };
+        let start = Vec3::new(movement.start.x, movement.start.y, movement.start.z);
+        let end = Vec3::new(end.x, end.y, end.z);
+        Transform::translation(start.lerp(end, progress))
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum FlickDirection {
     Left,
@@ -99,16 +611,74 @@ pub enum FlickDirection {
 
 #[derive(Clone, Debug)]
 pub struct FlickNote {
+    pub id: NoteId,
     pub direction: FlickDirection,
+    /// Where the flick starts.
     pub position: TrackPosition,
+    /// x the flick ends at.
+    pub end_x: f32,
 }
 
 #[derive(Clone, Debug)]
 pub struct HoldNote {
+    pub id: NoteId,
     pub ty: HitNoteType,
+    pub is_critical: bool,
+    pub points: Vec<TrackPosition>,
+}
+
+impl HoldNote {
+    /// Position of the hold at `time`, `None` before it starts or after it ends.
+    pub fn sample_at(&self, time: Time) -> Option<TrackPosition> {
+        util::sample_polyline(&self.points, time, POSITION_EPSILON)
+    }
+
+    /// Points between `start` and `end`, with interpolated points at both ends. Empty if the hold
+    /// does not overlap the range.
+    pub fn range(&self, start: Time, end: Time) -> Vec<TrackPosition> {
+        util::polyline_range(&self.points, start, end)
+    }
+}
+
+/// Note the player has to both collide with and hit. Targets do not belong to a lane.
+#[derive(Clone, Debug)]
+pub struct TargetNote {
+    pub id: NoteId,
+    pub position: TrackPosition,
+}
+
+/// Hold the player has to both follow and keep hitting.
+#[derive(Clone, Debug)]
+pub struct TargetHoldNote {
+    pub id: NoteId,
     pub points: Vec<TrackPosition>,
 }
 
+impl TargetHoldNote {
+    /// Position of the hold at `time`, `None` before it starts or after it ends.
+    pub fn sample_at(&self, time: Time) -> Option<TrackPosition> {
+        util::sample_polyline(&self.points, time, POSITION_EPSILON)
+    }
+
+    /// Points between `start` and `end`, with interpolated points at both ends. Empty if the hold
+    /// does not overlap the range.
+    pub fn range(&self, start: Time, end: Time) -> Vec<TrackPosition> {
+        util::polyline_range(&self.points, start, end)
+    }
+}
+
+/// Note referenced by its index in its `Notes` vector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NoteRef {
+    Hit(usize),
+    Hold(usize),
+    Contact(usize),
+    Evade(usize),
+    Flick(usize),
+    Target(usize),
+    TargetHold(usize),
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct Notes {
     pub hits: Vec<HitNote>,
@@ -116,6 +686,110 @@ pub struct Notes {
     pub contacts: Vec<ContactNote>,
     pub evades: Vec<EvadeNote>,
     pub flicks: Vec<FlickNote>,
+    pub targets: Vec<TargetNote>,
+    pub target_holds: Vec<TargetHoldNote>,
+}
+
+/// Note of any kind borrowed from `Notes`.
+#[derive(Clone, Copy, Debug)]
+pub enum Note<'a> {
+    Hit(&'a HitNote),
+    Hold(&'a HoldNote),
+    Contact(&'a ContactNote),
+    Evade(&'a EvadeNote),
+    Flick(&'a FlickNote),
+    Target(&'a TargetNote),
+    TargetHold(&'a TargetHoldNote),
+}
+
+impl Note<'_> {
+    pub fn id(&self) -> NoteId {
+        match self {
+            Self::Hit(n) => n.id,
+            Self::Hold(n) => n.id,
+            Self::Contact(n) => n.id,
+            Self::Evade(n) => n.id,
+            Self::Flick(n) => n.id,
+            Self::Target(n) => n.id,
+            Self::TargetHold(n) => n.id,
+        }
+    }
+
+    /// Time the note is played at. Holds and target holds start at their first point and evade
+    /// notes are played when their movement ends.
+    pub fn time(&self) -> Option<Time> {
+        match self {
+            Self::Hit(n) => Some(n.position.time),
+            Self::Hold(n) => n.points.first().map(|p| p.time),
+            Self::Contact(n) => Some(n.position.time),
+            Self::Evade(n) => Some(n.movement.end.time),
+            Self::Flick(n) => Some(n.position.time),
+            Self::Target(n) => Some(n.position.time),
+            Self::TargetHold(n) => n.points.first().map(|p| p.time),
+        }
+    }
+}
+
+impl Notes {
+    pub fn get(&self, note: NoteRef) -> Option<Note<'_>> {
+        match note {
+            NoteRef::Hit(i) => self.hits.get(i).map(Note::Hit),
+            NoteRef::Hold(i) => self.holds.get(i).map(Note::Hold),
+            NoteRef::Contact(i) => self.contacts.get(i).map(Note::Contact),
+            NoteRef::Evade(i) => self.evades.get(i).map(Note::Evade),
+            NoteRef::Flick(i) => self.flicks.get(i).map(Note::Flick),
+            NoteRef::Target(i) => self.targets.get(i).map(Note::Target),
+            NoteRef::TargetHold(i) => self.target_holds.get(i).map(Note::TargetHold),
+        }
+    }
+
+    /// Every note with its reference, grouped by kind.
+    pub fn iter(&self) -> impl Iterator<Item = (NoteRef, Note<'_>)> {
+        let hits = self.hits.iter().enumerate();
+        let holds = self.holds.iter().enumerate();
+        let contacts = self.contacts.iter().enumerate();
+        let evades = self.evades.iter().enumerate();
+        let flicks = self.flicks.iter().enumerate();
+        let targets = self.targets.iter().enumerate();
+        let target_holds = self.target_holds.iter().enumerate();
+        hits.map(|(i, n)| (NoteRef::Hit(i), Note::Hit(n)))
+            .chain(holds.map(|(i, n)| (NoteRef::Hold(i), Note::Hold(n))))
+            .chain(contacts.map(|(i, n)| (NoteRef::Contact(i), Note::Contact(n))))
+            .chain(evades.map(|(i, n)| (NoteRef::Evade(i), Note::Evade(n))))
+            .chain(flicks.map(|(i, n)| (NoteRef::Flick(i), Note::Flick(n))))
+            .chain(targets.map(|(i, n)| (NoteRef::Target(i), Note::Target(n))))
+            .chain(target_holds.map(|(i, n)| (NoteRef::TargetHold(i), Note::TargetHold(n))))
+    }
+
+    /// Finds a note by its id with a linear scan, use `index::ChartIndex` for repeated lookups.
+    pub fn find(&self, id: NoteId) -> Option<NoteRef> {
+        self.iter()
+            .find(|(_, n)| n.id() == id)
+            .map(|(note, _)| note)
+    }
+
+    /// Numbers every note by time from 0, notes with the same time are numbered by kind and then
+    /// by index. Importers call this once, ids must not be reassigned after notes are referenced.
+    pub fn assign_ids(&mut self) {
+        let mut notes = self
+            .iter()
+            .map(|(note, n)| (note, n.time().map_or(f32::MAX, |t| t.0)))
+            .collect::<Vec<_>>();
+        notes.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        for (id, (note, _)) in notes.into_iter().enumerate() {
+            let id = NoteId(id as u32);
+            match note {
+                NoteRef::Hit(i) => self.hits[i].id = id,
+                NoteRef::Hold(i) => self.holds[i].id = id,
+                NoteRef::Contact(i) => self.contacts[i].id = id,
+                NoteRef::Evade(i) => self.evades[i].id = id,
+                NoteRef::Flick(i) => self.flicks[i].id = id,
+                NoteRef::Target(i) => self.targets[i].id = id,
+                NoteRef::TargetHold(i) => self.target_holds[i].id = id,
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -124,7 +798,7 @@ pub struct BpmChange {
     pub bpm: u32,
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct TimeSignature {
     /// Enumerator.
     pub num_beats: u32,
@@ -138,9 +812,11 @@ pub struct TimeSignatureChange {
     pub time_signature: TimeSignature,
 }
 
+/// Scroll speed change. Scroll speed goes back to 1.0 once the soflan ends.
 #[derive(Clone, Debug)]
 pub struct Soflan {
     pub time: Time,
+    /// Duration in seconds.
     pub duration: f32,
     pub speed_multiplier: f32,
 }
@@ -157,23 +833,125 @@ pub struct Composition {
 pub struct ChartData {
     pub track: Track,
     pub notes: Notes,
+    pub animations: AnimationLibrary,
     pub composition: Composition,
 }
 
+/// Tempo range shown to the player, it is not used for timing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BpmRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+/// Chart information shown to the player. Strings are empty when the chart does not set them.
 #[derive(Clone, Debug, Default)]
 pub struct Header {
+    pub title: String,
+    pub artist: String,
+    pub chart_designer: String,
+
+    /// Name of the difficulty, eg. `Master`.
+    pub difficulty: String,
+    /// Level constant, eg. `13.7`.
+    pub level: Option<f32>,
+
+    pub bpm_range: Option<BpmRange>,
+
+    /// Start of the song preview in seconds since the start of the audio.
+    pub preview_start: f32,
+    /// Length of the song preview in seconds, there is no preview if this is 0.
+    pub preview_length: f32,
+
     /// Path of audio file.
     pub audio_filename: String,
 
-    /// Offset to start of audio in seconds.
+    /// Time of measure 0 in seconds since the start of the audio. The timing map already places
+    /// measure 0 here, chart times must not be shifted by it again.
     pub audio_offset: f32,
 }
 
+/// Color with components from 0.0 to 1.0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl Color {
+    /// Color from `0xRRGGBB`.
+    pub fn from_rgb_hex(rgb: u32) -> Self {
+        let component = |shift: u32| ((rgb >> shift) & 0xFF) as f32 / 255.0;
+        Self {
+            r: component(16),
+            g: component(8),
+            b: component(0),
+        }
+    }
+
+    /// Color as `0xRRGGBB`, components are clamped.
+    pub fn to_rgb_hex(&self) -> u32 {
+        let component = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u32;
+        (component(self.r) << 16) | (component(self.g) << 8) | component(self.b)
+    }
+}
+
+/// Note kinds whose appearance a chart can customize.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum NoteStyleKey {
+    /// Hits and holds.
+    Hit(HitNoteType),
+    /// Targets and target holds.
+    Target,
+    Flick,
+    /// Bullets of a kind and size.
+    Evade(EvadeNoteType, EvadeNoteSize),
+    Contact(ContactNoteType),
+}
+
+/// Appearance overrides of a note kind, `None` keeps the game's default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NoteStyle {
+    pub color: Option<Color>,
+}
+
+/// Appearance overrides set by the chart. Kinds without a style use the game's default palette.
+#[derive(Clone, Debug, Default)]
+pub struct NoteStyles {
+    styles: HashMap<NoteStyleKey, NoteStyle>,
+}
+
+impl NoteStyles {
+    pub fn get(&self, key: NoteStyleKey) -> Option<&NoteStyle> {
+        self.styles.get(&key)
+    }
+
+    pub fn get_mut(&mut self, key: NoteStyleKey) -> &mut NoteStyle {
+        self.styles.entry(key).or_default()
+    }
+
+    pub fn color(&self, key: NoteStyleKey) -> Option<Color> {
+        self.get(key).and_then(|s| s.color)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (NoteStyleKey, &NoteStyle)> {
+        self.styles.iter().map(|(key, style)| (*key, style))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.styles.is_empty()
+    }
+}
+
 /// Internal chart metadata, not set by chart file.
 #[derive(Clone, Debug, Default)]
 pub struct Metadata {
     /// Base speed multiplier used to calculate "time".
     pub base_speed: f32,
+
+    /// Audio file resolved against the directory of the chart file, set by `parse::load_chart`.
+    pub audio_path: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug)]
@@ -185,6 +963,325 @@ pub struct ChartUtils {
 pub struct Chart {
     pub header: Header,
     pub metadata: Metadata,
+    pub note_styles: NoteStyles,
     pub data: ChartData,
     pub utils: ChartUtils,
 }
+
+impl Chart {
+    /// Hash of the gameplay data, used to key scores and replays. The header, metadata and note
+    /// styles are not included so renaming a chart or moving its audio keeps its scores. Stable
+    /// across platforms and builds.
+    pub fn content_hash(&self) -> u64 {
+        hash::content_hash(&self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(time: f32, z: f32, x: f32) -> TrackPosition {
+        TrackPosition {
+            time: Time(time),
+            z,
+            x,
+            y: 0.0,
+        }
+    }
+
+    fn player_targeted_evade() -> EvadeNote {
+        EvadeNote {
+            id: NoteId(0),
+            ty: EvadeNoteType::Circle,
+            size: EvadeNoteSize::Normal,
+            speed: 1.0,
+            movement: NoteMovement {
+                start: position(1.0, 4.0, 0.0),
+                end: position(2.0, 2.0, 0.0),
+                target: MovementTarget::Player { x_offset: 0.5 },
+                trigger_time: Time(1.0),
+                duration: 1.0,
+            },
+            animations: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn player_target_resolves_x_only() {
+        let movement = player_targeted_evade().movement;
+        assert_eq!(movement.resolve_end(-1.0), position(2.0, 2.0, -0.5));
+        assert!(!movement.is_static());
+    }
+
+    #[test]
+    fn evade_moves_towards_resolved_end() {
+        let note = player_targeted_evade();
+        let animations = AnimationLibrary::default();
+        let end = note.movement.resolve_end(1.5);
+
+        let pose_at = |time| note.pose_at(&animations, &end, Time(time)).position();
+        assert_eq!(pose_at(0.0), Vec3::new(0.0, 0.0, 4.0));
+        assert_eq!(pose_at(1.5), Vec3::new(1.0, 0.0, 3.0));
+        assert_eq!(pose_at(3.0), Vec3::new(2.0, 0.0, 2.0));
+    }
+
+    fn straight(start: TrackPosition, end: TrackPosition) -> EdgeSegment {
+        EdgeSegment::Straight { start, end }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn straight_segments_are_sampled_linearly() {
+        let segment = straight(position(0.0, 0.0, 0.0), position(2.0, 2.0, 4.0));
+        assert_eq!(segment.sample_at(Time(1.0)), position(1.0, 1.0, 2.0));
+        assert_eq!(segment.sample_at(Time(0.0)), segment.start());
+        assert_eq!(segment.sample_at(Time(2.0)), segment.end());
+        // Clamped to the end points.
+        assert_eq!(segment.sample_at(Time(3.0)).x, 4.0);
+        assert_eq!(segment.points(8), vec![segment.start(), segment.end()]);
+    }
+
+    #[test]
+    fn cubic_bezier_segments_are_sampled_on_the_curve() {
+        let segment = EdgeSegment::CubicBezier {
+            start: position(0.0, 0.0, 0.0),
+            control_points: (position(1.0, 1.0, 0.0), position(2.0, 2.0, 3.0)),
+            end: position(3.0, 3.0, 3.0),
+        };
+
+        // Time and z are linear in the curve parameter, x follows the curve.
+        let middle = segment.sample_at(Time(1.5));
+        assert_eq!(middle.time, Time(1.5));
+        assert_close(middle.z, 1.5);
+        assert_close(middle.x, 1.5);
+        assert_close(segment.sample_at(Time(0.75)).x, 0.46875);
+        assert_close(segment.sample_at(Time(0.0)).x, 0.0);
+        assert_close(segment.sample_at(Time(3.0)).x, 3.0);
+
+        let points = segment.points(4);
+        assert_eq!(points.len(), 5);
+        assert_eq!(points.first(), Some(&segment.start()));
+        assert_eq!(points.last(), Some(&segment.end()));
+    }
+
+    #[test]
+    fn mirrored_edges_follow_the_other_edge() {
+        let left = PlatformEdge::from_points(&[position(0.0, 0.0, -1.0), position(2.0, 2.0, 1.0)]);
+        let platform = Platform {
+            left: left.clone(),
+            right: PlatformEdge::Mirror { x_offset: 2.0 },
+        };
+
+        let (l, r) = platform.sample_at(Time(1.0)).unwrap();
+        assert_eq!(l, position(1.0, 1.0, 0.0));
+        assert_eq!(r, position(1.0, 1.0, 2.0));
+        assert_eq!(
+            platform.right_segments()[0].start(),
+            position(0.0, 0.0, 1.0)
+        );
+        assert_eq!(platform.time_range(), Some((Time(0.0), Time(2.0))));
+
+        let mirrored = Platform {
+            left: PlatformEdge::Mirror { x_offset: -2.0 },
+            right: PlatformEdge::Mirror { x_offset: 2.0 },
+        };
+        assert!(mirrored.sample_at(Time(1.0)).is_none());
+        assert!(mirrored.left_segments().is_empty());
+        assert_eq!(mirrored.time_range(), None);
+    }
+
+    #[test]
+    fn platforms_are_sampled_across_segment_boundaries() {
+        let edge = |x_offset: f32| {
+            PlatformEdge::Segments(vec![
+                straight(
+                    position(0.0, 0.0, x_offset),
+                    position(1.0, 1.0, x_offset + 1.0),
+                ),
+                straight(
+                    position(1.0, 1.0, x_offset + 1.0),
+                    position(2.0, 2.0, x_offset + 3.0),
+                ),
+            ])
+        };
+        let platform = Platform {
+            left: edge(0.0),
+            right: edge(2.0),
+        };
+        let left_x = |time| platform.sample_at(Time(time)).map(|(left, _)| left.x);
+
+        assert_eq!(left_x(0.0), Some(0.0));
+        assert_eq!(left_x(1.0), Some(1.0));
+        assert_close(left_x(0.9).unwrap(), 0.9);
+        assert_close(left_x(1.1).unwrap(), 1.2);
+        assert_eq!(left_x(2.0), Some(3.0));
+        // Within the epsilon of the ends, clamped to them.
+        assert_eq!(left_x(2.0 + POSITION_EPSILON / 2.0), Some(3.0));
+        assert_eq!(left_x(-0.1), None);
+        assert_eq!(left_x(2.1), None);
+
+        let (left, _) = platform.range(Time(0.5), Time(1.5));
+        assert_eq!(left.first().map(|p| p.x), Some(0.5));
+        assert_eq!(left.last().map(|p| p.x), Some(2.0));
+    }
+
+    /// Lane with a point every second from `start`, at the given x positions.
+    fn lane_points(start: f32, xs: &[f32]) -> Vec<TrackPosition> {
+        xs.iter()
+            .enumerate()
+            .map(|(i, &x)| position(start + i as f32, start + i as f32, x))
+            .collect()
+    }
+
+    fn links(lanes: &Lanes, name: &str) -> (Vec<String>, Vec<String>) {
+        let lane = lanes.iter().find(|l| l.name == name).unwrap();
+        let names = |ids: &[LaneId]| {
+            ids.iter()
+                .map(|id| lanes.get(*id).unwrap().name.clone())
+                .collect::<Vec<_>>()
+        };
+        (names(&lane.predecessors), names(&lane.successors))
+    }
+
+    #[test]
+    fn lanes_continue_and_branch() {
+        let mut lanes = Lanes::default();
+        lanes.add(LaneType::Left, "trunk", lane_points(0.0, &[0.0, 0.0, 0.0]));
+        lanes.add(LaneType::Left, "next", lane_points(2.0, &[0.0, 1.0]));
+        lanes.add(LaneType::Left, "branch", lane_points(1.0, &[0.0, -1.0]));
+        // Starts on the trunk, but is another type.
+        lanes.add(LaneType::Right, "other", lane_points(1.0, &[0.0, 1.0]));
+        lanes.connect();
+
+        let strings = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            links(&lanes, "trunk"),
+            (vec![], strings(&["next", "branch"]))
+        );
+        assert_eq!(links(&lanes, "next"), (strings(&["trunk"]), vec![]));
+        assert_eq!(links(&lanes, "branch"), (strings(&["trunk"]), vec![]));
+        assert_eq!(links(&lanes, "other"), (vec![], vec![]));
+    }
+
+    #[test]
+    fn lanes_merge() {
+        let mut lanes = Lanes::default();
+        lanes.add(
+            LaneType::Center,
+            "main",
+            lane_points(0.0, &[1.0, 1.0, 1.0, 1.0]),
+        );
+        lanes.add(LaneType::Center, "side", lane_points(0.0, &[3.0, 2.0, 1.0]));
+        // Ends next to the main lane.
+        lanes.add(LaneType::Center, "apart", lane_points(0.0, &[-1.0, 0.5]));
+        lanes.connect();
+
+        let strings = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(links(&lanes, "side"), (vec![], strings(&["main"])));
+        assert_eq!(links(&lanes, "main"), (strings(&["side"]), vec![]));
+        assert_eq!(links(&lanes, "apart"), (vec![], vec![]));
+
+        // Connecting again does not duplicate links.
+        lanes.connect();
+        assert_eq!(links(&lanes, "main"), (strings(&["side"]), vec![]));
+    }
+
+    #[test]
+    fn nearest_lane_is_within_distance_at_time() {
+        let mut lanes = Lanes::default();
+        lanes.add(LaneType::Left, "left", lane_points(0.0, &[-1.0, -1.0]));
+        lanes.add(LaneType::Right, "right", lane_points(0.0, &[1.0, 1.0]));
+        lanes.add(LaneType::Center, "later", lane_points(2.0, &[0.0, 0.0]));
+        let nearest = |x, time, max_distance| {
+            lanes
+                .nearest(x, Time(time), max_distance)
+                .map(|l| l.name.as_str())
+        };
+
+        assert_eq!(nearest(-0.2, 0.5, 2.0), Some("left"));
+        assert_eq!(nearest(0.2, 0.5, 2.0), Some("right"));
+        assert_eq!(nearest(0.0, 0.5, 0.5), None);
+        // `later` is closer but does not exist yet.
+        assert_eq!(nearest(-0.1, 1.0, 1.0), Some("left"));
+        assert_eq!(nearest(0.1, 2.5, 1.0), Some("later"));
+        assert_eq!(lanes.at(Time(0.5)).count(), 2);
+    }
+
+    #[test]
+    fn unstyled_notes_have_no_color() {
+        let mut styles = NoteStyles::default();
+        assert!(styles.is_empty());
+        assert_eq!(styles.color(NoteStyleKey::Target), None);
+
+        // A style without a color still falls back to the default palette.
+        styles.get_mut(NoteStyleKey::Flick);
+        assert!(!styles.is_empty());
+        assert_eq!(styles.color(NoteStyleKey::Flick), None);
+
+        let red = Color::from_rgb_hex(0xFF0000);
+        styles.get_mut(NoteStyleKey::Hit(HitNoteType::Left)).color = Some(red);
+        assert_eq!(
+            styles.color(NoteStyleKey::Hit(HitNoteType::Left)),
+            Some(red)
+        );
+        assert_eq!(styles.color(NoteStyleKey::Hit(HitNoteType::Right)), None);
+        assert_eq!(styles.iter().count(), 2);
+    }
+
+    #[test]
+    fn colors_round_trip_through_hex() {
+        let color = Color::from_rgb_hex(0x00FF80);
+        assert_eq!((color.r, color.g), (0.0, 1.0));
+        assert_eq!(color.to_rgb_hex(), 0x00FF80);
+        let bright = Color {
+            r: 2.0,
+            g: -1.0,
+            b: 0.5,
+        };
+        assert_eq!(bright.to_rgb_hex(), 0xFF0080);
+    }
+
+    #[test]
+    fn holds_are_sampled_within_their_points() {
+        let hold = HoldNote {
+            id: NoteId(0),
+            ty: HitNoteType::Left,
+            is_critical: false,
+            points: vec![position(1.0, 0.0, 0.0), position(2.0, 1.0, 1.0)],
+        };
+        assert_eq!(hold.sample_at(Time(1.5)), Some(position(1.5, 0.5, 0.5)));
+        assert_eq!(hold.sample_at(Time(2.5)), None);
+        assert_eq!(hold.range(Time(0.0), Time(1.5)).len(), 2);
+        assert!(hold.range(Time(3.0), Time(4.0)).is_empty());
+    }
+
+    #[test]
+    fn ids_are_assigned_by_time_then_kind() {
+        let hit = |time| HitNote {
+            id: NoteId(0),
+            ty: HitNoteType::Left,
+            is_critical: false,
+            position: position(time, 0.0, 0.0),
+        };
+        let mut notes = Notes {
+            hits: vec![hit(2.0), hit(1.0)],
+            targets: vec![TargetNote {
+                id: NoteId(0),
+                position: position(1.0, 0.0, 0.0),
+            }],
+            evades: vec![player_targeted_evade()],
+            ..Default::default()
+        };
+        notes.assign_ids();
+
+        assert_eq!(notes.hits[1].id, NoteId(0));
+        assert_eq!(notes.targets[0].id, NoteId(1));
+        assert_eq!(notes.hits[0].id, NoteId(2));
+        // Evade notes are played when their movement ends.
+        assert_eq!(notes.evades[0].id, NoteId(3));
+    }
+}
@@ -0,0 +1,1653 @@
+use std::{fs, sync::OnceLock};
+
+use regex::Regex;
+
+use crate::{
+    animation::{Animation, AnimationId, AnimationKind, AnimationLibrary, Transform, Vec3},
+    error::{ChartError, SourceLocation},
+    timing::{BeatPosition, MeterChange, TempoChange, TimingMap},
+    util::{cubic_bezier_track_positions, ZPosition, ZPositionCalculator},
+    BpmChange, BpmRange, Chart, ChartData, ChartUtils, Color, Composition, ContactNote,
+    ContactNoteType, EdgeSegment, EvadeNote, EvadeNoteSize, EvadeNoteType, FlickDirection,
+    FlickNote, Header, HitNote, HitNoteType, HoldNote, Lanes, Metadata, MovementTarget, NoteId,
+    NoteMovement, NoteStyleKey, NoteStyles, Notes, Platform, PlatformEdge, Soflan, TargetHoldNote,
+    TargetNote, Time, TimeSignature, TimeSignatureChange, Track, TrackPosition,
+};
+
+/// Position-based base velocity on the z axis.
+const ECL_Z_BASE_SPEED: f32 = 1.0;
+
+/// Number of line segments a bezier edge or hold note is flattened into.
+const ECL_BEZIER_SUBDIVISIONS: usize = 16;
+
+const ECL_DEFAULT_TIME_SIGNATURE: TimeSignature = TimeSignature {
+    num_beats: 4,
+    note_value: 4,
+};
+
+type Result<T> = std::result::Result<T, ChartError>;
+
+/// `[body_type] (beat) |position| {additional_options}`, every part except the body type is
+/// optional.
+fn body_line_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| {
+        Regex::new(
+            r"^\[(?P<tag>[^\]]+)\]\s*(?:\((?P<beats>[^)]*)\))?\s*(?:\|(?P<positions>[^|]*)\|)?\s*(?:\{(?P<options>[^}]*)\})?$",
+        )
+        .unwrap()
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Section {
+    Header,
+    Notes,
+    Animations,
+    ChartBody,
+}
+
+impl Section {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "header" => Some(Self::Header),
+            "notes" => Some(Self::Notes),
+            "animations" => Some(Self::Animations),
+            "chart_body" => Some(Self::ChartBody),
+            _ => None,
+        }
+    }
+}
+
+/// Non-empty, non-comment line of the source file.
+#[derive(Clone, Copy, Debug)]
+struct SourceLine<'a> {
+    /// Starts at 1.
+    number: usize,
+    /// Whole line as written in the source.
+    raw: &'a str,
+    /// Line without surrounding whitespace.
+    text: &'a str,
+}
+
+impl SourceLine<'_> {
+    /// Location of `part`, which is expected to be a slice of this line. Falls back to the start
+    /// of the line otherwise.
+    fn location_of(&self, part: &str) -> SourceLocation {
+        let line_start = self.raw.as_ptr() as usize;
+        let part_start = part.as_ptr() as usize;
+        let offset = if (line_start..=line_start + self.raw.len()).contains(&part_start) {
+            part_start - line_start
+        } else {
+            self.raw.len() - self.raw.trim_start().len()
+        };
+
+        // Columns count characters, not bytes.
+        SourceLocation::new(self.number, self.raw[..offset].chars().count() + 1)
+    }
+
+    fn location(&self) -> SourceLocation {
+        self.location_of(self.text)
+    }
+
+    fn syntax_error(&self, message: impl Into<String>) -> ChartError {
+        ChartError::Syntax {
+            location: self.location(),
+            message: message.into(),
+        }
+    }
+
+    fn invalid_value(&self, expected: &'static str, value: &str) -> ChartError {
+        ChartError::InvalidValue {
+            location: self.location_of(value),
+            expected,
+            value: value.to_string(),
+        }
+    }
+
+    fn unknown_name(&self, kind: &'static str, name: &str) -> ChartError {
+        ChartError::UnknownName {
+            location: self.location_of(name),
+            kind,
+            name: name.to_string(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Sections<'a> {
+    /// `<header>` line, `None` if the chart has no header section.
+    header_heading: Option<SourceLine<'a>>,
+    header: Vec<SourceLine<'a>>,
+    notes: Vec<SourceLine<'a>>,
+    animations: Vec<SourceLine<'a>>,
+    chart_body: Vec<SourceLine<'a>>,
+}
+
+impl<'a> Sections<'a> {
+    fn split(source: &'a str) -> Result<Self> {
+        let mut sections = Self::default();
+        let mut current_section = None;
+
+        for (index, raw) in source.lines().enumerate() {
+            let line = SourceLine {
+                number: index + 1,
+                raw,
+                text: raw.trim(),
+            };
+            if line.text.is_empty() || line.text.starts_with("//") {
+                continue;
+            }
+
+            if let Some(name) = line
+                .text
+                .strip_prefix('<')
+                .and_then(|t| t.strip_suffix('>'))
+            {
+                let section = Section::from_name(name.trim())
+                    .ok_or_else(|| line.unknown_name("section", name.trim()))?;
+                if section == Section::Header {
+                    sections.header_heading.get_or_insert(line);
+                }
+                current_section = Some(section);
+                continue;
+            }
+
+            let lines = match current_section {
+                Some(Section::Header) => &mut sections.header,
+                Some(Section::Notes) => &mut sections.notes,
+                Some(Section::Animations) => &mut sections.animations,
+                Some(Section::ChartBody) => &mut sections.chart_body,
+                None => return Err(line.syntax_error("line is outside of a section")),
+            };
+            lines.push(line);
+        }
+
+        Ok(sections)
+    }
+}
+
+fn parse_key_value<'a>(line: SourceLine<'a>) -> Option<(&'a str, &'a str)> {
+    line.text
+        .split_once('=')
+        .map(|(key, value)| (key.trim(), value.trim()))
+}
+
+fn parse_value<T: std::str::FromStr>(
+    line: SourceLine,
+    expected: &'static str,
+    value: &str,
+) -> Result<T> {
+    let value = value.trim();
+    value
+        .parse()
+        .map_err(|_| line.invalid_value(expected, value))
+}
+
+/// Tempos are whole beats per minute, a tempo of 0 would never reach the next beat.
+fn parse_tempo(line: SourceLine, value: &str) -> Result<u32> {
+    let tempo: u32 = parse_value(line, "tempo", value)?;
+    if tempo == 0 {
+        return Err(line.invalid_value("tempo", value.trim()));
+    }
+
+    Ok(tempo)
+}
+
+fn parse_time_signature(line: SourceLine, value: &str) -> Result<TimeSignature> {
+    let (num_beats, note_value) = value
+        .split_once('/')
+        .ok_or_else(|| line.invalid_value("time signature", value))?;
+    let time_signature = TimeSignature {
+        num_beats: parse_value(line, "time signature", num_beats)?,
+        note_value: parse_value(line, "time signature", note_value)?,
+    };
+
+    if time_signature.num_beats == 0 || time_signature.note_value == 0 {
+        return Err(line.invalid_value("time signature", value));
+    }
+
+    Ok(time_signature)
+}
+
+/// `min-max`, or a single tempo for charts without tempo changes.
+fn parse_bpm_range(line: SourceLine, value: &str) -> Result<BpmRange> {
+    let (min, max) = value.split_once('-').unwrap_or((value, value));
+    let bpm_range = BpmRange {
+        min: parse_value(line, "tempo range", min)?,
+        max: parse_value(line, "tempo range", max)?,
+    };
+
+    if bpm_range.min <= 0.0 || bpm_range.max < bpm_range.min {
+        return Err(line.invalid_value("tempo range", value));
+    }
+
+    Ok(bpm_range)
+}
+
+#[derive(Clone, Debug, Default)]
+struct EclHeader {
+    title: String,
+    artist: String,
+    chart_designer: String,
+    difficulty: String,
+    level: Option<f32>,
+    bpm_range: Option<BpmRange>,
+    /// Milliseconds.
+    preview_start: f32,
+    /// Milliseconds.
+    preview_length: f32,
+    audio_filename: String,
+    default_tempo: Option<u32>,
+    default_time_signature: Option<TimeSignature>,
+    /// Milliseconds.
+    offset: f32,
+    /// Location of the `<header>` line, errors about missing fields point here.
+    location: SourceLocation,
+}
+
+impl EclHeader {
+    fn parse(heading: Option<SourceLine>, lines: &[SourceLine]) -> Result<Self> {
+        let mut header = Self {
+            location: heading.map_or_else(SourceLocation::default, |h| h.location()),
+            ..Default::default()
+        };
+
+        for line in lines.iter().copied() {
+            let (key, value) =
+                parse_key_value(line).ok_or_else(|| line.syntax_error("expected `key=value`"))?;
+            match key {
+                "title" => header.title = value.to_string(),
+                "artist" => header.artist = value.to_string(),
+                "chart_designer" => header.chart_designer = value.to_string(),
+                "difficulty" => header.difficulty = value.to_string(),
+                "level" => header.level = Some(parse_value(line, "level", value)?),
+                "bpm_range" => header.bpm_range = Some(parse_bpm_range(line, value)?),
+                "preview_start" => {
+                    header.preview_start = parse_value(line, "preview start", value)?
+                }
+                "preview_length" => {
+                    header.preview_length = parse_value(line, "preview length", value)?
+                }
+                "audio_filename" => header.audio_filename = value.to_string(),
+                "default_tempo" => header.default_tempo = Some(parse_tempo(line, value)?),
+                "default_time_signature" => {
+                    header.default_time_signature = Some(parse_time_signature(line, value)?)
+                }
+                "offset" => header.offset = parse_value(line, "offset", value)?,
+                _ => log::warn!(
+                    "{}: Ignoring unknown header key `{}`",
+                    line.location_of(key),
+                    key
+                ),
+            }
+        }
+
+        Ok(header)
+    }
+}
+
+/// `[tag] (beats) |positions| {options}` with its parts still unparsed.
+#[derive(Clone, Copy, Debug)]
+struct BodyLine<'a> {
+    source: SourceLine<'a>,
+    tag: &'a str,
+    beats: Option<&'a str>,
+    positions: Option<&'a str>,
+    options: Option<&'a str>,
+}
+
+impl<'a> BodyLine<'a> {
+    fn parse(line: SourceLine<'a>) -> Result<Self> {
+        let captures = body_line_regex().captures(line.text).ok_or_else(|| {
+            line.syntax_error("expected `[body_type] (beat) |position| {additional_options}`")
+        })?;
+
+        Ok(Self {
+            source: line,
+            tag: captures.name("tag").unwrap().as_str().trim(),
+            beats: captures.name("beats").map(|m| m.as_str()),
+            positions: captures.name("positions").map(|m| m.as_str()),
+            options: captures.name("options").map(|m| m.as_str()),
+        })
+    }
+
+    fn error(&self, message: impl Into<String>) -> ChartError {
+        self.source.syntax_error(message)
+    }
+
+    fn parse_float(&self, value: &str) -> Result<f32> {
+        parse_value(self.source, "number", value)
+    }
+
+    fn parse_beat(&self, value: &str) -> Result<Beat> {
+        let mut values = value.split(',');
+        let subdivision_index = self.parse_float(values.next().unwrap_or_default())?;
+        let bars_ahead = match values.next() {
+            Some(bars) => parse_value(self.source, "bar count", bars)?,
+            None => 0,
+        };
+        if values.next().is_some() {
+            return Err(self.source.invalid_value("beat", value.trim()));
+        }
+
+        Ok(Beat {
+            subdivision_index,
+            bars_ahead,
+        })
+    }
+
+    /// Parses between `min_count` and `max_count` beats.
+    fn beats(&self, min_count: usize, max_count: usize) -> Result<Vec<Beat>> {
+        let beats = self
+            .beats
+            .ok_or_else(|| self.error("missing beat"))?
+            .split(';')
+            .map(|b| self.parse_beat(b))
+            .collect::<Result<Vec<_>>>()?;
+
+        if beats.len() < min_count || beats.len() > max_count {
+            return Err(self.error(format!(
+                "`{}` expects {} to {} beat value(s), found {}",
+                self.tag,
+                min_count,
+                max_count,
+                beats.len()
+            )));
+        }
+
+        Ok(beats)
+    }
+
+    fn parse_vector(&self, value: &str) -> Result<Vec3> {
+        let values = value
+            .split(',')
+            .map(|v| self.parse_float(v))
+            .collect::<Result<Vec<_>>>()?;
+
+        match values[..] {
+            [x] => Ok(Vec3 { x, y: 0.0, z: 0.0 }),
+            [x, z] => Ok(Vec3 { x, y: 0.0, z }),
+            [x, y, z] => Ok(Vec3 { x, y, z }),
+            _ => Err(self.source.invalid_value("position", value.trim())),
+        }
+    }
+
+    /// Parses between `min_count` and `max_count` position vectors.
+    fn positions(&self, min_count: usize, max_count: usize) -> Result<Vec<Vec3>> {
+        let positions = self
+            .positions
+            .ok_or_else(|| self.error("missing position"))?
+            .split(';')
+            .map(|p| self.parse_vector(p))
+            .collect::<Result<Vec<_>>>()?;
+
+        if positions.len() < min_count || positions.len() > max_count {
+            return Err(self.error(format!(
+                "`{}` expects {} to {} position(s), found {}",
+                self.tag,
+                min_count,
+                max_count,
+                positions.len()
+            )));
+        }
+
+        Ok(positions)
+    }
+
+    fn options(&self) -> &'a str {
+        self.options.unwrap_or_default().trim()
+    }
+}
+
+/// Beat position relative to the measure an object is written in.
+#[derive(Clone, Copy, Debug)]
+struct Beat {
+    /// Starts at 1, in units of the measure's subdivision.
+    subdivision_index: f32,
+    /// Number of bars ahead of the object's measure.
+    bars_ahead: usize,
+}
+
+/// Parses the animations section. Rotation angles are written in degrees and durations in
+/// milliseconds.
+fn parse_animations(lines: &[SourceLine]) -> Result<AnimationLibrary> {
+    let mut animations = AnimationLibrary::default();
+
+    for line in lines.iter().copied() {
+        let body_line = BodyLine::parse(line)?;
+
+        let duration = body_line.parse_float(
+            body_line
+                .positions
+                .ok_or_else(|| body_line.error("missing animation duration"))?,
+        )? / 1000.0;
+        let values = body_line
+            .options()
+            .split(';')
+            .map(|v| body_line.parse_vector(v))
+            .collect::<Result<Vec<_>>>()?;
+        let (v0, v1) = match values[..] {
+            [v0, v1] => (v0, v1),
+            _ => return Err(body_line.error("animation expects two vec3 values")),
+        };
+
+        let kind = match body_line.beats.map(str::trim) {
+            Some("t") => AnimationKind::Translation { from: v0, to: v1 },
+            Some("r") => AnimationKind::Rotation {
+                angles: Vec3::new(v0.x.to_radians(), v0.y.to_radians(), v0.z.to_radians()),
+                center: v1,
+            },
+            Some("s") => {
+                if [v0.x, v0.y, v0.z, v1.x, v1.y, v1.z].contains(&0.0) {
+                    return Err(body_line.error("scale animations cannot scale to or from 0"));
+                }
+                AnimationKind::Scale { from: v0, to: v1 }
+            }
+            _ => {
+                return Err(body_line
+                    .source
+                    .invalid_value("animation type", body_line.beats.unwrap_or_default()))
+            }
+        };
+
+        if animations.find(body_line.tag).is_some() {
+            return Err(body_line.error(format!("duplicate animation `{}`", body_line.tag)));
+        }
+        animations.add(Animation {
+            name: body_line.tag.to_string(),
+            kind,
+            duration,
+        });
+    }
+
+    Ok(animations)
+}
+
+/// Note kind of a `[note_type]` heading of the notes section.
+fn note_style_key(name: &str) -> Option<NoteStyleKey> {
+    let numbered = |prefix: &str, max: u16| {
+        name.strip_prefix(prefix)
+            .and_then(|n| n.parse::<u16>().ok())
+            .filter(|n| (1..=max).contains(n))
+    };
+
+    match name {
+        "target" => Some(NoteStyleKey::Target),
+        "flick" => Some(NoteStyleKey::Flick),
+        _ => numbered("basic_", 5)
+            .map(|n| NoteStyleKey::Hit(EclChartCreator::hit_note_type(n)))
+            .or_else(|| {
+                numbered("evade_", 4).map(|n| {
+                    let (ty, size) = EclChartCreator::evade_note_kind(n);
+                    NoteStyleKey::Evade(ty, size)
+                })
+            })
+            .or_else(|| {
+                numbered("contact_", 2)
+                    .map(|n| NoteStyleKey::Contact(EclChartCreator::contact_note_type(n)))
+            }),
+    }
+}
+
+fn parse_notes_section(lines: &[SourceLine]) -> Result<NoteStyles> {
+    let mut styles = NoteStyles::default();
+    let mut current = None;
+
+    for line in lines.iter().copied() {
+        if let Some(name) = line
+            .text
+            .strip_prefix('[')
+            .and_then(|text| text.strip_suffix(']'))
+        {
+            let name = name.trim();
+            current =
+                Some(note_style_key(name).ok_or_else(|| line.unknown_name("note type", name))?);
+            continue;
+        }
+
+        let Some(key) = current else {
+            return Err(line.syntax_error("expected `[note_type]` before note options"));
+        };
+        match parse_key_value(line) {
+            Some(("color", value)) => {
+                let rgb = u32::from_str_radix(value, 16)
+                    .ok()
+                    .filter(|_| value.len() == 6)
+                    .ok_or_else(|| line.invalid_value("color", value))?;
+                styles.get_mut(key).color = Some(Color::from_rgb_hex(rgb));
+            }
+            _ => return Err(line.syntax_error("expected `[note_type]` or `color=RRGGBB`")),
+        }
+    }
+
+    Ok(styles)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BodyType {
+    PlatformRect,
+    PlatformRectStatic,
+    PlatformQuad,
+    PlatformCurved,
+    Basic(u16),
+    Target,
+    HoldBasic(u16),
+    HoldTarget,
+    FlickLeft,
+    FlickRight,
+    Evade(u16),
+    Contact(u16),
+    Tempo,
+    Soflan,
+}
+
+impl BodyType {
+    fn from_tag(tag: &str) -> Option<Self> {
+        let numbered = |prefix: &str, max: u16| {
+            tag.strip_prefix(prefix)
+                .and_then(|n| n.parse::<u16>().ok())
+                .filter(|n| (1..=max).contains(n))
+        };
+
+        match tag {
+            "PR" => Some(Self::PlatformRect),
+            "PRS" => Some(Self::PlatformRectStatic),
+            "PQ" => Some(Self::PlatformQuad),
+            "PC" => Some(Self::PlatformCurved),
+            "T" => Some(Self::Target),
+            "HT" => Some(Self::HoldTarget),
+            "FL" => Some(Self::FlickLeft),
+            "FR" => Some(Self::FlickRight),
+            "BPM" => Some(Self::Tempo),
+            "SPD" => Some(Self::Soflan),
+            _ => numbered("B", 5)
+                .map(Self::Basic)
+                .or_else(|| numbered("HB", 5).map(Self::HoldBasic))
+                .or_else(|| numbered("E", 4).map(Self::Evade))
+                .or_else(|| numbered("C", 2).map(Self::Contact)),
+        }
+    }
+
+    fn is_platform(&self) -> bool {
+        matches!(
+            self,
+            Self::PlatformRect
+                | Self::PlatformRectStatic
+                | Self::PlatformQuad
+                | Self::PlatformCurved
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+struct EclMeasure<'a> {
+    time_signature: TimeSignature,
+    /// Tempo at the start of the measure.
+    tempo: u32,
+    /// Tempo changes within the measure as (beat, tempo), sorted by beat.
+    tempo_changes: Vec<(f32, u32)>,
+    /// Notes are written in units of `1 / subdivision` notes.
+    subdivision: u32,
+    objects: Vec<(BodyType, BodyLine<'a>)>,
+}
+
+impl EclMeasure<'_> {
+    /// Number of subdivision units that fit in this measure.
+    fn subdivision_count(&self) -> f32 {
+        (self.time_signature.num_beats * self.subdivision) as f32
+            / self.time_signature.note_value as f32
+    }
+
+    /// Converts a 1-based beat in subdivision units to time signature beats.
+    fn beat_position(&self, measure_index: usize, subdivision_index: f32) -> BeatPosition {
+        BeatPosition::new(
+            measure_index as _,
+            (subdivision_index - 1.0) * self.time_signature.note_value as f32
+                / self.subdivision as f32,
+        )
+    }
+}
+
+/// Measure options written in the chart body, before defaults are resolved.
+#[derive(Default)]
+struct MeasureOptions<'a> {
+    time_signature: Option<TimeSignature>,
+    tempo: Option<u32>,
+    tempo_changes: Vec<(f32, u32)>,
+    subdivision: Option<u32>,
+    objects: Vec<(BodyType, BodyLine<'a>)>,
+}
+
+fn parse_chart_body<'a>(
+    lines: &[SourceLine<'a>],
+    header: &EclHeader,
+) -> Result<Vec<EclMeasure<'a>>> {
+    let mut measure_options = vec![MeasureOptions::default()];
+
+    for line in lines.iter().copied() {
+        let current = measure_options.last_mut().unwrap();
+
+        if line.text == "--" {
+            measure_options.push(MeasureOptions::default());
+        } else if line.text.starts_with('[') {
+            let body_line = BodyLine::parse(line)?;
+            let body_type = BodyType::from_tag(body_line.tag)
+                .ok_or_else(|| line.unknown_name("body type", body_line.tag))?;
+            if body_type == BodyType::Tempo {
+                let beat = body_line.beats(1, 1)?[0];
+                if beat.bars_ahead != 0 {
+                    return Err(body_line.error("tempo changes cannot be placed in another bar"));
+                }
+                let tempo = parse_tempo(line, body_line.options())?;
+                current.tempo_changes.push((beat.subdivision_index, tempo));
+            }
+            current.objects.push((body_type, body_line));
+        } else {
+            let (key, value) = parse_key_value(line)
+                .ok_or_else(|| line.syntax_error("expected `--`, `key=value` or a body line"))?;
+            match key {
+                "time_signature" => {
+                    current.time_signature = Some(parse_time_signature(line, value)?)
+                }
+                "tempo" => current.tempo = Some(parse_tempo(line, value)?),
+                "subdivision" => {
+                    let subdivision: u32 = parse_value(line, "subdivision", value)?;
+                    if subdivision == 0 {
+                        return Err(line.invalid_value("subdivision", value));
+                    }
+                    current.subdivision = Some(subdivision);
+                }
+                _ => return Err(line.unknown_name("measure option", key)),
+            }
+        }
+    }
+
+    let mut time_signature = header
+        .default_time_signature
+        .unwrap_or(ECL_DEFAULT_TIME_SIGNATURE);
+    let mut tempo = header.default_tempo;
+
+    measure_options
+        .into_iter()
+        .map(|mut options| {
+            time_signature = options.time_signature.unwrap_or(time_signature);
+            tempo = options.tempo.or(tempo);
+
+            // Only the first measure can be missing a tempo.
+            let measure_tempo = tempo.ok_or_else(|| ChartError::MissingHeaderField {
+                location: header.location.clone(),
+                field: "default_tempo",
+            })?;
+
+            // The next measure starts with the last tempo change of this one.
+            options.tempo_changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+            if let Some((_, last_tempo)) = options.tempo_changes.last() {
+                tempo = Some(*last_tempo);
+            }
+
+            Ok(EclMeasure {
+                time_signature,
+                tempo: measure_tempo,
+                tempo_changes: options.tempo_changes,
+                subdivision: options.subdivision.unwrap_or(time_signature.note_value),
+                objects: options.objects,
+            })
+        })
+        .collect()
+}
+
+struct EclChartCreator<'a> {
+    header: EclHeader,
+    note_styles: NoteStyles,
+    animations: AnimationLibrary,
+    measures: Vec<EclMeasure<'a>>,
+    z_position_calculator: ZPositionCalculator,
+    /// Sorted by time.
+    soflans: Vec<Soflan>,
+}
+
+impl<'a> EclChartCreator<'a> {
+    fn new(source: &'a str) -> Result<Self> {
+        let sections = Sections::split(source)?;
+
+        let header = EclHeader::parse(sections.header_heading, &sections.header)?;
+        let note_styles = parse_notes_section(&sections.notes)?;
+        let animations = parse_animations(&sections.animations)?;
+        let measures = parse_chart_body(&sections.chart_body, &header)?;
+
+        let timing_map = Self::create_timing_map(&header, &measures);
+        let z_position_calculator = ZPositionCalculator::new(timing_map, ECL_Z_BASE_SPEED);
+
+        let mut creator = Self {
+            header,
+            note_styles,
+            animations,
+            measures,
+            z_position_calculator,
+            soflans: Vec::new(),
+        };
+
+        // Soflan times only depend on the timing map, every z position depends on the soflans.
+        creator.soflans = creator.create_soflans()?;
+        creator.z_position_calculator.set_soflans(&creator.soflans);
+
+        Ok(creator)
+    }
+
+    /// `[SPD] (start;end) {speed_multiplier}` lines, the end may be in a later bar.
+    fn create_soflans(&self) -> Result<Vec<Soflan>> {
+        let mut soflans = Vec::new();
+        for (measure_index, measure) in self.measures.iter().enumerate() {
+            for (_, line) in measure
+                .objects
+                .iter()
+                .filter(|(body_type, _)| *body_type == BodyType::Soflan)
+            {
+                let beats = line.beats(2, 2)?;
+                let start = self.z_position(line, measure_index, beats[0])?.time;
+                let end = self.z_position(line, measure_index, beats[1])?.time;
+                if end.0 <= start.0 {
+                    return Err(line.error("soflans must end after they start"));
+                }
+
+                soflans.push(Soflan {
+                    time: start,
+                    duration: end.0 - start.0,
+                    speed_multiplier: line.parse_float(line.options())?,
+                });
+            }
+        }
+
+        soflans.sort_by(|a, b| a.time.0.total_cmp(&b.time.0));
+        Ok(soflans)
+    }
+
+    fn create_timing_map(header: &EclHeader, measures: &[EclMeasure]) -> TimingMap {
+        let mut meter_changes = Vec::new();
+        let mut tempo_changes = Vec::new();
+
+        let mut previous: Option<(TimeSignature, u32)> = None;
+        for (measure_index, measure) in measures.iter().enumerate() {
+            if previous.is_none_or(|(time_signature, _)| time_signature != measure.time_signature) {
+                meter_changes.push(MeterChange {
+                    measure: measure_index as _,
+                    time_signature: measure.time_signature,
+                });
+            }
+            if previous.is_none_or(|(_, tempo)| tempo != measure.tempo) {
+                tempo_changes.push(TempoChange {
+                    position: BeatPosition::new(measure_index as _, 0.0),
+                    bpm: measure.tempo as _,
+                });
+            }
+
+            let mut end_tempo = measure.tempo;
+            for (subdivision_index, tempo) in measure.tempo_changes.iter() {
+                tempo_changes.push(TempoChange {
+                    position: measure.beat_position(measure_index, *subdivision_index),
+                    bpm: *tempo as _,
+                });
+                end_tempo = *tempo;
+            }
+
+            previous = Some((measure.time_signature, end_tempo));
+        }
+
+        TimingMap::new(Time(header.offset / 1000.0), &meter_changes, &tempo_changes)
+    }
+
+    fn z_position(&self, line: &BodyLine, measure_index: usize, beat: Beat) -> Result<ZPosition> {
+        let measure_index = measure_index + beat.bars_ahead;
+        let measure =
+            self.measures
+                .get(measure_index)
+                .ok_or_else(|| ChartError::MeasureOutOfRange {
+                    location: line.source.location(),
+                    measure: measure_index + 1,
+                    num_measures: self.measures.len(),
+                })?;
+
+        // The end of the measure is allowed so objects can end exactly on a bar line.
+        if beat.subdivision_index < 1.0
+            || beat.subdivision_index > measure.subdivision_count() + 1.0
+        {
+            return Err(ChartError::BeatOutOfRange {
+                location: line.source.location(),
+                measure: measure_index + 1,
+                beat: beat.subdivision_index,
+            });
+        }
+
+        Ok(self
+            .z_position_calculator
+            .z_position_at(measure.beat_position(measure_index, beat.subdivision_index)))
+    }
+
+    fn track_position(
+        &self,
+        line: &BodyLine,
+        measure_index: usize,
+        beat: Beat,
+        position: Vec3,
+    ) -> Result<TrackPosition> {
+        let z_position = self.z_position(line, measure_index, beat)?;
+        Ok(TrackPosition {
+            time: z_position.time,
+            z: z_position.z + position.z,
+            x: position.x,
+            y: position.y,
+        })
+    }
+
+    /// Parses bezier control points of the form `x,beat : x,beat`.
+    fn bezier_control_points(
+        &self,
+        line: &BodyLine,
+        measure_index: usize,
+        value: &str,
+    ) -> Result<(TrackPosition, TrackPosition)> {
+        let control_points = value
+            .split(':')
+            .map(|point| {
+                let (x, beat) = point
+                    .split_once(',')
+                    .ok_or_else(|| line.source.invalid_value("control point", point.trim()))?;
+                let position = Vec3 {
+                    x: line.parse_float(x)?,
+                    ..Default::default()
+                };
+                self.track_position(line, measure_index, line.parse_beat(beat)?, position)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        match control_points[..] {
+            [c0, c1] => Ok((c0, c1)),
+            _ => Err(line.error("expected two bezier control points separated by `:`")),
+        }
+    }
+
+    fn create_platform(
+        &self,
+        measure_index: usize,
+        body_type: BodyType,
+        line: &BodyLine,
+        next_platform_start: Option<TrackPosition>,
+    ) -> Result<Platform> {
+        let (start, end) = match body_type {
+            BodyType::PlatformRectStatic => {
+                let start = self.z_position(line, measure_index, line.beats(1, 1)?[0])?;
+                let end = match next_platform_start {
+                    Some(next) => ZPosition::new(next.time, next.z),
+                    // End of the last measure.
+                    None => self
+                        .z_position_calculator
+                        .z_position_at(BeatPosition::new(self.measures.len() as _, 0.0)),
+                };
+                (start, end)
+            }
+            _ => {
+                let beats = line.beats(2, 2)?;
+                (
+                    self.z_position(line, measure_index, beats[0])?,
+                    self.z_position(line, measure_index, beats[1])?,
+                )
+            }
+        };
+
+        let point = |z_position: ZPosition, x: f32| TrackPosition {
+            time: z_position.time,
+            z: z_position.z,
+            x,
+            y: 0.0,
+        };
+
+        let (bottom_left, bottom_right, top_left, top_right) = match body_type {
+            BodyType::PlatformRect | BodyType::PlatformRectStatic => {
+                let positions = line.positions(2, 2)?;
+                (
+                    positions[0].x,
+                    positions[1].x,
+                    positions[0].x,
+                    positions[1].x,
+                )
+            }
+            _ => {
+                let positions = line.positions(4, 4)?;
+                (
+                    positions[0].x,
+                    positions[1].x,
+                    positions[2].x,
+                    positions[3].x,
+                )
+            }
+        };
+
+        let straight_left = EdgeSegment::Straight {
+            start: point(start, bottom_left),
+            end: point(end, top_left),
+        };
+        let straight_right = EdgeSegment::Straight {
+            start: point(start, bottom_right),
+            end: point(end, top_right),
+        };
+
+        if body_type != BodyType::PlatformCurved || line.options().is_empty() {
+            return Ok(Platform {
+                left: PlatformEdge::Segments(vec![straight_left]),
+                right: PlatformEdge::Segments(vec![straight_right]),
+            });
+        }
+
+        let (left_edge, right_edge) = line
+            .options()
+            .split_once(';')
+            .ok_or_else(|| line.error("expected `{left_edge;right_edge}`"))?;
+
+        let edge = |edge: &str, straight: EdgeSegment, x_offset: f32| -> Result<_> {
+            let segment = match edge.trim() {
+                "m" => return Ok(PlatformEdge::Mirror { x_offset }),
+                "" => straight,
+                edge => EdgeSegment::CubicBezier {
+                    start: straight.start(),
+                    control_points: self.bezier_control_points(line, measure_index, edge)?,
+                    end: straight.end(),
+                },
+            };
+            Ok(PlatformEdge::Segments(vec![segment]))
+        };
+
+        let platform = Platform {
+            left: edge(left_edge, straight_left, bottom_left - bottom_right)?,
+            right: edge(right_edge, straight_right, bottom_right - bottom_left)?,
+        };
+        if matches!(
+            (&platform.left, &platform.right),
+            (PlatformEdge::Mirror { .. }, PlatformEdge::Mirror { .. })
+        ) {
+            return Err(line.error("both platform edges cannot be mirrored"));
+        }
+
+        Ok(platform)
+    }
+
+    fn create_platforms(&self) -> Result<Vec<Platform>> {
+        let platform_lines = self
+            .measures
+            .iter()
+            .enumerate()
+            .flat_map(|(measure_index, measure)| {
+                measure
+                    .objects
+                    .iter()
+                    .filter(|(body_type, _)| body_type.is_platform())
+                    .map(move |(body_type, line)| (measure_index, *body_type, line))
+            })
+            .collect::<Vec<_>>();
+
+        // Iterate backwards so static platforms can end at the start of the next platform.
+        let mut platforms = Vec::with_capacity(platform_lines.len());
+        let mut next_platform_start = None;
+        for (measure_index, body_type, line) in platform_lines.into_iter().rev() {
+            let platform =
+                self.create_platform(measure_index, body_type, line, next_platform_start)?;
+            next_platform_start = platform.left_segments().first().map(EdgeSegment::start);
+            platforms.push(platform);
+        }
+        platforms.reverse();
+
+        Ok(platforms)
+    }
+
+    /// Basic note colors follow the OGKR lane note colors.
+    fn hit_note_type(number: u16) -> HitNoteType {
+        match number {
+            1 => HitNoteType::Left,
+            2 => HitNoteType::Center,
+            3 => HitNoteType::Right,
+            4 => HitNoteType::WallLeft,
+            _ => HitNoteType::WallRight,
+        }
+    }
+
+    /// Evade note numbers are bullet kinds, the fourth is a large circle.
+    fn evade_note_kind(number: u16) -> (EvadeNoteType, EvadeNoteSize) {
+        match number {
+            1 => (EvadeNoteType::Circle, EvadeNoteSize::Normal),
+            2 => (EvadeNoteType::Needle, EvadeNoteSize::Normal),
+            3 => (EvadeNoteType::Square, EvadeNoteSize::Normal),
+            _ => (EvadeNoteType::Circle, EvadeNoteSize::Large),
+        }
+    }
+
+    fn contact_note_type(number: u16) -> ContactNoteType {
+        if number == 1 {
+            ContactNoteType::Primary
+        } else {
+            ContactNoteType::Secondary
+        }
+    }
+
+    /// Points of a basic or target hold.
+    fn create_hold_points(
+        &self,
+        measure_index: usize,
+        line: &BodyLine,
+    ) -> Result<Vec<TrackPosition>> {
+        let beats = line.beats(2, usize::MAX)?;
+        let positions = line.positions(1, beats.len())?;
+        if positions.len() != 1 && positions.len() != beats.len() {
+            return Err(line.error("hold notes expect either one position or one per beat"));
+        }
+
+        // A single position is used for every beat.
+        let points = beats
+            .iter()
+            .zip(positions.iter().cycle())
+            .map(|(beat, position)| self.track_position(line, measure_index, *beat, *position))
+            .collect::<Result<Vec<_>>>()?;
+
+        if line.options().is_empty() {
+            Ok(points)
+        } else if points.len() == 2 {
+            let control_points = self.bezier_control_points(line, measure_index, line.options())?;
+            Ok(cubic_bezier_track_positions(
+                points[0],
+                control_points,
+                points[1],
+                ECL_BEZIER_SUBDIVISIONS,
+            ))
+        } else {
+            Err(line.error("curved hold notes expect exactly two beats"))
+        }
+    }
+
+    /// Evade notes play their animations in order and reach their written position at the hit
+    /// time. The movement spans the whole animation stack.
+    fn create_evade_note_movement(
+        &self,
+        line: &BodyLine,
+        end: TrackPosition,
+    ) -> Result<(NoteMovement, Vec<AnimationId>)> {
+        let stack = line
+            .options()
+            .split(',')
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .map(|name| {
+                self.animations
+                    .find(name)
+                    .ok_or_else(|| line.source.unknown_name("animation", name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // The start pose is the inverse of the whole animation stack from the end pose.
+        let end_pose = Transform::translation(Vec3::new(end.x, end.y, end.z));
+        let start_position = self.animations.start_pose(&stack, end_pose).position();
+        let duration = self.animations.duration(&stack);
+        let trigger_time = Time(end.time.0 - duration);
+        // The note is at its start position when the stack starts playing.
+        let start = TrackPosition {
+            time: trigger_time,
+            z: start_position.z,
+            x: start_position.x,
+            y: start_position.y,
+        };
+
+        let movement = NoteMovement {
+            start,
+            end,
+            target: MovementTarget::Fixed,
+            trigger_time,
+            duration,
+        };
+        Ok((movement, stack))
+    }
+
+    fn create_notes(&self) -> Result<Notes> {
+        let mut notes = Notes::default();
+
+        for (measure_index, measure) in self.measures.iter().enumerate() {
+            for (body_type, line) in measure.objects.iter() {
+                let single_position = || -> Result<TrackPosition> {
+                    let beat = line.beats(1, 1)?[0];
+                    let position = line.positions(1, 1)?[0];
+                    self.track_position(line, measure_index, beat, position)
+                };
+
+                match *body_type {
+                    // ECL has no critical notes.
+                    BodyType::Basic(n) => notes.hits.push(HitNote {
+                        id: NoteId::default(),
+                        ty: Self::hit_note_type(n),
+                        is_critical: false,
+                        position: single_position()?,
+                    }),
+                    BodyType::HoldBasic(n) => notes.holds.push(HoldNote {
+                        id: NoteId::default(),
+                        ty: Self::hit_note_type(n),
+                        is_critical: false,
+                        points: self.create_hold_points(measure_index, line)?,
+                    }),
+                    BodyType::Target => notes.targets.push(TargetNote {
+                        id: NoteId::default(),
+                        position: single_position()?,
+                    }),
+                    BodyType::HoldTarget => notes.target_holds.push(TargetHoldNote {
+                        id: NoteId::default(),
+                        points: self.create_hold_points(measure_index, line)?,
+                    }),
+                    BodyType::FlickLeft | BodyType::FlickRight => {
+                        let beat = line.beats(1, 1)?[0];
+                        let positions = line.positions(2, 2)?;
+                        notes.flicks.push(FlickNote {
+                            id: NoteId::default(),
+                            direction: if *body_type == BodyType::FlickLeft {
+                                FlickDirection::Left
+                            } else {
+                                FlickDirection::Right
+                            },
+                            position: self.track_position(
+                                line,
+                                measure_index,
+                                beat,
+                                positions[0],
+                            )?,
+                            end_x: positions[1].x,
+                        })
+                    }
+                    BodyType::Evade(n) => {
+                        let end = single_position()?;
+                        let (movement, animations) = self.create_evade_note_movement(line, end)?;
+                        let (ty, size) = Self::evade_note_kind(n);
+                        notes.evades.push(EvadeNote {
+                            id: NoteId::default(),
+                            ty,
+                            size,
+                            speed: 1.0,
+                            movement,
+                            animations,
+                        })
+                    }
+                    BodyType::Contact(n) => notes.contacts.push(ContactNote {
+                        id: NoteId::default(),
+                        ty: Self::contact_note_type(n),
+                        position: single_position()?,
+                    }),
+                    BodyType::PlatformRect
+                    | BodyType::PlatformRectStatic
+                    | BodyType::PlatformQuad
+                    | BodyType::PlatformCurved => (),
+                    // Only validated here, tempo changes are part of the timing map.
+                    BodyType::Tempo => {
+                        self.z_position(line, measure_index, line.beats(1, 1)?[0])?;
+                    }
+                    // Part of the z position calculator.
+                    BodyType::Soflan => (),
+                }
+            }
+        }
+
+        Ok(notes)
+    }
+
+    fn create_composition(&self) -> Composition {
+        let timing_map = self.z_position_calculator.timing_map();
+        let mut composition = Composition::default();
+
+        let mut previous_measure: Option<&EclMeasure> = None;
+        let mut previous_tempo = None;
+        for (measure_index, measure) in self.measures.iter().enumerate() {
+            let time = timing_map.measure_start(measure_index as _);
+
+            if previous_tempo != Some(measure.tempo) {
+                composition.bpm_changes.push(BpmChange {
+                    time,
+                    bpm: measure.tempo,
+                });
+            }
+            if previous_measure.is_none_or(|m| m.time_signature != measure.time_signature) {
+                composition
+                    .time_signature_changes
+                    .push(TimeSignatureChange {
+                        time,
+                        time_signature: measure.time_signature,
+                    });
+            }
+
+            previous_tempo = Some(measure.tempo);
+            for (subdivision_index, tempo) in measure.tempo_changes.iter() {
+                composition.bpm_changes.push(BpmChange {
+                    time: timing_map
+                        .time_at(measure.beat_position(measure_index, *subdivision_index)),
+                    bpm: *tempo,
+                });
+                previous_tempo = Some(*tempo);
+            }
+
+            previous_measure = Some(measure);
+        }
+        composition.soflans = self.soflans.clone();
+
+        composition
+    }
+
+    fn create(self) -> Result<Chart> {
+        let mut notes = self.create_notes()?;
+        notes.assign_ids();
+
+        let data = ChartData {
+            track: Track {
+                platforms: self.create_platforms()?,
+                lanes: Lanes::default(),
+            },
+            notes,
+            composition: self.create_composition(),
+            animations: self.animations,
+        };
+
+        Ok(Chart {
+            header: Header {
+                title: self.header.title,
+                artist: self.header.artist,
+                chart_designer: self.header.chart_designer,
+                difficulty: self.header.difficulty,
+                level: self.header.level,
+                bpm_range: self.header.bpm_range,
+                preview_start: self.header.preview_start / 1000.0,
+                preview_length: self.header.preview_length / 1000.0,
+                audio_filename: self.header.audio_filename,
+                audio_offset: self.header.offset / 1000.0,
+            },
+            metadata: Metadata {
+                base_speed: ECL_Z_BASE_SPEED,
+                ..Default::default()
+            },
+            note_styles: self.note_styles,
+            data,
+            utils: ChartUtils {
+                z_position_calculator: self.z_position_calculator,
+            },
+        })
+    }
+}
+
+pub fn create_chart_from_ecl_source(source: &str) -> Result<Chart> {
+    EclChartCreator::new(source)?.create()
+}
+
+pub fn create_chart_from_ecl_file(file_name: &str) -> Result<Chart> {
+    let source = fs::read_to_string(file_name).map_err(|source| ChartError::Io {
+        location: SourceLocation::file(file_name),
+        source,
+    })?;
+    create_chart_from_ecl_source(&source).map_err(|e| e.with_file(file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHART: &str = "\
+<header>
+title=Test
+default_tempo=120
+default_time_signature=4/4
+offset=500
+
+<animations>
+[approach] (t) |500| {0,0,2;0,0,0}
+
+<chart_body>
+[B1] (1) |0|
+[B2] (3) |0.5|
+[E1] (2) |0.2| {approach}
+--
+tempo=240
+[C1] (1) |0|
+";
+
+    /// 120 BPM 4/4, a measure lasts 2 seconds and a beat 0.5 seconds.
+    const BODY: &str = "\
+<header>
+default_tempo=120
+default_time_signature=4/4
+
+<notes>
+[basic_1]
+color=FF0000
+[target]
+color=00ff80
+
+<chart_body>
+[PR] (1;5) |-1;1|
+[HB2] (1;2;3) |0;0.5;1|
+[HB3] (1;3) |0.25|
+[HT] (1;5) |0| {-1,2 : 1,4}
+[B1] (3.5,8) |0|
+[HB1] (2;3.5,8) |0|
+[FL] (3) |0.5;-0.5|
+[FR] (4) |-0.5,0.5;0.5|
+--
+[PRS] (1) |-2;2|
+--
+[PQ] (1;5) |-1;1;-2;2|
+--
+[PC] (1;5) |-1;1;-1;1| {-2,2 : -2,4 ; m}
+--
+--
+--
+--
+--
+";
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn parses_header_and_notes() {
+        let chart = create_chart_from_ecl_source(CHART).unwrap();
+        assert_eq!(chart.header.title, "Test");
+        assert_close(chart.header.audio_offset, 0.5);
+
+        let notes = &chart.data.notes;
+        assert_eq!(notes.hits.len(), 2);
+        assert_eq!(notes.evades.len(), 1);
+        assert_eq!(notes.contacts.len(), 1);
+
+        // Measure 0 starts at the offset, a 4/4 measure at 120 BPM lasts 2 seconds.
+        assert_eq!(notes.hits[0].ty, HitNoteType::Left);
+        assert_close(notes.hits[0].position.time.0, 0.5);
+        assert_close(notes.hits[1].position.time.0, 1.5);
+        assert_close(notes.hits[1].position.x, 0.5);
+        assert_close(notes.contacts[0].position.time.0, 2.5);
+    }
+
+    #[test]
+    fn evade_movement_starts_at_trigger_time() {
+        let chart = create_chart_from_ecl_source(CHART).unwrap();
+        let evade = &chart.data.notes.evades[0];
+        let movement = &evade.movement;
+
+        assert_close(movement.duration, 0.5);
+        assert_close(movement.end.time.0, 1.0);
+        assert_close(movement.trigger_time.0, 0.5);
+        assert_close(movement.start.time.0, movement.trigger_time.0);
+        assert_close(movement.start.z, movement.end.z + 2.0);
+    }
+
+    #[test]
+    fn fourth_evade_note_is_a_large_circle() {
+        let source = CHART.replace("[E1]", "[E4]");
+        let chart = create_chart_from_ecl_source(&source).unwrap();
+        let evade = &chart.data.notes.evades[0];
+        assert_eq!(
+            (evade.ty, evade.size),
+            (EvadeNoteType::Circle, EvadeNoteSize::Large)
+        );
+
+        assert_eq!(
+            note_style_key("evade_4"),
+            Some(NoteStyleKey::Evade(
+                EvadeNoteType::Circle,
+                EvadeNoteSize::Large
+            ))
+        );
+        assert_eq!(note_style_key("evade_5"), None);
+    }
+
+    #[test]
+    fn parses_rect_static_quad_and_curved_platforms() {
+        let chart = create_chart_from_ecl_source(BODY).unwrap();
+        let platforms = &chart.data.track.platforms;
+        assert_eq!(platforms.len(), 4);
+
+        let xs_at = |platform: &Platform, time: f32| {
+            let (left, right) = platform.sample_at(Time(time)).unwrap();
+            (left.x, right.x)
+        };
+
+        // PR keeps its x for its whole range.
+        assert_eq!(platforms[0].time_range(), Some((Time(0.0), Time(2.0))));
+        assert_eq!(xs_at(&platforms[0], 1.0), (-1.0, 1.0));
+
+        // PRS ends where the next platform starts.
+        assert_eq!(platforms[1].time_range(), Some((Time(2.0), Time(4.0))));
+        assert_eq!(xs_at(&platforms[1], 3.0), (-2.0, 2.0));
+
+        // PQ interpolates from its bottom to its top edge.
+        assert_eq!(platforms[2].time_range(), Some((Time(4.0), Time(6.0))));
+        assert_eq!(xs_at(&platforms[2], 5.0), (-1.5, 1.5));
+
+        // PC has a bezier left edge and a right edge mirroring it.
+        let PlatformEdge::Segments(left) = &platforms[3].left else {
+            panic!("left edge should have segments");
+        };
+        let EdgeSegment::CubicBezier { control_points, .. } = left[0] else {
+            panic!("left edge should be a bezier curve");
+        };
+        assert_eq!(control_points.0.x, -2.0);
+        assert_close(control_points.0.time.0, 6.5);
+        assert_close(control_points.1.time.0, 7.5);
+        assert_eq!(platforms[3].right, PlatformEdge::Mirror { x_offset: 2.0 });
+        let (left, right) = xs_at(&platforms[3], 7.0);
+        assert!(left < -1.0, "{}", left);
+        assert_close(right - left, 2.0);
+    }
+
+    #[test]
+    fn parses_holds_and_curved_holds() {
+        let chart = create_chart_from_ecl_source(BODY).unwrap();
+        let notes = &chart.data.notes;
+
+        let hold = &notes.holds[0];
+        assert_eq!(hold.ty, HitNoteType::Center);
+        let points = hold
+            .points
+            .iter()
+            .map(|p| (p.time.0, p.x))
+            .collect::<Vec<_>>();
+        assert_eq!(points, vec![(0.0, 0.0), (0.5, 0.5), (1.0, 1.0)]);
+
+        // A single position is used for every beat.
+        let hold = &notes.holds[1];
+        assert_eq!(hold.points.len(), 2);
+        assert!(hold.points.iter().all(|p| p.x == 0.25));
+
+        // Curved holds are flattened, their ends stay on the written beats.
+        let points = &notes.target_holds[0].points;
+        assert_eq!(points.len(), ECL_BEZIER_SUBDIVISIONS + 1);
+        assert_eq!((points[0].time.0, points[0].x), (0.0, 0.0));
+        let last = points.last().unwrap();
+        assert_close(last.time.0, 2.0);
+        assert_close(last.x, 0.0);
+        assert!(points[4].x < 0.0 && points[12].x > 0.0);
+
+        let error =
+            create_chart_from_ecl_source(&BODY.replace("|0;0.5;1|", "|0;0.5|")).unwrap_err();
+        assert_eq!(error.location().line, Some(13));
+    }
+
+    #[test]
+    fn beats_can_be_written_in_a_later_bar() {
+        let chart = create_chart_from_ecl_source(BODY).unwrap();
+        let notes = &chart.data.notes;
+
+        // Beat 3.5 of the 8th bar after the first, the hold starts at beat 2 of the first.
+        let end = 8.0 * 2.0 + 2.5 * 0.5;
+        assert_close(notes.hits[0].position.time.0, end);
+        let hold = &notes.holds[2];
+        assert_close(hold.points[0].time.0, 0.5);
+        assert_close(hold.points[1].time.0, end);
+
+        let error =
+            create_chart_from_ecl_source(&BODY.replace("(2;3.5,8)", "(2;3.5,9)")).unwrap_err();
+        assert!(matches!(
+            error,
+            ChartError::MeasureOutOfRange { measure: 10, .. }
+        ));
+    }
+
+    #[test]
+    fn parses_flicks() {
+        let chart = create_chart_from_ecl_source(BODY).unwrap();
+        let flicks = &chart.data.notes.flicks;
+
+        assert_eq!(flicks[0].direction, FlickDirection::Left);
+        assert_eq!((flicks[0].position.x, flicks[0].end_x), (0.5, -0.5));
+        assert_close(flicks[0].position.time.0, 1.0);
+
+        assert_eq!(flicks[1].direction, FlickDirection::Right);
+        assert_eq!((flicks[1].position.x, flicks[1].end_x), (-0.5, 0.5));
+        assert_close(flicks[1].position.z - flicks[1].position.time.0, 0.5);
+
+        let error = create_chart_from_ecl_source(&BODY.replace("|0.5;-0.5|", "|0.5|")).unwrap_err();
+        assert_eq!(error.location().line, Some(18));
+    }
+
+    #[test]
+    fn parses_note_styles() {
+        let chart = create_chart_from_ecl_source(BODY).unwrap();
+        let styles = &chart.note_styles;
+
+        assert_eq!(
+            styles.color(NoteStyleKey::Hit(HitNoteType::Left)),
+            Some(Color::from_rgb_hex(0xFF0000))
+        );
+        assert_eq!(
+            styles.color(NoteStyleKey::Target),
+            Some(Color::from_rgb_hex(0x00FF80))
+        );
+        assert_eq!(styles.color(NoteStyleKey::Flick), None);
+
+        for (from, to) in [
+            ("color=FF0000", "color=FF00"),
+            ("color=FF0000", "size=2"),
+            ("[target]", "[target_2]"),
+        ] {
+            let error = create_chart_from_ecl_source(&BODY.replace(from, to)).unwrap_err();
+            assert!(error.location().line.is_some(), "{}", error);
+        }
+        let error = create_chart_from_ecl_source(&BODY.replace("[basic_1]\n", "")).unwrap_err();
+        assert_eq!(error.location().line, Some(6));
+    }
+
+    #[test]
+    fn note_style_names_resolve_to_note_kinds() {
+        let hit = |ty| Some(NoteStyleKey::Hit(ty));
+        assert_eq!(note_style_key("basic_1"), hit(HitNoteType::Left));
+        assert_eq!(note_style_key("basic_2"), hit(HitNoteType::Center));
+        assert_eq!(note_style_key("basic_3"), hit(HitNoteType::Right));
+        assert_eq!(note_style_key("basic_4"), hit(HitNoteType::WallLeft));
+        assert_eq!(note_style_key("basic_5"), hit(HitNoteType::WallRight));
+        assert_eq!(note_style_key("target"), Some(NoteStyleKey::Target));
+        assert_eq!(note_style_key("flick"), Some(NoteStyleKey::Flick));
+        assert_eq!(
+            note_style_key("evade_2"),
+            Some(NoteStyleKey::Evade(
+                EvadeNoteType::Needle,
+                EvadeNoteSize::Normal
+            ))
+        );
+        assert_eq!(
+            note_style_key("evade_4"),
+            Some(NoteStyleKey::Evade(
+                EvadeNoteType::Circle,
+                EvadeNoteSize::Large
+            ))
+        );
+        assert_eq!(
+            note_style_key("contact_2"),
+            Some(NoteStyleKey::Contact(ContactNoteType::Secondary))
+        );
+
+        for name in [
+            "basic_0",
+            "basic_6",
+            "evade_5",
+            "contact_3",
+            "hold",
+            "basic_",
+        ] {
+            assert_eq!(note_style_key(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn later_note_styles_win() {
+        let source = BODY.replace(
+            "[target]\ncolor=00ff80\n",
+            "[target]\ncolor=00ff80\n[ basic_1 ]\ncolor=0000FF\n",
+        );
+        let chart = create_chart_from_ecl_source(&source).unwrap();
+        let styles = &chart.note_styles;
+        assert_eq!(
+            styles.color(NoteStyleKey::Hit(HitNoteType::Left)),
+            Some(Color::from_rgb_hex(0x0000FF))
+        );
+        // Unstyled notes are left to the game's palette.
+        assert_eq!(styles.iter().count(), 2);
+        assert_eq!(styles.color(NoteStyleKey::Hit(HitNoteType::Center)), None);
+    }
+
+    #[test]
+    fn walls_have_distinct_numbers() {
+        let source = CHART
+            .replace("[B1] (1) |0|", "[B4] (1) |0|")
+            .replace("[B2] (3) |0.5|", "[B5] (3) |0.5|");
+        let chart = create_chart_from_ecl_source(&source).unwrap();
+        let types = chart
+            .data
+            .notes
+            .hits
+            .iter()
+            .map(|n| n.ty)
+            .collect::<Vec<_>>();
+        assert_eq!(types, vec![HitNoteType::WallLeft, HitNoteType::WallRight]);
+
+        assert_eq!(
+            note_style_key("basic_5"),
+            Some(NoteStyleKey::Hit(HitNoteType::WallRight))
+        );
+        assert_eq!(note_style_key("basic_6"), None);
+    }
+
+    #[test]
+    fn zero_tempos_are_rejected() {
+        for (from, to, line) in [
+            ("default_tempo=120", "default_tempo=0", 3),
+            ("tempo=240", "tempo=0", 15),
+        ] {
+            let error = create_chart_from_ecl_source(&CHART.replace(from, to)).unwrap_err();
+            assert!(
+                matches!(error, ChartError::InvalidValue { .. }),
+                "{}",
+                error
+            );
+            assert_eq!(error.location().line, Some(line));
+        }
+    }
+
+    #[test]
+    fn missing_default_tempo_points_at_the_header() {
+        let source = CHART.replace("default_tempo=120\n", "");
+        let error = create_chart_from_ecl_source(&source).unwrap_err();
+        assert!(matches!(
+            error,
+            ChartError::MissingHeaderField {
+                field: "default_tempo",
+                ..
+            }
+        ));
+        assert_eq!(error.location().line, Some(1));
+    }
+
+    #[test]
+    fn unknown_animation_is_an_error() {
+        let source = CHART.replace("{approach}", "{missing}");
+        let error = create_chart_from_ecl_source(&source).unwrap_err();
+        assert!(matches!(error, ChartError::UnknownName { .. }));
+        assert_eq!(error.location().line, Some(13));
+    }
+}
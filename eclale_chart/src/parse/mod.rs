@@ -1 +1,228 @@
+use std::{fs, path::Path};
+
+use crate::{Chart, ChartError, SourceLocation};
+
+pub mod ecl;
 pub mod ogkr;
+
+/// Number of lines at the start of a chart that are searched for magic lines.
+const MAGIC_LINE_SEARCH_LENGTH: usize = 32;
+
+/// Chart format that can be imported by `load_chart`.
+pub trait ChartImporter: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// File extensions without the leading dot, compared case insensitively.
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// Lines that only appear in charts of this format, compared after trimming whitespace.
+    fn magic_lines(&self) -> &'static [&'static str];
+
+    fn import(&self, bytes: &[u8]) -> Result<Chart, ChartError>;
+
+    /// Imports a chart read from `path`, for formats that keep part of the chart in other files.
+    fn import_file(&self, _path: &Path, bytes: &[u8]) -> Result<Chart, ChartError> {
+        self.import(bytes)
+    }
+}
+
+fn source_from_bytes(bytes: &[u8]) -> Result<&str, ChartError> {
+    std::str::from_utf8(bytes).map_err(|source| ChartError::Encoding {
+        location: SourceLocation::default(),
+        source,
+    })
+}
+
+pub struct EclImporter;
+
+impl ChartImporter for EclImporter {
+    fn name(&self) -> &'static str {
+        "ECL"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["ecl"]
+    }
+
+    fn magic_lines(&self) -> &'static [&'static str] {
+        &["<header>"]
+    }
+
+    fn import(&self, bytes: &[u8]) -> Result<Chart, ChartError> {
+        ecl::create_chart_from_ecl_source(source_from_bytes(bytes)?)
+    }
+}
+
+pub struct OgkrImporter;
+
+impl ChartImporter for OgkrImporter {
+    fn name(&self) -> &'static str {
+        "OGKR"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["ogkr"]
+    }
+
+    fn magic_lines(&self) -> &'static [&'static str] {
+        &["[HEADER]"]
+    }
+
+    fn import(&self, bytes: &[u8]) -> Result<Chart, ChartError> {
+        ogkr::create_chart_from_ogkr_source(source_from_bytes(bytes)?)
+    }
+
+    fn import_file(&self, path: &Path, bytes: &[u8]) -> Result<Chart, ChartError> {
+        ogkr::create_chart_from_ogkr_source_at(source_from_bytes(bytes)?, path)
+    }
+}
+
+pub struct ImporterRegistry {
+    importers: Vec<Box<dyn ChartImporter>>,
+}
+
+impl Default for ImporterRegistry {
+    /// Registry with every importer of this crate.
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(EclImporter));
+        registry.register(Box::new(OgkrImporter));
+        registry
+    }
+}
+
+impl ImporterRegistry {
+    /// Empty registry.
+    pub fn new() -> Self {
+        Self {
+            importers: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, importer: Box<dyn ChartImporter>) {
+        self.importers.push(importer);
+    }
+
+    pub fn importers(&self) -> impl Iterator<Item = &dyn ChartImporter> {
+        self.importers.iter().map(|i| i.as_ref())
+    }
+
+    /// Magic lines take precedence over the file extension, as extensions are not always reliable.
+    /// Importers registered first win when several recognize the chart.
+    pub fn find(&self, path: &Path, bytes: &[u8]) -> Option<&dyn ChartImporter> {
+        let head = String::from_utf8_lossy(&bytes[..bytes.len().min(4096)]);
+        let lines = head
+            .lines()
+            .take(MAGIC_LINE_SEARCH_LENGTH)
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        let by_magic_line = self
+            .importers()
+            .find(|i| i.magic_lines().iter().any(|magic| lines.contains(magic)));
+
+        by_magic_line.or_else(|| {
+            let extension = path.extension()?.to_str()?;
+            self.importers().find(|i| {
+                i.extensions()
+                    .iter()
+                    .any(|e| e.eq_ignore_ascii_case(extension))
+            })
+        })
+    }
+
+    /// Imports the chart at `path` with the importer that recognizes it. The audio path of the
+    /// chart is resolved against the directory of `path`.
+    pub fn load_chart(&self, path: impl AsRef<Path>) -> Result<Chart, ChartError> {
+        let path = path.as_ref();
+        let file_name = path.to_string_lossy();
+
+        let bytes = fs::read(path).map_err(|source| ChartError::Io {
+            location: SourceLocation::file(&file_name),
+            source,
+        })?;
+        let importer = self
+            .find(path, &bytes)
+            .ok_or_else(|| ChartError::UnknownFormat {
+                location: SourceLocation::file(&file_name),
+            })?;
+        log::info!("Importing chart {} as {}", file_name, importer.name());
+
+        let mut chart = importer
+            .import_file(path, &bytes)
+            .map_err(|e| e.with_file(&file_name))?;
+
+        if !chart.header.audio_filename.is_empty() {
+            let chart_dir = path.parent().unwrap_or(Path::new(""));
+            chart.metadata.audio_path = Some(chart_dir.join(&chart.header.audio_filename));
+        }
+
+        Ok(chart)
+    }
+}
+
+/// Loads the chart at `path` with the default importers.
+pub fn load_chart(path: impl AsRef<Path>) -> Result<Chart, ChartError> {
+    ImporterRegistry::default().load_chart(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHART: &str = "\
+<header>
+audio_filename=song.ogg
+default_tempo=120
+default_time_signature=4/4
+
+<chart_body>
+[B1] (1) |0|
+";
+
+    fn found(path: &str, source: &str) -> Option<&'static str> {
+        ImporterRegistry::default()
+            .find(Path::new(path), source.as_bytes())
+            .map(|i| i.name())
+    }
+
+    #[test]
+    fn magic_lines_win_over_extensions() {
+        assert_eq!(found("chart.ogkr", "// comment\n  <header>\n"), Some("ECL"));
+        assert_eq!(
+            found("chart.txt", "[HEADER]\nVERSION 1 0 0\n"),
+            Some("OGKR")
+        );
+    }
+
+    #[test]
+    fn extensions_are_used_without_magic_lines() {
+        assert_eq!(found("chart.ECL", ""), Some("ECL"));
+        assert_eq!(found("chart.ogkr", ""), Some("OGKR"));
+        assert_eq!(found("chart.txt", ""), None);
+        assert_eq!(found("chart", ""), None);
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_encoding_error() {
+        let error = EclImporter.import(&[0x3c, 0xff, 0xfe]).unwrap_err();
+        assert!(matches!(error, ChartError::Encoding { .. }));
+    }
+
+    #[test]
+    fn load_chart_resolves_the_audio_path() {
+        let dir = std::env::temp_dir().join(format!("eclale_chart_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chart.txt");
+        fs::write(&path, CHART).unwrap();
+
+        let chart = load_chart(&path).unwrap();
+        assert_eq!(chart.metadata.audio_path, Some(dir.join("song.ogg")));
+
+        fs::write(&path, "").unwrap();
+        let error = load_chart(&path).unwrap_err();
+        assert!(matches!(error, ChartError::UnknownFormat { .. }));
+        assert!(error.location().file.is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
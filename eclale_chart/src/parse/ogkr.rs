@@ -1,13 +1,18 @@
-use std::{
-    collections::{BTreeMap, HashMap},
-    fs,
-};
+//! Importer for OGKR charts. OGKR files only carry the chart designer and tempos of the chart
+//! information in `Header`, the rest is read from the music data file next to the chart and from
+//! the chart's file name:
+//! - `music.txt` in the chart's directory has one `KEY<tab>value` line per field, all optional:
+//!   `TITLE`, `ARTIST`, `AUDIO` (audio file name), `OFFSET` (time of measure 0 in milliseconds
+//!   since the start of the audio), `PREVIEW` (start and length in milliseconds) and `LEVEL`
+//!   (difficulty number and level, once per difficulty).
+//! - Chart file names end with their difficulty number, eg. `0801_03.ogkr`. Numbers 0 to 4 are
+//!   Basic, Advanced, Expert, Master and Lunatic.
 
-use anyhow::Result;
+use std::{collections::BTreeMap, fs, path::Path};
 
 use ogkr::{
     lex::{
-        command::{BulletShooter, BulletTarget},
+        command::{BulletShooter, BulletSize, BulletTarget, BulletType},
         tokenize,
     },
     parse::{
@@ -17,16 +22,42 @@ use ogkr::{
 };
 
 use crate::{
-    util::{MeasureCompositionData, XPositionCalculator, ZPosition, ZPositionCalculator},
-    BpmChange, Chart, ChartData, ChartUtils, Composition, ContactNote, ContactNoteType, EvadeNote,
-    EvadeNoteType, FlickDirection, FlickNote, Header, HitNote, HitNoteType, HoldNote, Lane,
-    LaneType, Metadata, NoteMovement, Notes, Platform, Soflan, Time, TimeSignature,
-    TimeSignatureChange, Track, TrackPosition,
+    animation::AnimationLibrary,
+    error::{ChartError, SourceLocation},
+    timing::{BeatPosition, MeterChange, TempoChange, TimingMap},
+    util::{
+        clip_track_positions, time_range, x_at, XPositionCalculator, ZPosition, ZPositionCalculator,
+    },
+    BpmChange, BpmRange, Chart, ChartData, ChartUtils, Composition, ContactNote, ContactNoteType,
+    EvadeNote, EvadeNoteSize, EvadeNoteType, FlickDirection, FlickNote, Header, HitNote,
+    HitNoteType, HoldNote, LaneType, Lanes, Metadata, MovementTarget, NoteId, NoteMovement,
+    NoteStyles, Notes, Platform, PlatformEdge, Soflan, Time, TimeSignature, TimeSignatureChange,
+    Track, TrackPosition,
 };
 
 const OGKR_X_POSITION_MULTIPLIER: f32 = 1.0 / 25.0;
 
-// XXX TODO: Have dedicated type enums defined here for notes, lanes etc.
+const OGKR_Z_BASE_SPEED: f32 = 1.0;
+
+/// Times closer than this are treated as the same point in seconds.
+const TIME_EPSILON: f32 = 1e-3;
+
+/// Music data file in the directory of OGKR charts.
+const OGKR_MUSIC_DATA_FILE_NAME: &str = "music.txt";
+
+/// Difficulty names by the number chart file names end with.
+const OGKR_DIFFICULTIES: &[&str] = &["Basic", "Advanced", "Expert", "Master", "Lunatic"];
+
+/// Commands that start a lane, `<command> <lane id> <measure> <tick> ...`.
+const OGKR_LANE_START_COMMANDS: &[&str] = &["WLS", "WRS", "LLS", "LCS", "LRS", "ENS"];
+
+/// Tap and hold commands, `<command> <lane id> <measure> <tick> ...`.
+const OGKR_NOTE_COMMANDS: &[&str] = &["TAP", "CTP", "XTP", "CXT", "HLD", "CHD", "XHD", "CXD"];
+
+/// Bullet command, `BLT <palette id> <measure> <tick> ...`.
+const OGKR_BULLET_COMMANDS: &[&str] = &["BLT"];
+
+type Result<T> = std::result::Result<T, ChartError>;
 
 impl From<ogkr_analysis::MeterChange> for TimeSignature {
     fn from(m: ogkr_analysis::MeterChange) -> Self {
@@ -37,14 +68,21 @@ impl From<ogkr_analysis::MeterChange> for TimeSignature {
     }
 }
 
-impl From<ogkr_analysis::LaneType> for LaneType {
-    fn from(t: ogkr_analysis::LaneType) -> Self {
+impl From<BulletSize> for EvadeNoteSize {
+    fn from(s: BulletSize) -> Self {
+        match s {
+            BulletSize::Normal => Self::Normal,
+            BulletSize::Large => Self::Large,
+        }
+    }
+}
+
+impl From<BulletType> for EvadeNoteType {
+    fn from(t: BulletType) -> Self {
         match t {
-            ogkr_analysis::LaneType::Left => LaneType(1),
-            ogkr_analysis::LaneType::Center => LaneType(2),
-            ogkr_analysis::LaneType::Right => LaneType(3),
-            ogkr_analysis::LaneType::Enemy => LaneType(4),
-            _ => LaneType(99),
+            BulletType::Circle => Self::Circle,
+            BulletType::Needle => Self::Needle,
+            BulletType::Square => Self::Square,
         }
     }
 }
@@ -58,46 +96,239 @@ impl From<ogkr::lex::command::FlickDirection> for FlickDirection {
     }
 }
 
-struct OgkrChartCreator {
+/// The OGKR analysis does not keep source lines. Errors about an object are located by searching
+/// the source for the first command of its kind at its measure and tick.
+struct OgkrSource<'a> {
+    source: &'a str,
+}
+
+impl OgkrSource<'_> {
+    /// Location of the first `<command> <id> <measure> <tick>` line with a command in `commands`
+    /// at `time`. The location has no line if there is none.
+    fn locate(&self, commands: &[&str], time: ogkr_analysis::TimingPoint) -> SourceLocation {
+        let (measure, tick) = (time.measure.to_string(), time.beat_offset.to_string());
+        self.source
+            .lines()
+            .enumerate()
+            .find(|(_, line)| {
+                let tokens = line.split_whitespace().collect::<Vec<_>>();
+                match tokens[..] {
+                    [command, _, m, t, ..] => {
+                        commands.contains(&command) && m == measure && t == tick
+                    }
+                    _ => false,
+                }
+            })
+            .map_or_else(SourceLocation::default, |(index, line)| {
+                let column = line.len() - line.trim_start().len() + 1;
+                SourceLocation::new(index + 1, column)
+            })
+    }
+}
+
+/// Chart information OGKR files do not have. Times are in seconds.
+#[derive(Clone, Debug, Default)]
+struct OgkrMusicData {
+    title: String,
+    artist: String,
+    difficulty: String,
+    level: Option<f32>,
+    preview_start: f32,
+    preview_length: f32,
+    audio_filename: String,
+    audio_offset: f32,
+}
+
+impl OgkrMusicData {
+    /// Difficulty number at the end of a chart file name, eg. 3 for `0801_03.ogkr`.
+    fn difficulty_number(chart_path: &Path) -> Option<usize> {
+        let stem = chart_path.file_stem()?.to_str()?;
+        let (_, number) = stem.rsplit_once('_')?;
+        number.parse().ok().filter(|n| *n < OGKR_DIFFICULTIES.len())
+    }
+
+    /// Music data of the chart at `chart_path`. Charts without a music data file only get their
+    /// difficulty.
+    fn load(chart_path: &Path) -> Result<Self> {
+        let difficulty_number = Self::difficulty_number(chart_path);
+        let path = chart_path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(OGKR_MUSIC_DATA_FILE_NAME);
+        let file_name = path.to_string_lossy();
+
+        let mut music_data = if path.is_file() {
+            let source = fs::read_to_string(&path).map_err(|source| ChartError::Io {
+                location: SourceLocation::file(&file_name),
+                source,
+            })?;
+            Self::parse(&source, difficulty_number).map_err(|e| e.with_file(&file_name))?
+        } else {
+            Self::default()
+        };
+        if let Some(number) = difficulty_number {
+            music_data.difficulty = OGKR_DIFFICULTIES[number].to_string();
+        }
+
+        Ok(music_data)
+    }
+
+    /// Only the `LEVEL` line of `difficulty_number` is used.
+    fn parse(source: &str, difficulty_number: Option<usize>) -> Result<Self> {
+        let mut music_data = Self::default();
+
+        for (index, raw) in source.lines().enumerate() {
+            let mut values = raw.split('\t').map(str::trim);
+            let key = values.next().unwrap_or_default();
+            let values = values.collect::<Vec<_>>();
+            let location = |value: &str| {
+                let offset = value.as_ptr() as usize - raw.as_ptr() as usize;
+                SourceLocation::new(index + 1, raw[..offset].chars().count() + 1)
+            };
+            let invalid = |expected: &'static str| ChartError::InvalidValue {
+                location: location(raw.trim_start()),
+                expected,
+                value: raw.trim().to_string(),
+            };
+            let number = |expected: &'static str, value: &str| {
+                value.parse::<f32>().map_err(|_| ChartError::InvalidValue {
+                    location: location(value),
+                    expected,
+                    value: value.to_string(),
+                })
+            };
+
+            match (key, &values[..]) {
+                ("", _) => (),
+                ("TITLE", [title]) => music_data.title = title.to_string(),
+                ("ARTIST", [artist]) => music_data.artist = artist.to_string(),
+                ("AUDIO", [audio_filename]) => {
+                    music_data.audio_filename = audio_filename.to_string()
+                }
+                ("OFFSET", [offset]) => {
+                    music_data.audio_offset = number("offset", offset)? / 1000.0
+                }
+                ("PREVIEW", [start, length]) => {
+                    music_data.preview_start = number("preview start", start)? / 1000.0;
+                    music_data.preview_length = number("preview length", length)? / 1000.0;
+                }
+                ("LEVEL", [difficulty, level]) => {
+                    let level = number("level", level)?;
+                    let difficulty = difficulty
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|n| *n < OGKR_DIFFICULTIES.len())
+                        .ok_or_else(|| ChartError::InvalidValue {
+                            location: location(difficulty),
+                            expected: "difficulty number",
+                            value: difficulty.to_string(),
+                        })?;
+                    if Some(difficulty) == difficulty_number {
+                        music_data.level = Some(level);
+                    }
+                }
+                ("TITLE" | "ARTIST" | "AUDIO" | "OFFSET", _) => return Err(invalid("value")),
+                ("PREVIEW", _) => return Err(invalid("`PREVIEW<tab>start<tab>length`")),
+                ("LEVEL", _) => return Err(invalid("`LEVEL<tab>difficulty<tab>level`")),
+                (key, _) => log::warn!(
+                    "{}: Ignoring unknown music data key `{}`",
+                    location(key),
+                    key
+                ),
+            }
+        }
+
+        Ok(music_data)
+    }
+}
+
+struct OgkrChartCreator<'a> {
+    source: OgkrSource<'a>,
     ogkr: Ogkr,
+    music_data: OgkrMusicData,
+    tick_resolution: u32,
     z_position_calculator: ZPositionCalculator,
     x_position_calculator: XPositionCalculator,
 }
 
-impl OgkrChartCreator {
-    fn new(ogkr: Ogkr) -> Self {
-        // XXX TODO: Properly handle unwraps or verify this info inside ogkr parser.
-        let starting_time_signature = ogkr.header.meter_definition.unwrap();
+impl<'a> OgkrChartCreator<'a> {
+    fn new(source: &'a str, ogkr: Ogkr, music_data: OgkrMusicData) -> Result<Self> {
+        let starting_time_signature = ogkr
+            .header
+            .meter_definition
+            .ok_or_else(|| Self::missing_header_field("MET_DEF"))?;
         let starting_time_signature = TimeSignature {
             num_beats: starting_time_signature.num_beats,
             note_value: starting_time_signature.note_value,
         };
-        // XXX TODO FIXME: unwrap bpm def bits and other header data inside ogkr parse analysis.
-        let starting_bpm = f32::from_bits(ogkr.header.bpm_definition.unwrap().first) as _;
-        let starting_speed_multiplier = 1.0;
-        let subdivision = ogkr.header.tick_resolution.unwrap().resolution;
-
-        let num_measures = ogkr.extra_metadata.num_measures as usize + 1;
+        // XXX TODO FIXME: unwrap bpm def bits inside ogkr parse analysis.
+        let starting_bpm = f32::from_bits(
+            ogkr.header
+                .bpm_definition
+                .ok_or_else(|| Self::missing_header_field("BPM_DEF"))?
+                .first,
+        );
+        let tick_resolution = ogkr
+            .header
+            .tick_resolution
+            .ok_or_else(|| Self::missing_header_field("TRESOLUTION"))?
+            .resolution;
 
-        let z_position_calculator = Self::create_z_position_calculator(
+        let timing_map = Self::create_timing_map(
             &ogkr.composition,
             starting_time_signature,
             starting_bpm,
-            starting_speed_multiplier,
-            subdivision,
-            num_measures,
+            tick_resolution,
+            Time(music_data.audio_offset),
         );
+        let z_position_calculator = ZPositionCalculator::new(timing_map, OGKR_Z_BASE_SPEED);
 
-        let x_resolution = ogkr.header.x_resolution.unwrap().resolution;
+        let x_resolution = ogkr
+            .header
+            .x_resolution
+            .ok_or_else(|| Self::missing_header_field("XRESOLUTION"))?
+            .resolution;
         let x_position_calculator = XPositionCalculator::new(x_resolution as _);
 
-        Self {
+        let mut creator = Self {
+            source: OgkrSource { source },
             ogkr,
+            music_data,
+            tick_resolution,
             z_position_calculator,
             x_position_calculator,
+        };
+
+        // Soflan durations are only known once measure timings are.
+        let soflans = creator.create_soflans();
+        creator.z_position_calculator.set_soflans(&soflans);
+
+        Ok(creator)
+    }
+
+    /// Missing fields have no line, the file is filled in by the caller.
+    fn missing_header_field(field: &'static str) -> ChartError {
+        ChartError::MissingHeaderField {
+            location: SourceLocation::default(),
+            field,
         }
     }
 
+    /// `start` is the time the lane starts at.
+    fn get_lane(
+        &self,
+        start: ogkr_analysis::TimingPoint,
+        lane_id: ogkr_analysis::LaneId,
+    ) -> Result<&ogkr_analysis::Lane> {
+        self.ogkr
+            .track
+            .get_lane(lane_id)
+            .ok_or_else(|| ChartError::DanglingLaneId {
+                location: self.source.locate(OGKR_LANE_START_COMMANDS, start),
+                lane_id: format!("{:?}", lane_id),
+            })
+    }
+
     fn x_position(&self, position: ogkr_analysis::XPosition) -> f32 {
         self.x_position_calculator.x_position_at(
             position.position as _,
@@ -108,57 +339,103 @@ impl OgkrChartCreator {
 
     fn z_position(&self, time: ogkr_analysis::TimingPoint) -> ZPosition {
         self.z_position_calculator
-            .z_position_at(time.measure as _, time.beat_offset as _)
+            .z_position_at(self.beat_position(time.measure, time.beat_offset))
+    }
+
+    /// Ticks are counted from the start of the measure, a measure has `tick_resolution` ticks.
+    fn beat_position(&self, measure: u32, tick: u32) -> BeatPosition {
+        Self::beat_position_with(
+            measure,
+            tick,
+            self.tick_resolution,
+            self.z_position_calculator
+                .timing_map()
+                .time_signature_at(measure as _),
+        )
     }
 
-    fn create_z_position_calculator(
+    fn beat_position_with(
+        measure: u32,
+        tick: u32,
+        tick_resolution: u32,
+        time_signature: TimeSignature,
+    ) -> BeatPosition {
+        BeatPosition::new(
+            measure as _,
+            tick as f32 / tick_resolution as f32 * time_signature.num_beats as f32,
+        )
+    }
+
+    /// Time that is `ticks` after `time`, which may be in a later measure.
+    fn time_after_ticks(&self, time: ogkr_analysis::TimingPoint, ticks: u32) -> Time {
+        let total_ticks = time.measure as u64 * self.tick_resolution as u64
+            + time.beat_offset as u64
+            + ticks as u64;
+        let measure = total_ticks / self.tick_resolution as u64;
+        let tick = total_ticks % self.tick_resolution as u64;
+
+        self.z_position_calculator
+            .timing_map()
+            .time_at(self.beat_position(measure as _, tick as _))
+    }
+
+    fn create_soflans(&self) -> Vec<Soflan> {
+        self.ogkr
+            .composition
+            .soflans
+            .iter()
+            .map(|(t, s)| {
+                let time = self.z_position(*t).time;
+                Soflan {
+                    time,
+                    duration: self.time_after_ticks(*t, s.duration).0 - time.0,
+                    speed_multiplier: s.speed_multiplier,
+                }
+            })
+            .collect()
+    }
+
+    fn create_timing_map(
         composition: &ogkr_analysis::Composition,
         starting_time_signature: TimeSignature,
-        starting_bpm: u32,
-        starting_speed_multiplier: f32,
-        subdivision: u32,
-        num_measures: usize,
-    ) -> ZPositionCalculator {
-        let mut current_bpm_change = composition.bpm_changes.iter();
-        let mut current_time_signature_change = composition.meter_changes.iter();
-        let mut current_soflan = composition.soflans.iter();
-
-        let mut measure_compositions = Vec::new();
-        let mut current_measure = MeasureCompositionData {
+        starting_bpm: f32,
+        tick_resolution: u32,
+        audio_offset: Time,
+    ) -> TimingMap {
+        let meter_changes = std::iter::once(MeterChange {
+            measure: 0,
             time_signature: starting_time_signature,
-            bpm: starting_bpm,
-            speed_multiplier: starting_speed_multiplier,
-            subdivision,
-        };
-
-        for measure_index in 0..num_measures as u32 {
-            if let Some((timing_point, change)) = current_bpm_change.clone().next() {
-                // -1 because measure starts from 1.
-                if measure_index >= timing_point.measure - 1 {
-                    current_measure.bpm = change.bpm;
-                    current_bpm_change.next();
-                }
-            }
-            if let Some((timing_point, change)) = current_time_signature_change.clone().next() {
-                // -1 because measure starts from 1.
-                if measure_index >= timing_point.measure - 1 {
-                    current_measure.time_signature = change.clone().into();
-                    current_time_signature_change.next();
-                }
-            }
-            if let Some((timing_point, soflan)) = current_soflan.clone().next() {
-                // -1 because measure starts from 1.
-                if measure_index >= timing_point.measure - 1 {
-                    current_measure.speed_multiplier = soflan.speed_multiplier;
-                    current_soflan.next();
-                }
-            }
+        })
+        .chain(composition.meter_changes.iter().map(|(t, c)| MeterChange {
+            measure: t.measure as _,
+            time_signature: c.clone().into(),
+        }))
+        .collect::<Vec<_>>();
 
-            measure_compositions.push(current_measure.clone());
-        }
+        // Tempo changes need the time signature of their measure to find their beat.
+        let time_signature_at = |measure: u32| {
+            meter_changes
+                .iter()
+                .rev()
+                .find(|c| c.measure <= measure as i32)
+                .map_or(starting_time_signature, |c| c.time_signature)
+        };
+        let tempo_changes = std::iter::once(TempoChange {
+            position: BeatPosition::default(),
+            bpm: starting_bpm,
+        })
+        .chain(composition.bpm_changes.iter().map(|(t, c)| TempoChange {
+            position: Self::beat_position_with(
+                t.measure,
+                t.beat_offset,
+                tick_resolution,
+                time_signature_at(t.measure),
+            ),
+            bpm: c.bpm as _,
+        }))
+        .collect::<Vec<_>>();
 
-        // XXX TODO: Properly get audio offset.
-        ZPositionCalculator::new(measure_compositions, Time(0.0), 1.0)
+        TimingMap::new(audio_offset, &meter_changes, &tempo_changes)
     }
 
     fn create_composition(&self) -> Composition {
@@ -180,16 +457,7 @@ impl OgkrChartCreator {
                 time_signature: c.clone().into(),
             })
             .collect();
-        let soflans = composition
-            .soflans
-            .iter()
-            .map(|(t, s)| Soflan {
-                time: self.z_position(*t).time,
-                // XXX TODO: Properly fill this(in seconds).
-                duration: 1.0,
-                speed_multiplier: s.speed_multiplier,
-            })
-            .collect();
+        let soflans = self.create_soflans();
 
         Composition {
             bpm_changes,
@@ -204,6 +472,7 @@ impl OgkrChartCreator {
             time: z_position.time,
             z: z_position.z,
             x: self.x_position(track_position.x),
+            y: 0.0,
         }
     }
 
@@ -224,252 +493,766 @@ impl OgkrChartCreator {
             .collect()
     }
 
-    fn create_single_lane(
+    /// Every wall lane is kept separate, walls can start, stop or split.
+    fn create_wall_lanes(
         &self,
         lanes: &BTreeMap<ogkr_analysis::TimingPoint, ogkr_analysis::LaneId>,
-    ) -> Lane {
-        let points = lanes
+    ) -> Result<Vec<Vec<TrackPosition>>> {
+        lanes
             .iter()
-            .flat_map(|(_, lane_id)| {
-                // XXX TODO: Properly handle unwrap here.
-                let lane = self.ogkr.track.get_lane(*lane_id).unwrap();
-
-                self.create_points_from_lane(lane)
+            .map(|(start, lane_id)| {
+                Ok(self.create_points_from_lane(self.get_lane(*start, *lane_id)?))
             })
-            .collect();
-
-        Lane { points }
+            .collect()
     }
 
+    /// Every OGKR lane becomes one segment named after its lane id.
     fn create_lanes(
         &self,
         lanes: &BTreeMap<ogkr_analysis::TimingPoint, Vec<ogkr_analysis::LaneId>>,
-    ) -> Vec<Lane> {
+    ) -> Result<Vec<(String, Vec<TrackPosition>)>> {
         lanes
             .iter()
-            .flat_map(|(_, lane_ids)| {
-                lane_ids
-                    .iter()
-                    .map(|lane_id| {
-                        // XXX TODO: Properly handle unwrap here.
-                        let lane = self.ogkr.track.get_lane(*lane_id).unwrap();
-                        let points = self.create_points_from_lane(lane);
-                        Lane { points }
-                    })
-                    .collect::<Vec<_>>()
+            .flat_map(|(start, lane_ids)| lane_ids.iter().map(move |lane_id| (start, lane_id)))
+            .map(|(start, lane_id)| {
+                let points = self.create_points_from_lane(self.get_lane(*start, *lane_id)?);
+                Ok((lane_id.0.to_string(), points))
             })
             .collect()
     }
 
-    fn create_platform(&self) -> Platform {
+    fn create_platforms(&self) -> Result<Vec<Platform>> {
         let track = &self.ogkr.track;
-        let points_left = self.create_single_lane(&track.walls_left).points;
-        let points_right = self.create_single_lane(&track.walls_right).points;
+        let walls_left = self.create_wall_lanes(&track.walls_left)?;
+        let walls_right = self.create_wall_lanes(&track.walls_right)?;
 
-        Platform {
-            points_left,
-            points_right,
-        }
+        Ok(pair_walls(&walls_left, &walls_right))
     }
 
-    fn create_track(&self) -> Track {
-        let platforms = vec![self.create_platform()];
+    fn create_track(&self) -> Result<Track> {
+        let platforms = self.create_platforms()?;
 
-        let mut lanes = HashMap::new();
+        let mut lanes = Lanes::default();
         let track = &self.ogkr.track;
-        lanes.insert(
-            ogkr_analysis::LaneType::Left.into(),
-            self.create_lanes(&track.lanes_left),
-        );
-        lanes.insert(
-            ogkr_analysis::LaneType::Center.into(),
-            self.create_lanes(&track.lanes_center),
-        );
-        lanes.insert(
-            ogkr_analysis::LaneType::Right.into(),
-            self.create_lanes(&track.lanes_right),
-        );
-        lanes.insert(
-            ogkr_analysis::LaneType::Enemy.into(),
-            self.create_lanes(&track.enemy_lanes),
-        );
+        for (lane_type, ogkr_lanes) in [
+            (LaneType::Left, &track.lanes_left),
+            (LaneType::Center, &track.lanes_center),
+            (LaneType::Right, &track.lanes_right),
+            (LaneType::Enemy, &track.enemy_lanes),
+        ] {
+            for (name, points) in self.create_lanes(ogkr_lanes)? {
+                lanes.add(lane_type, name, points);
+            }
+        }
+        lanes.connect();
+
+        Ok(Track { platforms, lanes })
+    }
 
-        Track { platforms, lanes }
+    /// x of the enemy at `time`, which fires `bullet`.
+    fn enemy_x_at(&self, lanes: &Lanes, time: Time, bullet: &ogkr_analysis::Bullet) -> Result<f32> {
+        lanes
+            .of_type(LaneType::Enemy)
+            .find_map(|lane| lane.x_at(time))
+            .ok_or_else(|| ChartError::MissingEnemyLane {
+                location: self
+                    .source
+                    .locate(OGKR_BULLET_COMMANDS, bullet.position.time),
+                time: time.0,
+            })
+    }
+
+    /// Seconds a bullet with palette speed `speed` is in flight. Palette speeds scale the scroll
+    /// speed and the enemy stands as far ahead as the track scrolls in one second at base speed, so
+    /// a bullet with speed 1.0 flies for one second.
+    fn bullet_duration(speed: f32) -> f32 {
+        1.0 / speed
     }
 
-    fn create_evade_notes(&self) -> Vec<EvadeNote> {
+    /// z a bullet hitting at `end` is fired from at `trigger_time`. It scrolls `speed` times as fast
+    /// as the track, soflans included.
+    fn bullet_start_z(&self, trigger_time: Time, end: &TrackPosition, speed: f32) -> f32 {
+        let trigger_z = self.z_position_calculator.z_at(trigger_time);
+        trigger_z + (end.z - trigger_z) * speed
+    }
+
+    fn create_evade_notes(&self, lanes: &Lanes) -> Result<Vec<EvadeNote>> {
         let mut evade_notes = Vec::new();
         for bullet in self.ogkr.bullets.all_bullets() {
-            // XXX: Remove this unwrap. Make this return the raw value directly, the id should
-            // always be valid as static analysis is already done when parsing.
             let palette = self
                 .ogkr
                 .bullets
                 .get_bullet_palette(&bullet.palette_id)
-                .unwrap();
-
-            if palette.shooter == BulletShooter::Enemy || palette.target == BulletTarget::Player {
-                log::debug!(
-                    "Skipping unsupported bullet palette {:?} with {:?} {:?}",
-                    &palette.id,
-                    palette.shooter,
-                    palette.target
-                );
-                continue;
-            }
+                .ok_or_else(|| ChartError::DanglingBulletPalette {
+                    location: self
+                        .source
+                        .locate(OGKR_BULLET_COMMANDS, bullet.position.time),
+                    palette_id: format!("{:?}", bullet.palette_id),
+                })?;
 
-            let end_position = self.create_track_position(bullet.position);
+            let x_offset = self.x_position(ogkr_analysis::XPosition {
+                position: palette.x_offset,
+                offset: 0,
+            });
+            let mut end_position = self.create_track_position(bullet.position);
+            end_position.x += x_offset;
 
-            // XXX TODO: Determine a nice number for this.
-            let speed_factor = 1.5;
-            let duration = palette.speed * speed_factor;
+            let speed = if palette.speed > 0.0 {
+                palette.speed
+            } else {
+                log::warn!(
+                    "Bullet palette {:?} has speed {}, using 1.0",
+                    palette.id,
+                    palette.speed
+                );
+                1.0
+            };
+            let duration = Self::bullet_duration(speed);
+            let trigger_time = Time(end_position.time.0 - duration);
 
-            // XXX TODO: Properly support time arithmetic.
-            // Initial Z axis position of the bullet.
-            let start_time = Time(end_position.time.0 + duration);
-            let start_z = end_position.z + duration;
+            let start_time = trigger_time;
+            let start_z = self.bullet_start_z(start_time, &end_position, speed);
 
-            let start_position = match palette.shooter {
-                BulletShooter::EndPosition => TrackPosition {
-                    time: start_time,
-                    z: start_z,
-                    x: end_position.x,
-                },
-                BulletShooter::Center => TrackPosition {
-                    time: start_time,
-                    z: start_z,
-                    x: 0.0,
-                },
-                _ => todo!(),
+            let start_x = match palette.shooter {
+                BulletShooter::EndPosition => end_position.x,
+                BulletShooter::Center => 0.0,
+                BulletShooter::Enemy => self.enemy_x_at(lanes, trigger_time, bullet)?,
+            };
+            let start_position = TrackPosition {
+                time: start_time,
+                z: start_z,
+                x: start_x,
+                y: end_position.y,
             };
 
-            let trigger_time = Time(end_position.time.0 - duration);
+            let target = match palette.target {
+                BulletTarget::FixedField => MovementTarget::Fixed,
+                BulletTarget::Player => MovementTarget::Player { x_offset },
+            };
 
-            // XXX TODO: Properly set movement parameters.
             let movement = NoteMovement {
                 start: start_position,
                 end: end_position,
+                target,
                 trigger_time,
                 duration,
             };
 
             let evade_note = EvadeNote {
-                // XXX TODO: Properly differentiate different bullet types.
-                ty: EvadeNoteType(0),
+                id: NoteId::default(),
+                ty: palette.bullet_type.into(),
+                size: palette.size.into(),
+                speed,
                 movement,
+                animations: Vec::new(),
             };
 
             evade_notes.push(evade_note);
         }
 
-        evade_notes
-    }
-
-    fn hit_note_type(lane: ogkr_analysis::LaneType, is_critical: bool) -> HitNoteType {
-        let value = match lane {
-            ogkr_analysis::LaneType::WallLeft => 0,
-            ogkr_analysis::LaneType::WallRight => 1,
-            ogkr_analysis::LaneType::Left => 2,
-            ogkr_analysis::LaneType::Center => 3,
-            ogkr_analysis::LaneType::Right => 4,
-            _ => 99,
-        };
-        let value = if is_critical { value + 10 } else { value };
-        HitNoteType(value)
-    }
-
-    fn contact_note_type() -> ContactNoteType {
-        ContactNoteType(0)
+        Ok(evade_notes)
     }
 
-    // XXX TODO: Support other bullet types.
-    fn evade_note_type() -> EvadeNoteType {
-        EvadeNoteType(0)
+    /// `time` is the time the note starts at.
+    fn hit_note_type(
+        &self,
+        lane: ogkr_analysis::LaneType,
+        time: Option<ogkr_analysis::TimingPoint>,
+    ) -> Result<HitNoteType> {
+        match lane {
+            ogkr_analysis::LaneType::WallLeft => Ok(HitNoteType::WallLeft),
+            ogkr_analysis::LaneType::WallRight => Ok(HitNoteType::WallRight),
+            ogkr_analysis::LaneType::Left => Ok(HitNoteType::Left),
+            ogkr_analysis::LaneType::Center => Ok(HitNoteType::Center),
+            ogkr_analysis::LaneType::Right => Ok(HitNoteType::Right),
+            _ => Err(ChartError::UnsupportedNoteLane {
+                location: time.map_or_else(SourceLocation::default, |time| {
+                    self.source.locate(OGKR_NOTE_COMMANDS, time)
+                }),
+                lane_type: format!("{:?}", lane),
+            }),
+        }
     }
 
-    fn create_hold_notes<'a>(
+    fn create_hold_notes<'b>(
         &self,
-        hold_notes: impl Iterator<Item = &'a ogkr_analysis::HoldNote>,
-    ) -> Vec<HoldNote> {
+        hold_notes: impl Iterator<Item = &'b ogkr_analysis::HoldNote>,
+    ) -> Result<Vec<HoldNote>> {
         hold_notes
-            .map(|h| HoldNote {
-                ty: Self::hit_note_type(h.lane_type, h.is_critical),
-                points: h
-                    .points
-                    .iter()
-                    .map(|p| self.create_track_position(*p))
-                    .collect(),
+            .map(|h| {
+                Ok(HoldNote {
+                    id: NoteId::default(),
+                    ty: self.hit_note_type(h.lane_type, h.points.first().map(|p| p.time))?,
+                    is_critical: h.is_critical,
+                    points: h
+                        .points
+                        .iter()
+                        .map(|p| self.create_track_position(*p))
+                        .collect(),
+                })
             })
             .collect()
     }
 
-    fn create_notes(&self) -> Notes {
+    fn create_notes(&self, lanes: &Lanes) -> Result<Notes> {
         let notes = &self.ogkr.notes;
 
         let hits = notes
             .all_taps()
-            .map(|t| HitNote {
-                ty: Self::hit_note_type(t.lane_type, t.is_critical),
-                position: self.create_track_position(t.position),
+            .map(|t| {
+                Ok(HitNote {
+                    id: NoteId::default(),
+                    ty: self.hit_note_type(t.lane_type, Some(t.position.time))?,
+                    is_critical: t.is_critical,
+                    position: self.create_track_position(t.position),
+                })
             })
-            .collect();
+            .collect::<Result<_>>()?;
 
         let contacts = notes
             .all_bells()
             .map(|b| ContactNote {
-                ty: Self::contact_note_type(),
+                id: NoteId::default(),
+                ty: ContactNoteType::Primary,
                 position: self.create_track_position(b.position),
             })
             .collect();
         let flicks = notes
             .all_flicks()
-            .map(|f| FlickNote {
-                direction: f.direction.into(),
-                position: self.create_track_position(f.position),
+            .map(|f| {
+                let position = self.create_track_position(f.position);
+                // OGKR flicks only have a direction, they end where they start.
+                FlickNote {
+                    id: NoteId::default(),
+                    direction: f.direction.into(),
+                    position,
+                    end_x: position.x,
+                }
             })
             .collect();
 
-        let holds = self.create_hold_notes(notes.all_holds());
+        let holds = self.create_hold_notes(notes.all_holds())?;
 
-        let evades = self.create_evade_notes();
+        let evades = self.create_evade_notes(lanes)?;
 
-        Notes {
+        // OGKR has no target notes.
+        Ok(Notes {
             hits,
             holds,
             contacts,
             evades,
             flicks,
+            ..Default::default()
+        })
+    }
+
+    /// `CREATOR` and `BPM_DEF` come from the chart, everything else from the music data.
+    fn create_header(&self) -> Header {
+        let header = &self.ogkr.header;
+        let music_data = self.music_data.clone();
+        Header {
+            chart_designer: header.creator.clone().unwrap_or_default(),
+            bpm_range: header.bpm_definition.map(|definition| BpmRange {
+                min: f32::from_bits(definition.minimum),
+                max: f32::from_bits(definition.maximum),
+            }),
+            title: music_data.title,
+            artist: music_data.artist,
+            difficulty: music_data.difficulty,
+            level: music_data.level,
+            preview_start: music_data.preview_start,
+            preview_length: music_data.preview_length,
+            audio_filename: music_data.audio_filename,
+            audio_offset: music_data.audio_offset,
         }
     }
 
-    fn create(self) -> Chart {
-        Chart {
-            // XXX TODO: Properly fill these.
-            header: Header::default(),
-            metadata: Metadata::default(),
+    fn create(self) -> Result<Chart> {
+        let track = self.create_track()?;
+        let mut notes = self.create_notes(&track.lanes)?;
+        notes.assign_ids();
+
+        Ok(Chart {
+            header: self.create_header(),
+            metadata: Metadata {
+                base_speed: OGKR_Z_BASE_SPEED,
+                ..Default::default()
+            },
+            // Note appearance is not part of OGKR charts.
+            note_styles: NoteStyles::default(),
             data: ChartData {
-                track: self.create_track(),
-                notes: self.create_notes(),
+                track,
+                notes,
+                animations: AnimationLibrary::default(),
                 composition: self.create_composition(),
             },
             utils: ChartUtils {
                 z_position_calculator: self.z_position_calculator,
             },
+        })
+    }
+}
+
+/// Errors of the ogkr crate only come with a message, they have no line.
+/// Wall of either side, `index` is into the walls of its side.
+#[derive(Clone, Copy)]
+enum WallSide {
+    Left(usize),
+    Right(usize),
+}
+
+/// Pairs every left wall with the right wall that bounds it, for every span of time no wall of
+/// either side is between the two.
+///
+/// Sweeps over the times walls start, end or bend at. All walls are straight between two of these
+/// times, so their order is only checked at both ends of each span and spans are split where two
+/// walls cross. Spans in which the same walls are neighbours are merged into one platform.
+fn pair_walls(
+    walls_left: &[Vec<TrackPosition>],
+    walls_right: &[Vec<TrackPosition>],
+) -> Vec<Platform> {
+    let walls = walls_left
+        .iter()
+        .enumerate()
+        .map(|(i, wall)| (WallSide::Left(i), wall.as_slice()))
+        .chain(
+            walls_right
+                .iter()
+                .enumerate()
+                .map(|(i, wall)| (WallSide::Right(i), wall.as_slice())),
+        )
+        .filter_map(|(side, wall)| {
+            let (start, end) = time_range(wall)?;
+            Some((start.0, end.0, side, wall))
+        })
+        .collect::<Vec<_>>();
+
+    let mut times = walls
+        .iter()
+        .flat_map(|(_, _, _, wall)| wall.iter().map(|p| p.time.0))
+        .collect::<Vec<_>>();
+    times.sort_by(f32::total_cmp);
+    times.dedup_by(|a, b| *a - *b < TIME_EPSILON);
+
+    // Walls by start time, walls are active from the span they start in until they end.
+    let mut by_start = (0..walls.len()).collect::<Vec<_>>();
+    by_start.sort_by(|a, b| walls[*a].0.total_cmp(&walls[*b].0));
+    let mut next_wall = 0;
+    let mut active: Vec<usize> = Vec::new();
+
+    // Pairs of the previous span, with the time they started at.
+    let mut open: BTreeMap<(usize, usize), Time> = BTreeMap::new();
+    let mut platforms = Vec::new();
+    let mut close = |pair: (usize, usize), start: Time, end: Time| {
+        let (left, right) = (&walls_left[pair.0], &walls_right[pair.1]);
+        platforms.push((
+            start,
+            Platform {
+                left: PlatformEdge::from_points(&clip_track_positions(left, start, end)),
+                right: PlatformEdge::from_points(&clip_track_positions(right, start, end)),
+            },
+        ));
+    };
+
+    for span in times.windows(2) {
+        let (span_start, span_end) = (span[0], span[1]);
+        while let Some(&index) = by_start.get(next_wall) {
+            if walls[index].0 > span_start + TIME_EPSILON {
+                break;
+            }
+            active.push(index);
+            next_wall += 1;
         }
+        active.retain(|&index| walls[index].1 >= span_end - TIME_EPSILON);
+
+        // Every pair of active walls that swaps order crosses once within the span.
+        let mut cuts = vec![span_start, span_end];
+        for (i, &a) in active.iter().enumerate() {
+            for &b in &active[i + 1..] {
+                let (a, b) = (walls[a].3, walls[b].3);
+                let start = x_at(a, Time(span_start)) - x_at(b, Time(span_start));
+                let end = x_at(a, Time(span_end)) - x_at(b, Time(span_end));
+                if start * end < 0.0 {
+                    let t = start / (start - end);
+                    cuts.push(span_start + (span_end - span_start) * t);
+                }
+            }
+        }
+        cuts.sort_by(f32::total_cmp);
+        cuts.dedup_by(|a, b| *a - *b < TIME_EPSILON);
+
+        for cut in cuts.windows(2) {
+            let (start, end) = (Time(cut[0]), Time(cut[1]));
+            let middle = Time((start.0 + end.0) / 2.0);
+            let mut order = active
+                .iter()
+                .map(|&index| (x_at(walls[index].3, middle), walls[index].2))
+                .collect::<Vec<_>>();
+            order.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let pairs = order
+                .windows(2)
+                .filter_map(|w| match (w[0].1, w[1].1) {
+                    (WallSide::Left(left), WallSide::Right(right)) => Some((left, right)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            let ended = open
+                .keys()
+                .filter(|pair| !pairs.contains(pair))
+                .copied()
+                .collect::<Vec<_>>();
+            for pair in ended {
+                let pair_start = open.remove(&pair).unwrap();
+                close(pair, pair_start, start);
+            }
+            for pair in pairs {
+                open.entry(pair).or_insert(start);
+            }
+        }
+    }
+    let end = Time(times.last().copied().unwrap_or_default());
+    for (pair, start) in open {
+        close(pair, start, end);
     }
+
+    platforms.sort_by(|a, b| a.0 .0.total_cmp(&b.0 .0));
+    platforms.into_iter().map(|(_, p)| p).collect()
 }
 
-fn parse_ogkr(file_name: &str) -> Result<Ogkr> {
-    let source = fs::read_to_string(file_name)?;
-    let tokens = tokenize(&source)?;
-    let raw = parse_tokens(tokens)?;
-    let ogkr = parse_raw_ogkr(raw)?;
+fn ogkr_error(error: impl std::fmt::Display) -> ChartError {
+    ChartError::Ogkr {
+        location: SourceLocation::default(),
+        message: error.to_string(),
+    }
+}
+
+fn parse_ogkr(source: &str) -> Result<Ogkr> {
+    let tokens = tokenize(source).map_err(ogkr_error)?;
+    let raw = parse_tokens(tokens).map_err(ogkr_error)?;
+    let ogkr = parse_raw_ogkr(raw).map_err(ogkr_error)?;
 
     Ok(ogkr)
 }
 
+/// Chart without music data, only the chart designer and tempos of the header are set.
+pub fn create_chart_from_ogkr_source(source: &str) -> Result<Chart> {
+    OgkrChartCreator::new(source, parse_ogkr(source)?, OgkrMusicData::default())?.create()
+}
+
+/// Chart whose source was read from `path`, with the music data file next to it.
+pub fn create_chart_from_ogkr_source_at(source: &str, path: &Path) -> Result<Chart> {
+    let music_data = OgkrMusicData::load(path)?;
+    OgkrChartCreator::new(source, parse_ogkr(source)?, music_data)?.create()
+}
+
 pub fn create_chart_from_ogkr_file(file_name: &str) -> Result<Chart> {
-    let ogkr = parse_ogkr(file_name)?;
-    let ogkr_chart_creator = OgkrChartCreator::new(ogkr);
-    Ok(ogkr_chart_creator.create())
+    let source = fs::read_to_string(file_name).map_err(|source| ChartError::Io {
+        location: SourceLocation::file(file_name),
+        source,
+    })?;
+    create_chart_from_ogkr_source_at(&source, Path::new(file_name))
+        .map_err(|e| e.with_file(file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+[HEADER]
+VERSION\t1\t0\t0
+
+[LANE]
+LLS\t0\t0\t0\t-12
+LLE\t0\t4\t0\t-12
+WLS\t1\t2\t480\t-24
+
+[BULLET]
+BLT\tA\t2\t480\t0\tNML
+";
+
+    fn time(measure: u32, beat_offset: u32) -> ogkr_analysis::TimingPoint {
+        ogkr_analysis::TimingPoint {
+            measure,
+            beat_offset,
+        }
+    }
+
+    #[test]
+    fn locates_first_matching_command() {
+        let source = OgkrSource { source: SOURCE };
+
+        let lane = source.locate(OGKR_LANE_START_COMMANDS, time(2, 480));
+        assert_eq!((lane.line, lane.column), (Some(7), Some(1)));
+
+        let bullet = source.locate(OGKR_BULLET_COMMANDS, time(2, 480));
+        assert_eq!(bullet.line, Some(10));
+    }
+
+    fn wall(points: &[(f32, f32)]) -> Vec<TrackPosition> {
+        points.iter().map(|&(time, x)| position(time, x)).collect()
+    }
+
+    /// Time range and x of both edges at the start of every platform.
+    fn platform_spans(platforms: &[Platform]) -> Vec<(f32, f32, f32, f32)> {
+        platforms
+            .iter()
+            .map(|platform| {
+                let (start, end) = platform.time_range().unwrap();
+                let left = platform.points_left()[0].x;
+                let right = platform.points_right()[0].x;
+                (start.0, end.0, left, right)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn walls_between_split_platforms() {
+        let walls_left = [
+            wall(&[(0.0, -2.0), (4.0, -2.0)]),
+            wall(&[(1.0, 0.5), (2.0, 0.5)]),
+        ];
+        let walls_right = [
+            wall(&[(0.0, 2.0), (4.0, 2.0)]),
+            wall(&[(1.0, -0.5), (2.0, -0.5)]),
+        ];
+
+        let platforms = pair_walls(&walls_left, &walls_right);
+        assert_eq!(
+            platform_spans(&platforms),
+            [
+                (0.0, 1.0, -2.0, 2.0),
+                (1.0, 2.0, -2.0, -0.5),
+                (1.0, 2.0, 0.5, 2.0),
+                (2.0, 4.0, -2.0, 2.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn platforms_merge_when_inner_walls_end() {
+        let walls_left = [
+            wall(&[(0.0, -3.0), (4.0, -3.0)]),
+            wall(&[(0.0, 1.0), (2.0, 1.0)]),
+        ];
+        let walls_right = [
+            wall(&[(0.0, -1.0), (2.0, -1.0)]),
+            wall(&[(0.0, 3.0), (4.0, 3.0)]),
+        ];
+
+        let platforms = pair_walls(&walls_left, &walls_right);
+        assert_eq!(
+            platform_spans(&platforms),
+            [
+                (0.0, 2.0, -3.0, -1.0),
+                (0.0, 2.0, 1.0, 3.0),
+                (2.0, 4.0, -3.0, 3.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn walls_are_checked_over_the_whole_range() {
+        // The inner walls only move onto the platform after its midpoint.
+        let walls_left = [
+            wall(&[(0.0, -2.0), (4.0, -2.0)]),
+            wall(&[(0.0, 4.0), (2.0, 4.0), (3.0, 0.5), (4.0, 0.5)]),
+        ];
+        let walls_right = [
+            wall(&[(0.0, 2.0), (4.0, 2.0)]),
+            wall(&[(0.0, 3.0), (2.0, 3.0), (3.0, -0.5), (4.0, -0.5)]),
+        ];
+
+        let spans = platform_spans(&pair_walls(&walls_left, &walls_right));
+        let expected = [
+            (0.0, 2.0 + 1.0 / 3.5, -2.0, 2.0),
+            (2.0 + 1.0 / 3.5, 4.0, -2.0, 2.0),
+            (2.0 + 2.0 / 3.5, 4.0, 2.0, 2.0),
+        ];
+        assert_eq!(spans.len(), expected.len());
+        for (span, expected) in spans.iter().zip(expected) {
+            assert!((span.0 - expected.0).abs() < 1e-4, "{:?}", spans);
+            assert!((span.1 - expected.1).abs() < 1e-4, "{:?}", spans);
+            assert!((span.2 - expected.2).abs() < 1e-4, "{:?}", spans);
+            assert!((span.3 - expected.3).abs() < 1e-4, "{:?}", spans);
+        }
+    }
+
+    #[test]
+    fn faster_bullets_take_less_time() {
+        assert_eq!(OgkrChartCreator::bullet_duration(1.0), 1.0);
+        assert_eq!(OgkrChartCreator::bullet_duration(2.0), 0.5);
+        assert_eq!(OgkrChartCreator::bullet_duration(0.5), 2.0);
+    }
+
+    fn creator(source: &str) -> OgkrChartCreator<'_> {
+        let ogkr = parse_ogkr(source).unwrap();
+        OgkrChartCreator::new(source, ogkr, OgkrMusicData::default()).unwrap()
+    }
+
+    fn position(time: f32, x: f32) -> TrackPosition {
+        TrackPosition {
+            time: Time(time),
+            z: time,
+            x,
+            y: 0.0,
+        }
+    }
+
+    #[test]
+    fn bullets_scroll_faster_than_the_track() {
+        let source = format!("{}{}", HEADER, SOURCE);
+        let creator = creator(&source);
+        let end = position(3.0, 0.0);
+
+        // Every bullet is fired one second of scrolling ahead of the hit bar.
+        for speed in [0.5, 1.0, 2.0] {
+            let trigger_time = Time(end.time.0 - OgkrChartCreator::bullet_duration(speed));
+            let start_z = creator.bullet_start_z(trigger_time, &end, speed);
+            let trigger_z = creator.z_position_calculator.z_at(trigger_time);
+            assert!((start_z - trigger_z - OGKR_Z_BASE_SPEED).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn enemy_bullets_need_an_enemy_lane() {
+        let source = format!("{}{}", HEADER, SOURCE);
+        let creator = creator(&source);
+        let bullet = ogkr_analysis::Bullet {
+            palette_id: ogkr_analysis::BulletPaletteId("A".to_string()),
+            position: ogkr_analysis::TrackPosition {
+                time: time(2, 480),
+                x: ogkr_analysis::XPosition {
+                    position: 0,
+                    offset: 0,
+                },
+            },
+        };
+
+        let mut lanes = Lanes::default();
+        lanes.add(
+            LaneType::Enemy,
+            "0",
+            vec![position(0.0, -1.0), position(2.0, 1.0)],
+        );
+        let x = creator.enemy_x_at(&lanes, Time(1.0), &bullet).unwrap();
+        assert_eq!(x, 0.0);
+
+        let error = creator.enemy_x_at(&lanes, Time(2.5), &bullet).unwrap_err();
+        assert!(matches!(error, ChartError::MissingEnemyLane { .. }));
+        // Line of the `BLT` command after the header.
+        assert_eq!(error.location().line, Some(17));
+    }
+
+    #[test]
+    fn unknown_objects_have_no_line() {
+        let source = OgkrSource { source: SOURCE };
+        let location = source.locate(OGKR_NOTE_COMMANDS, time(2, 480));
+        assert_eq!(location, SourceLocation::default());
+        assert_eq!(location.to_string(), "<chart>");
+    }
+
+    const HEADER: &str = "\
+[HEADER]
+VERSION\t1\t0\t0
+CREATOR\tDesigner
+BPM_DEF\t150.000\t150.000\t120.000\t180.000
+MET_DEF\t4\t4
+TRESOLUTION\t1920
+XRESOLUTION\t4096
+";
+
+    const MUSIC_DATA: &str = "\
+TITLE\tSong
+ARTIST\tComposer
+AUDIO\tsong.ogg
+OFFSET\t250
+PREVIEW\t30000\t15000
+LEVEL\t2\t10
+LEVEL\t3\t13.5
+";
+
+    /// Writes `files` to an empty directory and returns its path.
+    fn chart_dir(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("eclale_ogkr_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (file_name, contents) in files {
+            fs::write(dir.join(file_name), contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn header_is_filled_from_music_data_and_file_name() {
+        let dir = chart_dir(
+            "header",
+            &[("8003_03.ogkr", HEADER), ("music.txt", MUSIC_DATA)],
+        );
+
+        let chart = crate::parse::load_chart(dir.join("8003_03.ogkr")).unwrap();
+        let header = &chart.header;
+        assert_eq!(header.title, "Song");
+        assert_eq!(header.artist, "Composer");
+        assert_eq!(header.chart_designer, "Designer");
+        assert_eq!(header.difficulty, "Master");
+        assert_eq!(header.level, Some(13.5));
+        let bpm_range = header.bpm_range.unwrap();
+        assert_eq!((bpm_range.min, bpm_range.max), (120.0, 180.0));
+        assert_eq!((header.preview_start, header.preview_length), (30.0, 15.0));
+        assert_eq!(header.audio_filename, "song.ogg");
+        assert_eq!(header.audio_offset, 0.25);
+        assert_eq!(chart.metadata.audio_path, Some(dir.join("song.ogg")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn charts_without_music_data_only_get_their_difficulty() {
+        let dir = chart_dir("no_music_data", &[("8003_04.ogkr", HEADER)]);
+
+        let chart = create_chart_from_ogkr_file(&dir.join("8003_04.ogkr").to_string_lossy());
+        let header = chart.unwrap().header;
+        assert_eq!(header.difficulty, "Lunatic");
+        assert_eq!((header.title.as_str(), header.level), ("", None));
+        assert_eq!(header.audio_offset, 0.0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn difficulty_number_comes_from_the_file_name() {
+        let number = |path| OgkrMusicData::difficulty_number(Path::new(path));
+        assert_eq!(number("a/0801_00.ogkr"), Some(0));
+        assert_eq!(number("0801_02.ogkr"), Some(2));
+        assert_eq!(number("0801_05.ogkr"), None);
+        assert_eq!(number("chart.ogkr"), None);
+    }
+
+    #[test]
+    fn invalid_music_data_is_located_in_its_file() {
+        let dir = chart_dir(
+            "invalid_music_data",
+            &[
+                ("8003_01.ogkr", HEADER),
+                ("music.txt", "TITLE\tSong\nOFFSET\tsoon\n"),
+            ],
+        );
+
+        let error = crate::parse::load_chart(dir.join("8003_01.ogkr")).unwrap_err();
+        assert!(matches!(error, ChartError::InvalidValue { .. }));
+        let location = error.location();
+        assert_eq!((location.line, location.column), (Some(2), Some(8)));
+        assert_eq!(
+            location.file.as_deref(),
+            Some(dir.join("music.txt").to_string_lossy().as_ref())
+        );
+
+        let error = OgkrMusicData::parse("PREVIEW\t1000\n", None).unwrap_err();
+        assert_eq!(error.location().line, Some(1));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,463 @@
+//! Chart statistics and a rough difficulty estimate.
+
+use crate::{ChartData, Header, Time};
+
+/// Times closer than this are treated as the same point in seconds.
+const TIME_EPSILON: f32 = 1e-3;
+
+/// x positions closer than this are treated as the same position.
+const X_EPSILON: f32 = 1e-3;
+
+/// Consecutive notes on the same x further apart than this are not counted as jacks.
+const MAX_JACK_INTERVAL: f32 = 0.5;
+
+// Weights of every difficulty component.
+const STREAM_WEIGHT: f32 = 0.6;
+const JACK_WEIGHT: f32 = 0.15;
+const LATERAL_WEIGHT: f32 = 0.5;
+const BULLET_WEIGHT: f32 = 0.3;
+
+// Maps the weighted sum onto the level scale of `Header::level`. Chosen so a chart averaging 2
+// notes per second with a peak of 4 lands near level 3, and one averaging 7 with a peak of 12 and
+// some lateral movement lands near level 13.
+const LEVEL_PER_OVERALL: f32 = 1.9;
+const LEVEL_OFFSET: f32 = -1.0;
+const MIN_LEVEL: f32 = 1.0;
+const MAX_LEVEL: f32 = 15.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct AnalysisSettings {
+    /// Length of the sliding window used for notes per second, in seconds. Peaks and the density
+    /// series are empty if this is not positive.
+    pub window: f32,
+    /// Interval between samples of the density series, in seconds. The density series is empty if
+    /// this is not positive.
+    pub density_interval: f32,
+}
+
+impl Default for AnalysisSettings {
+    fn default() -> Self {
+        Self {
+            window: 1.0,
+            density_interval: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NoteCounts {
+    pub hits: usize,
+    pub holds: usize,
+    pub contacts: usize,
+    pub evades: usize,
+    pub flicks: usize,
+    pub targets: usize,
+    pub target_holds: usize,
+    /// Hits and holds that are critical.
+    pub criticals: usize,
+}
+
+impl NoteCounts {
+    pub fn total(&self) -> usize {
+        self.hits
+            + self.holds
+            + self.contacts
+            + self.evades
+            + self.flicks
+            + self.targets
+            + self.target_holds
+    }
+}
+
+/// Notes per second of the window starting at `time`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DensitySample {
+    pub time: Time,
+    pub notes_per_second: f32,
+}
+
+/// Components are in their own units, `overall` is their weighted sum.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DifficultyEstimate {
+    /// Mix of peak and average notes per second.
+    pub stream: f32,
+    /// Average repetitions per second of consecutive notes on the same x.
+    pub jack: f32,
+    /// Average x distance per second between consecutive notes.
+    pub lateral: f32,
+    /// Bullets per second.
+    pub bullet: f32,
+    pub overall: f32,
+}
+
+impl DifficultyEstimate {
+    /// `overall` on the level scale charts state in `Header::level`, from 1.0 to 15.0.
+    pub fn level(&self) -> f32 {
+        (LEVEL_PER_OVERALL * self.overall + LEVEL_OFFSET).clamp(MIN_LEVEL, MAX_LEVEL)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ChartStats {
+    pub note_counts: NoteCounts,
+    /// Time of the first and last note.
+    pub start: Time,
+    pub end: Time,
+    /// Seconds between the first and last note.
+    pub length: f32,
+    pub average_notes_per_second: f32,
+    /// Highest notes per second over any window.
+    pub peak_notes_per_second: f32,
+    /// Fraction of the chart length where at least one hold or target hold is active, from 0.0 to
+    /// 1.0.
+    pub hold_coverage: f32,
+    /// Bullets per second.
+    pub average_bullet_density: f32,
+    /// Highest bullets per second over any window.
+    pub peak_bullet_density: f32,
+    pub density: Vec<DensitySample>,
+    pub difficulty: DifficultyEstimate,
+}
+
+impl ChartStats {
+    /// Estimated level minus the level stated in `header`, positive if the chart plays harder than
+    /// it is labeled. `None` if the header states no level.
+    pub fn level_deviation(&self, header: &Header) -> Option<f32> {
+        header.level.map(|level| self.difficulty.level() - level)
+    }
+}
+
+/// Hit time and x of every note the player has to play, sorted by time. Evade notes are excluded.
+fn playable_notes(data: &ChartData) -> Vec<(f32, f32)> {
+    let notes = &data.notes;
+    let mut positions = notes
+        .hits
+        .iter()
+        .map(|n| &n.position)
+        .chain(notes.holds.iter().filter_map(|n| n.points.first()))
+        .chain(notes.contacts.iter().map(|n| &n.position))
+        .chain(notes.flicks.iter().map(|n| &n.position))
+        .chain(notes.targets.iter().map(|n| &n.position))
+        .chain(notes.target_holds.iter().filter_map(|n| n.points.first()))
+        .map(|p| (p.time.0, p.x))
+        .collect::<Vec<_>>();
+    positions.sort_by(|a, b| a.0.total_cmp(&b.0));
+    positions
+}
+
+/// Highest number of sorted `times` within any window of `window` seconds, per second.
+fn peak_per_second(times: &[f32], window: f32) -> f32 {
+    if window <= 0.0 {
+        return 0.0;
+    }
+
+    let mut peak = 0;
+    let mut window_start = 0;
+    for (index, time) in times.iter().enumerate() {
+        while times[window_start] <= time - window {
+            window_start += 1;
+        }
+        peak = peak.max(index + 1 - window_start);
+    }
+    peak as f32 / window
+}
+
+/// Total length of the union of `intervals`.
+fn covered_length(mut intervals: Vec<(f32, f32)>) -> f32 {
+    intervals.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut length = 0.0;
+    let mut current: Option<(f32, f32)> = None;
+    for (start, end) in intervals {
+        current = match current {
+            Some((current_start, current_end)) if start <= current_end => {
+                Some((current_start, current_end.max(end)))
+            }
+            Some((current_start, current_end)) => {
+                length += current_end - current_start;
+                Some((start, end))
+            }
+            None => Some((start, end)),
+        };
+    }
+    if let Some((start, end)) = current {
+        length += end - start;
+    }
+    length
+}
+
+fn density_series(
+    times: &[f32],
+    start: f32,
+    end: f32,
+    settings: &AnalysisSettings,
+) -> Vec<DensitySample> {
+    if times.is_empty() || settings.density_interval <= 0.0 || settings.window <= 0.0 {
+        return Vec::new();
+    }
+
+    let num_samples = ((end - start) / settings.density_interval).floor() as usize + 1;
+    (0..num_samples)
+        .map(|index| {
+            let time = start + index as f32 * settings.density_interval;
+            let from = times.partition_point(|t| *t < time);
+            let to = times.partition_point(|t| *t < time + settings.window);
+            DensitySample {
+                time: Time(time),
+                notes_per_second: (to - from) as f32 / settings.window,
+            }
+        })
+        .collect()
+}
+
+fn estimate_difficulty(
+    notes: &[(f32, f32)],
+    average_notes_per_second: f32,
+    peak_notes_per_second: f32,
+    bullet_density: f32,
+) -> DifficultyEstimate {
+    let mut jack_speeds = Vec::new();
+    let mut lateral_speeds = Vec::new();
+    for pair in notes.windows(2) {
+        let ((t0, x0), (t1, x1)) = (pair[0], pair[1]);
+        let interval = t1 - t0;
+        // Chords are not movement.
+        if interval < TIME_EPSILON {
+            continue;
+        }
+
+        let distance = (x1 - x0).abs();
+        if distance < X_EPSILON {
+            if interval <= MAX_JACK_INTERVAL {
+                jack_speeds.push(1.0 / interval);
+            }
+        } else {
+            lateral_speeds.push(distance / interval);
+        }
+    }
+
+    let average = |values: &[f32]| {
+        if values.is_empty() {
+            0.0
+        } else {
+            values.iter().sum::<f32>() / values.len() as f32
+        }
+    };
+
+    let stream = 0.5 * (peak_notes_per_second + average_notes_per_second);
+    let jack = average(&jack_speeds);
+    let lateral = average(&lateral_speeds);
+
+    DifficultyEstimate {
+        stream,
+        jack,
+        lateral,
+        bullet: bullet_density,
+        overall: STREAM_WEIGHT * stream
+            + JACK_WEIGHT * jack
+            + LATERAL_WEIGHT * lateral
+            + BULLET_WEIGHT * bullet_density,
+    }
+}
+
+pub fn analyze(data: &ChartData) -> ChartStats {
+    analyze_with(data, &AnalysisSettings::default())
+}
+
+pub fn analyze_with(data: &ChartData, settings: &AnalysisSettings) -> ChartStats {
+    let notes = &data.notes;
+    let note_counts = NoteCounts {
+        hits: notes.hits.len(),
+        holds: notes.holds.len(),
+        contacts: notes.contacts.len(),
+        evades: notes.evades.len(),
+        flicks: notes.flicks.len(),
+        targets: notes.targets.len(),
+        target_holds: notes.target_holds.len(),
+        criticals: notes.hits.iter().filter(|n| n.is_critical).count()
+            + notes.holds.iter().filter(|n| n.is_critical).count(),
+    };
+
+    let playable = playable_notes(data);
+    let note_times = playable.iter().map(|(t, _)| *t).collect::<Vec<_>>();
+    let mut bullet_times = notes
+        .evades
+        .iter()
+        .map(|n| n.movement.end.time.0)
+        .collect::<Vec<_>>();
+    bullet_times.sort_by(|a, b| a.total_cmp(b));
+
+    let hold_points = notes
+        .holds
+        .iter()
+        .map(|n| &n.points)
+        .chain(notes.target_holds.iter().map(|n| &n.points));
+    let hold_ends = hold_points
+        .clone()
+        .filter_map(|points| points.last())
+        .map(|p| p.time.0);
+    let (start, end) = note_times
+        .iter()
+        .chain(bullet_times.iter())
+        .copied()
+        .chain(hold_ends)
+        .fold(None, |range: Option<(f32, f32)>, t| match range {
+            Some((start, end)) => Some((start.min(t), end.max(t))),
+            None => Some((t, t)),
+        })
+        .unwrap_or_default();
+    let length = end - start;
+
+    let per_second = |count: usize| {
+        if length > 0.0 {
+            count as f32 / length
+        } else {
+            0.0
+        }
+    };
+    let average_notes_per_second = per_second(note_times.len());
+    let peak_notes_per_second = peak_per_second(&note_times, settings.window);
+    let average_bullet_density = per_second(bullet_times.len());
+    let peak_bullet_density = peak_per_second(&bullet_times, settings.window);
+
+    let hold_intervals = hold_points
+        .filter_map(|points| Some((points.first()?.time.0, points.last()?.time.0)))
+        .collect();
+    let hold_coverage = if length > 0.0 {
+        covered_length(hold_intervals) / length
+    } else {
+        0.0
+    };
+
+    ChartStats {
+        note_counts,
+        start: Time(start),
+        end: Time(end),
+        length,
+        average_notes_per_second,
+        peak_notes_per_second,
+        hold_coverage,
+        average_bullet_density,
+        peak_bullet_density,
+        density: density_series(&note_times, start, end, settings),
+        difficulty: estimate_difficulty(
+            &playable,
+            average_notes_per_second,
+            peak_notes_per_second,
+            average_bullet_density,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ecl::create_chart_from_ecl_source;
+
+    const CHART: &str = "\
+<header>
+default_tempo=120
+default_time_signature=4/4
+offset=0
+
+<chart_body>
+[B1] (1) |0|
+[B1] (2) |0|
+[B2] (3) |0.5|
+[HB3] (3;1,1) |1|
+[E1] (4) |0|
+--
+[C1] (1) |0|
+";
+
+    #[test]
+    fn peak_counts_notes_in_the_densest_window() {
+        let times = [0.0, 0.1, 0.2, 1.5, 3.0];
+        assert_eq!(peak_per_second(&times, 1.0), 3.0);
+        assert_eq!(peak_per_second(&times, 0.5), 6.0);
+        assert_eq!(peak_per_second(&[], 1.0), 0.0);
+    }
+
+    #[test]
+    fn peak_with_non_positive_window_is_zero() {
+        let times = [0.0, 0.1, 0.2];
+        assert_eq!(peak_per_second(&times, 0.0), 0.0);
+        assert_eq!(peak_per_second(&times, -1.0), 0.0);
+    }
+
+    #[test]
+    fn covered_length_merges_overlaps() {
+        assert_eq!(
+            covered_length(vec![(2.0, 3.0), (0.0, 1.0), (0.5, 1.5)]),
+            2.5
+        );
+        assert_eq!(covered_length(Vec::new()), 0.0);
+    }
+
+    #[test]
+    fn analyzes_chart() {
+        let chart = create_chart_from_ecl_source(CHART).unwrap();
+        let stats = analyze(&chart.data);
+
+        assert_eq!(stats.note_counts.hits, 3);
+        assert_eq!(stats.note_counts.holds, 1);
+        assert_eq!(stats.note_counts.total(), 6);
+        assert_eq!(stats.start, Time(0.0));
+        assert_eq!(stats.end, Time(2.0));
+        // The hold lasts from 1s to 2s.
+        assert!((stats.hold_coverage - 0.5).abs() < 1e-4);
+        assert!(stats.difficulty.overall > 0.0);
+    }
+
+    #[test]
+    fn levels_follow_note_density() {
+        let level = |average: f32, peak: f32, lateral: f32| {
+            let overall = STREAM_WEIGHT * 0.5 * (average + peak) + LATERAL_WEIGHT * lateral;
+            DifficultyEstimate {
+                overall,
+                ..Default::default()
+            }
+            .level()
+        };
+        assert!((level(2.0, 4.0, 0.5) - 3.0).abs() < 0.5);
+        assert!((level(7.0, 12.0, 3.0) - 13.0).abs() < 0.5);
+        assert_eq!(level(0.0, 0.0, 0.0), MIN_LEVEL);
+        assert_eq!(level(30.0, 60.0, 10.0), MAX_LEVEL);
+    }
+
+    #[test]
+    fn compares_estimate_with_stated_level() {
+        let chart = create_chart_from_ecl_source(CHART).unwrap();
+        let stats = analyze(&chart.data);
+        let estimate = stats.difficulty.level();
+
+        let header = |level| Header {
+            level,
+            ..Default::default()
+        };
+        assert_eq!(stats.level_deviation(&header(None)), None);
+        let deviation = stats
+            .level_deviation(&header(Some(estimate - 2.0)))
+            .unwrap();
+        assert!((deviation - 2.0).abs() < 1e-4);
+        let deviation = stats
+            .level_deviation(&header(Some(estimate + 1.0)))
+            .unwrap();
+        assert!((deviation + 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn analyzes_chart_with_invalid_settings() {
+        let chart = create_chart_from_ecl_source(CHART).unwrap();
+        let settings = AnalysisSettings {
+            window: 0.0,
+            density_interval: 0.0,
+        };
+        let stats = analyze_with(&chart.data, &settings);
+
+        assert_eq!(stats.peak_notes_per_second, 0.0);
+        assert_eq!(stats.peak_bullet_density, 0.0);
+        assert!(stats.density.is_empty());
+        assert!(stats.difficulty.overall.is_finite());
+    }
+}
@@ -0,0 +1,352 @@
+//! Conversion between musical positions (measures and beats) and time.
+
+use crate::{Time, TimeSignature};
+
+const DEFAULT_TIME_SIGNATURE: TimeSignature = TimeSignature {
+    num_beats: 4,
+    note_value: 4,
+};
+
+const DEFAULT_BPM: f32 = 120.0;
+
+/// Position in musical terms.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct BeatPosition {
+    /// Starts at 0. Negative measures are before the first measure of the chart.
+    pub measure: i32,
+    /// Beats of the measure's time signature since the start of the measure, starting at 0.0.
+    pub beat: f32,
+}
+
+impl BeatPosition {
+    pub fn new(measure: i32, beat: f32) -> Self {
+        Self { measure, beat }
+    }
+}
+
+/// Time signatures can only change at the start of a measure.
+#[derive(Clone, Copy, Debug)]
+pub struct MeterChange {
+    pub measure: i32,
+    pub time_signature: TimeSignature,
+}
+
+/// Tempo changes can happen anywhere within a measure.
+#[derive(Clone, Copy, Debug)]
+pub struct TempoChange {
+    pub position: BeatPosition,
+    /// Measured in quarter notes.
+    pub bpm: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct MeterSegment {
+    measure: i32,
+    /// Start of the segment in quarter notes since the start of measure 0.
+    quarter: f32,
+    time_signature: TimeSignature,
+}
+
+/// Length of a measure in quarter notes.
+fn measure_length(time_signature: TimeSignature) -> f32 {
+    time_signature.num_beats as f32 * 4.0 / time_signature.note_value as f32
+}
+
+impl MeterSegment {
+    fn measure_length(&self) -> f32 {
+        measure_length(self.time_signature)
+    }
+
+    fn quarters_per_beat(&self) -> f32 {
+        4.0 / self.time_signature.note_value as f32
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TempoSegment {
+    /// Start of the segment in quarter notes since the start of measure 0.
+    quarter: f32,
+    time: Time,
+    bpm: f32,
+}
+
+impl TempoSegment {
+    fn seconds_per_quarter(&self) -> f32 {
+        60.0 / self.bpm
+    }
+}
+
+/// Maps musical positions to time and back. The first time signature and tempo are extended before
+/// the first measure and the last ones are extended after the last measure, so every position and
+/// time can be converted.
+#[derive(Clone, Debug)]
+pub struct TimingMap {
+    /// Sorted by measure, never empty.
+    meter_segments: Vec<MeterSegment>,
+    /// Sorted by quarter and time, never empty.
+    tempo_segments: Vec<TempoSegment>,
+}
+
+impl TimingMap {
+    /// `music_offset` is the time at the start of measure 0. Changes do not need to be sorted, when
+    /// several changes happen at the same position the last one is used. Charts without a time
+    /// signature or tempo use 4/4 and 120 BPM.
+    pub fn new(
+        music_offset: Time,
+        meter_changes: &[MeterChange],
+        tempo_changes: &[TempoChange],
+    ) -> Self {
+        let mut meter_changes = meter_changes
+            .iter()
+            .filter(|c| c.time_signature.num_beats > 0 && c.time_signature.note_value > 0)
+            .copied()
+            .collect::<Vec<_>>();
+        meter_changes.sort_by_key(|c| c.measure);
+        // Keep the last change of every measure.
+        meter_changes.reverse();
+        meter_changes.dedup_by_key(|c| c.measure);
+        meter_changes.reverse();
+
+        let mut meter_segments: Vec<MeterSegment> = Vec::new();
+        for change in meter_changes {
+            let segment = match meter_segments.last() {
+                Some(last) => MeterSegment {
+                    measure: change.measure,
+                    quarter: last.quarter
+                        + (change.measure - last.measure) as f32 * last.measure_length(),
+                    time_signature: change.time_signature,
+                },
+                // The first time signature is extended back to measure 0.
+                None => MeterSegment {
+                    measure: change.measure,
+                    quarter: change.measure as f32 * measure_length(change.time_signature),
+                    time_signature: change.time_signature,
+                },
+            };
+            meter_segments.push(segment);
+        }
+        if meter_segments.is_empty() {
+            meter_segments.push(MeterSegment {
+                measure: 0,
+                quarter: 0.0,
+                time_signature: DEFAULT_TIME_SIGNATURE,
+            });
+        }
+
+        let mut timing_map = Self {
+            meter_segments,
+            tempo_segments: Vec::new(),
+        };
+
+        let mut tempo_changes = tempo_changes
+            .iter()
+            .filter(|c| {
+                if c.bpm > 0.0 {
+                    true
+                } else {
+                    log::warn!("Ignoring tempo change to {} BPM at {:?}", c.bpm, c.position);
+                    false
+                }
+            })
+            .map(|c| (timing_map.quarter_at(c.position), c.bpm))
+            .collect::<Vec<_>>();
+        tempo_changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut tempo_segments: Vec<TempoSegment> = Vec::new();
+        for (quarter, bpm) in tempo_changes {
+            let time = match tempo_segments.last() {
+                Some(last) => {
+                    Time(last.time.0 + (quarter - last.quarter) * last.seconds_per_quarter())
+                }
+                None => Time(music_offset.0 + quarter * 60.0 / bpm),
+            };
+            tempo_segments.push(TempoSegment { quarter, time, bpm });
+        }
+        if tempo_segments.is_empty() {
+            tempo_segments.push(TempoSegment {
+                quarter: 0.0,
+                time: music_offset,
+                bpm: DEFAULT_BPM,
+            });
+        }
+        timing_map.tempo_segments = tempo_segments;
+
+        timing_map
+    }
+
+    fn meter_segment_at_measure(&self, measure: i32) -> &MeterSegment {
+        let index = self
+            .meter_segments
+            .partition_point(|s| s.measure <= measure)
+            .saturating_sub(1);
+        &self.meter_segments[index]
+    }
+
+    fn meter_segment_at_quarter(&self, quarter: f32) -> &MeterSegment {
+        let index = self
+            .meter_segments
+            .partition_point(|s| s.quarter <= quarter)
+            .saturating_sub(1);
+        &self.meter_segments[index]
+    }
+
+    /// Later segments win when several start at the same quarter.
+    fn tempo_segment_at_quarter(&self, quarter: f32) -> &TempoSegment {
+        let index = self
+            .tempo_segments
+            .partition_point(|s| s.quarter <= quarter)
+            .saturating_sub(1);
+        &self.tempo_segments[index]
+    }
+
+    fn tempo_segment_at_time(&self, time: Time) -> &TempoSegment {
+        let index = self
+            .tempo_segments
+            .partition_point(|s| s.time <= time)
+            .saturating_sub(1);
+        &self.tempo_segments[index]
+    }
+
+    fn quarter_at(&self, position: BeatPosition) -> f32 {
+        let segment = self.meter_segment_at_measure(position.measure);
+        segment.quarter
+            + (position.measure - segment.measure) as f32 * segment.measure_length()
+            + position.beat * segment.quarters_per_beat()
+    }
+
+    fn quarter_at_time(&self, time: Time) -> f32 {
+        let segment = self.tempo_segment_at_time(time);
+        segment.quarter + (time.0 - segment.time.0) / segment.seconds_per_quarter()
+    }
+
+    pub fn time_at(&self, position: BeatPosition) -> Time {
+        let quarter = self.quarter_at(position);
+        let segment = self.tempo_segment_at_quarter(quarter);
+        Time(segment.time.0 + (quarter - segment.quarter) * segment.seconds_per_quarter())
+    }
+
+    /// Position of `time`, the beat is always within its measure.
+    pub fn position_at(&self, time: Time) -> BeatPosition {
+        let quarter = self.quarter_at_time(time);
+        let segment = self.meter_segment_at_quarter(quarter);
+
+        let measures = ((quarter - segment.quarter) / segment.measure_length()).floor();
+        let measure_start = segment.quarter + measures * segment.measure_length();
+        BeatPosition {
+            measure: segment.measure + measures as i32,
+            beat: (quarter - measure_start) / segment.quarters_per_beat(),
+        }
+    }
+
+    /// Beats from the start of `measure` to `time`, counted in the measure's time signature. The
+    /// result can be negative or go past the end of the measure.
+    pub fn beat_at(&self, measure: i32, time: Time) -> f32 {
+        let segment = self.meter_segment_at_measure(measure);
+        let measure_start = self.quarter_at(BeatPosition::new(measure, 0.0));
+        (self.quarter_at_time(time) - measure_start) / segment.quarters_per_beat()
+    }
+
+    pub fn measure_start(&self, measure: i32) -> Time {
+        self.time_at(BeatPosition::new(measure, 0.0))
+    }
+
+    pub fn time_signature_at(&self, measure: i32) -> TimeSignature {
+        self.meter_segment_at_measure(measure).time_signature
+    }
+
+    /// Tempo in effect at `time`, a change happening exactly at `time` is already applied.
+    pub fn bpm_at(&self, time: Time) -> f32 {
+        self.tempo_segment_at_time(time).bpm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THREE_FOUR: TimeSignature = TimeSignature {
+        num_beats: 3,
+        note_value: 4,
+    };
+
+    fn meter(measure: i32, time_signature: TimeSignature) -> MeterChange {
+        MeterChange {
+            measure,
+            time_signature,
+        }
+    }
+
+    fn tempo(measure: i32, beat: f32, bpm: f32) -> TempoChange {
+        TempoChange {
+            position: BeatPosition::new(measure, beat),
+            bpm,
+        }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn defaults_to_four_four_at_120_bpm() {
+        let timing = TimingMap::new(Time(1.0), &[], &[]);
+        assert_close(timing.measure_start(0).0, 1.0);
+        assert_close(timing.measure_start(1).0, 3.0);
+        assert_close(timing.measure_start(-1).0, -1.0);
+        assert_eq!(timing.position_at(Time(2.0)), BeatPosition::new(0, 2.0));
+        assert_eq!(timing.bpm_at(Time(0.0)), 120.0);
+    }
+
+    #[test]
+    fn tempo_changes_apply_within_a_measure() {
+        let timing = TimingMap::new(
+            Time(0.0),
+            &[],
+            &[tempo(0, 0.0, 120.0), tempo(0, 2.0, 240.0)],
+        );
+        assert_close(timing.measure_start(1).0, 1.5);
+        assert_eq!(timing.bpm_at(Time(0.99)), 120.0);
+        assert_eq!(timing.bpm_at(Time(1.0)), 240.0);
+        assert_close(timing.time_at(BeatPosition::new(0, 3.0)).0, 1.25);
+    }
+
+    #[test]
+    fn time_signatures_change_at_measure_starts() {
+        let timing = TimingMap::new(
+            Time(0.0),
+            &[meter(0, DEFAULT_TIME_SIGNATURE), meter(1, THREE_FOUR)],
+            &[],
+        );
+        assert_eq!(timing.time_signature_at(0), DEFAULT_TIME_SIGNATURE);
+        assert_eq!(timing.time_signature_at(2), THREE_FOUR);
+        assert_close(timing.measure_start(2).0, 3.5);
+        assert_close(timing.beat_at(1, Time(3.0)), 2.0);
+        assert_close(timing.beat_at(1, Time(4.0)), 4.0);
+    }
+
+    #[test]
+    fn first_changes_extend_back_to_measure_zero() {
+        let timing = TimingMap::new(Time(0.0), &[meter(2, THREE_FOUR)], &[tempo(1, 0.0, 60.0)]);
+        assert_eq!(timing.time_signature_at(0), THREE_FOUR);
+        assert_eq!(timing.bpm_at(Time(0.0)), 60.0);
+        assert_close(timing.measure_start(1).0, 3.0);
+    }
+
+    #[test]
+    fn positions_round_trip() {
+        let timing = TimingMap::new(
+            Time(0.3),
+            &[meter(0, DEFAULT_TIME_SIGNATURE), meter(2, THREE_FOUR)],
+            &[tempo(0, 0.0, 120.0), tempo(1, 1.5, 180.0)],
+        );
+        for position in [
+            BeatPosition::new(0, 0.5),
+            BeatPosition::new(1, 3.0),
+            BeatPosition::new(3, 2.25),
+        ] {
+            let round_trip = timing.position_at(timing.time_at(position));
+            assert_eq!(round_trip.measure, position.measure);
+            assert_close(round_trip.beat, position.beat);
+        }
+    }
+}
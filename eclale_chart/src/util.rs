@@ -1,22 +1,15 @@
 use std::fmt;
 
-use crate::Time;
-
-use super::TimeSignature;
-
-/// Contains all required musical and rhythm data for a single measure/bar to calculate raw time offsets.
-#[derive(Default, Clone, Debug)]
-pub(crate) struct MeasureCompositionData {
-    pub(crate) time_signature: TimeSignature,
-    pub(crate) bpm: u32,
-    pub(crate) speed_multiplier: f32,
-    /// Subdivision the notes in the measure are written against.
-    pub(crate) subdivision: u32,
-}
+use crate::{
+    timing::{BeatPosition, TimingMap},
+    Soflan, Time, TrackPosition,
+};
 
 #[derive(Default, Clone, Copy, Debug)]
 pub struct ZPosition {
+    /// Hit time, not affected by scroll speed.
     pub time: Time,
+    /// Visual position on the track, follows scroll speed.
     pub z: f32,
 }
 
@@ -26,24 +19,20 @@ impl ZPosition {
     }
 }
 
-/// Contains positional/offset data for a measure.
-#[derive(Default, Clone, Copy, Debug)]
-struct MeasurePositionData {
-    offset: ZPosition,
-    duration: ZPosition,
-}
-
-#[derive(Default, Clone, Debug)]
-struct MeasureData {
-    composition: MeasureCompositionData,
-    position: MeasurePositionData,
+/// Point in time where the scroll speed changes. z is continuous across speed changes.
+#[derive(Clone, Copy, Debug)]
+struct SpeedChange {
+    position: ZPosition,
+    speed_multiplier: f32,
 }
 
-/// Counts offset at a specific measure and subdivison.
+/// Counts offset at a specific musical position.
 #[derive(Clone)]
 pub struct ZPositionCalculator {
-    /// Sorted by positiona; offset.
-    measures: Vec<MeasureData>,
+    timing_map: TimingMap,
+
+    /// Sorted by time. Scroll speed is 1.0 before the first change.
+    speed_changes: Vec<SpeedChange>,
 
     /// Position-based (not time) base velocity on the z axis.
     z_base_speed: f32,
@@ -56,74 +45,72 @@ impl fmt::Debug for ZPositionCalculator {
 }
 
 impl ZPositionCalculator {
-    pub(crate) fn new(
-        measure_compositions: Vec<MeasureCompositionData>,
-        music_offset: Time,
-        z_base_speed: f32,
-    ) -> Self {
-        let measures = measure_compositions
-            .into_iter()
-            .scan(
-                ZPosition::new(music_offset, music_offset.0 * z_base_speed),
-                |current_position, measure| {
-                    let time_signature = measure.time_signature;
-                    let bpm = measure.bpm;
-
-                    let beat_duration = 60.0 / bpm as f32;
-                    let z_duration_time = time_signature.num_beats as f32
-                            * beat_duration
-                            // BPM is measured in quarter notes, i.e. `4.0`.
-                            * (4.0 / time_signature.note_value as f32);
-
-                    // XXX TODO: Handle soflans properly. Soflans can happen multiple times within
-                    // a measure as well.
-                    let z_duration_length = time_signature.num_beats as f32
-                            * beat_duration
-                            // BPM is measured in quarter notes, i.e. `4.0`.
-                            * (4.0 / time_signature.note_value as f32)
-                            * z_base_speed;
-                    // * measure.speed_multiplier;
-
-                    let offset = current_position.clone();
-                    current_position.time.0 += z_duration_time;
-                    current_position.z += z_duration_length;
-
-                    Some(MeasureData {
-                        composition: measure,
-                        position: MeasurePositionData {
-                            offset,
-                            duration: ZPosition::new(Time(z_duration_time), z_duration_length),
-                        },
-                    })
-                },
-            )
-            .collect::<Vec<_>>();
-
+    pub(crate) fn new(timing_map: TimingMap, z_base_speed: f32) -> Self {
         Self {
-            measures,
+            timing_map,
+            speed_changes: Vec::new(),
             z_base_speed,
         }
     }
 
-    pub fn z_position_at(&self, measure: usize, subdivision_index: f32) -> ZPosition {
-        if measure >= self.measures.len() {
-            log::error!(
-                "Measure {} is higher than the number of measure data provided!",
-                measure
-            );
+    /// Replaces the scroll speed curve. Soflans can start at any time and may overlap, in which
+    /// case a soflan ends where the next one starts. Scroll speed goes back to 1.0 after a soflan
+    /// ends.
+    pub(crate) fn set_soflans(&mut self, soflans: &[Soflan]) {
+        let mut soflans = soflans.to_vec();
+        soflans.sort_by(|a, b| a.time.0.total_cmp(&b.time.0));
+
+        self.speed_changes.clear();
+        for (index, soflan) in soflans.iter().enumerate() {
+            let mut end_time = Time(soflan.time.0 + soflan.duration.max(0.0));
+            if let Some(next) = soflans.get(index + 1) {
+                if next.time < end_time {
+                    end_time = next.time;
+                }
+            }
+            if end_time <= soflan.time {
+                continue;
+            }
+
+            self.push_speed_change(soflan.time, soflan.speed_multiplier);
+            self.push_speed_change(end_time, 1.0);
         }
+    }
 
-        let subdivision = self.measures[measure].composition.subdivision;
-        let position_data = self.measures[measure].position;
+    /// `time` must not be earlier than the last speed change.
+    fn push_speed_change(&mut self, time: Time, speed_multiplier: f32) {
+        let z = self.z_at(time);
+        self.speed_changes.push(SpeedChange {
+            position: ZPosition::new(time, z),
+            speed_multiplier,
+        });
+    }
 
-        // Index starts at 1.
-        let index = subdivision_index as f32 - 1.0;
-        let ratio = (index / subdivision as f32) as f32;
+    /// Visual z position of the hit bar at `time`. Zero speed soflans stop the track and negative
+    /// ones scroll it backwards.
+    pub fn z_at(&self, time: Time) -> f32 {
+        // Last change at or before `time`, later changes win on ties.
+        let index = self
+            .speed_changes
+            .partition_point(|c| c.position.time <= time);
+        match index.checked_sub(1).map(|i| self.speed_changes[i]) {
+            Some(change) => {
+                change.position.z
+                    + (time.0 - change.position.time.0)
+                        * change.speed_multiplier
+                        * self.z_base_speed
+            }
+            None => time.0 * self.z_base_speed,
+        }
+    }
 
-        let time = Time(position_data.offset.time.0 + ratio * position_data.duration.time.0);
-        let z = position_data.offset.z + ratio * position_data.duration.z;
+    pub fn z_position_at(&self, position: BeatPosition) -> ZPosition {
+        let time = self.timing_map.time_at(position);
+        ZPosition::new(time, self.z_at(time))
+    }
 
-        ZPosition::new(time, z)
+    pub fn timing_map(&self) -> &TimingMap {
+        &self.timing_map
     }
 
     /// Position-based (not time) base velocity on the z axis.
@@ -150,3 +137,250 @@ impl XPositionCalculator {
         (abs_position + (offset_position / self.resolution)) * multiplier
     }
 }
+
+/// Number of bisection steps used to find the point of a bezier curve at a time.
+const BEZIER_BISECTION_ITERATIONS: usize = 24;
+
+/// Point of a cubic bezier curve at `t` from 0.0 to 1.0. Every track position component is
+/// interpolated independently.
+fn cubic_bezier_track_position(
+    p0: TrackPosition,
+    control_points: (TrackPosition, TrackPosition),
+    p1: TrackPosition,
+    t: f32,
+) -> TrackPosition {
+    let (c0, c1) = control_points;
+    let bezier = |v0: f32, v1: f32, v2: f32, v3: f32| {
+        let u = 1.0 - t;
+        u * u * u * v0 + 3.0 * u * u * t * v1 + 3.0 * u * t * t * v2 + t * t * t * v3
+    };
+
+    TrackPosition {
+        time: Time(bezier(p0.time.0, c0.time.0, c1.time.0, p1.time.0)),
+        z: bezier(p0.z, c0.z, c1.z, p1.z),
+        x: bezier(p0.x, c0.x, c1.x, p1.x),
+        y: bezier(p0.y, c0.y, c1.y, p1.y),
+    }
+}
+
+/// Flattens a cubic bezier curve into `subdivisions + 1` points.
+pub(crate) fn cubic_bezier_track_positions(
+    p0: TrackPosition,
+    control_points: (TrackPosition, TrackPosition),
+    p1: TrackPosition,
+    subdivisions: usize,
+) -> Vec<TrackPosition> {
+    (0..subdivisions + 1)
+        .map(|i| {
+            cubic_bezier_track_position(p0, control_points, p1, i as f32 / subdivisions as f32)
+        })
+        .collect()
+}
+
+/// Point of a cubic bezier curve at `time`, clamped to its end points. The curve has to go
+/// forward in time.
+pub(crate) fn cubic_bezier_track_position_at(
+    p0: TrackPosition,
+    control_points: (TrackPosition, TrackPosition),
+    p1: TrackPosition,
+    time: Time,
+) -> TrackPosition {
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..BEZIER_BISECTION_ITERATIONS {
+        let middle = (low + high) / 2.0;
+        if cubic_bezier_track_position(p0, control_points, p1, middle)
+            .time
+            .0
+            < time.0
+        {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    TrackPosition {
+        time,
+        ..cubic_bezier_track_position(p0, control_points, p1, (low + high) / 2.0)
+    }
+}
+
+/// x position of a polyline at `time`, clamped to its end points.
+pub(crate) fn x_at(points: &[TrackPosition], time: Time) -> f32 {
+    let index = points.partition_point(|p| p.time.0 < time.0);
+    match (index.checked_sub(1).map(|i| &points[i]), points.get(index)) {
+        (Some(a), Some(b)) if b.time.0 > a.time.0 => {
+            a.x + (b.x - a.x) * (time.0 - a.time.0) / (b.time.0 - a.time.0)
+        }
+        (_, Some(b)) => b.x,
+        (Some(a), None) => a.x,
+        (None, None) => 0.0,
+    }
+}
+
+/// Position of a polyline at `time`, clamped to its end points. Every component is interpolated
+/// linearly.
+pub(crate) fn track_position_at(points: &[TrackPosition], time: Time) -> TrackPosition {
+    let index = points.partition_point(|p| p.time.0 < time.0);
+    match (index.checked_sub(1).map(|i| &points[i]), points.get(index)) {
+        (Some(a), Some(b)) if b.time.0 > a.time.0 => {
+            let t = (time.0 - a.time.0) / (b.time.0 - a.time.0);
+            TrackPosition {
+                time,
+                z: a.z + (b.z - a.z) * t,
+                x: a.x + (b.x - a.x) * t,
+                y: a.y + (b.y - a.y) * t,
+            }
+        }
+        (_, Some(p)) | (Some(p), None) => TrackPosition { time, ..*p },
+        (None, None) => TrackPosition {
+            time,
+            ..Default::default()
+        },
+    }
+}
+
+/// Part of a polyline between `start` and `end`, with interpolated points at both ends.
+pub(crate) fn clip_track_positions(
+    points: &[TrackPosition],
+    start: Time,
+    end: Time,
+) -> Vec<TrackPosition> {
+    let from = points.partition_point(|p| p.time.0 <= start.0);
+    let to = points.partition_point(|p| p.time.0 < end.0).max(from);
+
+    std::iter::once(track_position_at(points, start))
+        .chain(points[from..to].iter().copied())
+        .chain(std::iter::once(track_position_at(points, end)))
+        .collect()
+}
+
+/// Times of the first and last point of a polyline.
+pub(crate) fn time_range(points: &[TrackPosition]) -> Option<(Time, Time)> {
+    Some((points.first()?.time, points.last()?.time))
+}
+
+/// Position of a polyline at `time`, `None` if `time` is more than `epsilon` outside of it.
+pub(crate) fn sample_polyline(
+    points: &[TrackPosition],
+    time: Time,
+    epsilon: f32,
+) -> Option<TrackPosition> {
+    let (start, end) = time_range(points)?;
+    (start.0 - epsilon <= time.0 && time.0 <= end.0 + epsilon)
+        .then(|| track_position_at(points, time))
+}
+
+/// Part of a polyline visible between `start` and `end`, empty if the polyline is not.
+pub(crate) fn polyline_range(
+    points: &[TrackPosition],
+    start: Time,
+    end: Time,
+) -> Vec<TrackPosition> {
+    let Some((first, last)) = time_range(points) else {
+        return Vec::new();
+    };
+    let (start, end) = (Time(start.0.max(first.0)), Time(end.0.min(last.0)));
+    if start.0 > end.0 {
+        return Vec::new();
+    }
+    clip_track_positions(points, start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    fn soflan(time: f32, duration: f32, speed_multiplier: f32) -> Soflan {
+        Soflan {
+            time: Time(time),
+            duration,
+            speed_multiplier,
+        }
+    }
+
+    fn calculator(soflans: &[Soflan]) -> ZPositionCalculator {
+        let mut calculator = ZPositionCalculator::new(TimingMap::new(Time(0.0), &[], &[]), 2.0);
+        calculator.set_soflans(soflans);
+        calculator
+    }
+
+    #[test]
+    fn z_follows_the_base_speed_without_soflans() {
+        let calculator = calculator(&[]);
+        assert_close(calculator.z_at(Time(1.5)), 3.0);
+        assert_close(calculator.z_position_at(BeatPosition::new(1, 0.0)).z, 4.0);
+    }
+
+    #[test]
+    fn soflans_change_the_speed_for_their_duration() {
+        let calculator = calculator(&[soflan(1.0, 1.0, 2.0)]);
+        assert_close(calculator.z_at(Time(1.0)), 2.0);
+        assert_close(calculator.z_at(Time(1.5)), 4.0);
+        assert_close(calculator.z_at(Time(2.0)), 6.0);
+        // Back to the base speed after the soflan.
+        assert_close(calculator.z_at(Time(3.0)), 8.0);
+    }
+
+    #[test]
+    fn overlapping_soflans_end_where_the_next_starts() {
+        let calculator = calculator(&[soflan(2.0, 1.0, 0.0), soflan(1.0, 5.0, -1.0)]);
+        // Scrolls backwards until the second soflan starts and stops the track.
+        assert_close(calculator.z_at(Time(2.0)), 0.0);
+        assert_close(calculator.z_at(Time(2.5)), 0.0);
+        assert_close(calculator.z_at(Time(4.0)), 2.0);
+    }
+
+    fn point(time: f32, x: f32) -> TrackPosition {
+        TrackPosition {
+            time: Time(time),
+            z: time,
+            x,
+            y: 0.0,
+        }
+    }
+
+    #[test]
+    fn polylines_are_interpolated_and_clamped() {
+        let points = [point(0.0, 0.0), point(1.0, 2.0), point(2.0, 2.0)];
+        assert_eq!(track_position_at(&points, Time(0.5)), point(0.5, 1.0));
+        assert_eq!(track_position_at(&points, Time(3.0)).x, 2.0);
+        assert_eq!(track_position_at(&points, Time(-1.0)).x, 0.0);
+        assert_close(x_at(&points, Time(0.25)), 0.5);
+    }
+
+    #[test]
+    fn polylines_are_only_sampled_within_their_range() {
+        let points = [point(1.0, 0.0), point(2.0, 1.0)];
+        assert!(sample_polyline(&points, Time(0.5), 1e-3).is_none());
+        assert!(sample_polyline(&points, Time(2.0005), 1e-3).is_some());
+        assert!(sample_polyline(&[], Time(1.0), 1e-3).is_none());
+    }
+
+    #[test]
+    fn polyline_ranges_are_clipped() {
+        let points = [point(0.0, 0.0), point(1.0, 1.0), point(2.0, 0.0)];
+        assert_eq!(
+            polyline_range(&points, Time(0.5), Time(5.0)),
+            vec![point(0.5, 0.5), point(1.0, 1.0), point(2.0, 0.0)]
+        );
+        assert!(polyline_range(&points, Time(3.0), Time(4.0)).is_empty());
+    }
+
+    #[test]
+    fn bezier_curves_are_sampled_at_a_time() {
+        let (p0, p1) = (point(0.0, 0.0), point(1.0, 1.0));
+        let control_points = (point(1.0 / 3.0, 0.0), point(2.0 / 3.0, 1.0));
+        let position = cubic_bezier_track_position_at(p0, control_points, p1, Time(0.5));
+        assert_eq!(position.time, Time(0.5));
+        assert_close(position.x, 0.5);
+
+        let points = cubic_bezier_track_positions(p0, control_points, p1, 4);
+        assert_eq!(points.len(), 5);
+        assert_eq!(points[4], p1);
+    }
+}
@@ -0,0 +1,462 @@
+//! Lint checks for charts. Errors are problems that break gameplay or rendering, warnings are
+//! likely charting mistakes.
+
+use std::fmt;
+
+use crate::{Chart, LaneId, LaneType, NoteRef, PlatformEdge, Time, TrackPosition};
+
+/// Times closer than this are treated as the same point in seconds.
+const TIME_EPSILON: f32 = 1e-3;
+
+/// x positions closer than this are treated as the same position.
+const X_EPSILON: f32 = 1e-3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum IssueKind {
+    /// `hold` is a hold or target hold.
+    HoldTooShort {
+        hold: NoteRef,
+        num_points: usize,
+    },
+    NoteOutsidePlatform {
+        note: NoteRef,
+        x: f32,
+    },
+    OverlappingNotes {
+        first: NoteRef,
+        second: NoteRef,
+    },
+    /// `index` is the first element that is earlier than the one before it.
+    UnsortedComposition {
+        list: &'static str,
+        index: usize,
+    },
+    NonMonotonicLane {
+        lane_type: LaneType,
+        lane: LaneId,
+        point: usize,
+    },
+    NonMonotonicPlatformEdge {
+        platform: usize,
+        point: usize,
+    },
+    MirroredPlatformEdges {
+        platform: usize,
+    },
+    NonPositiveMovementDuration {
+        evade: usize,
+        duration: f32,
+    },
+}
+
+impl IssueKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::NoteOutsidePlatform { .. } | Self::OverlappingNotes { .. } => Severity::Warning,
+            Self::HoldTooShort { .. }
+            | Self::UnsortedComposition { .. }
+            | Self::NonMonotonicLane { .. }
+            | Self::NonMonotonicPlatformEdge { .. }
+            | Self::MirroredPlatformEdges { .. }
+            | Self::NonPositiveMovementDuration { .. } => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::HoldTooShort { hold, num_points } => write!(
+                f,
+                "{:?} has {} point(s), at least 2 are required",
+                hold, num_points
+            ),
+            Self::NoteOutsidePlatform { note, x } => {
+                write!(f, "{:?} at x {} is outside of every platform", note, x)
+            }
+            Self::OverlappingNotes { first, second } => {
+                write!(f, "{:?} and {:?} overlap", first, second)
+            }
+            Self::UnsortedComposition { list, index } => {
+                write!(f, "composition {} are not sorted at index {}", list, index)
+            }
+            Self::NonMonotonicLane {
+                lane_type,
+                lane,
+                point,
+            } => write!(
+                f,
+                "{:?} lane {} goes back in time at point {}",
+                lane_type, lane.0, point
+            ),
+            Self::NonMonotonicPlatformEdge { platform, point } => write!(
+                f,
+                "platform {} has an edge that goes back in time at point {}",
+                platform, point
+            ),
+            Self::MirroredPlatformEdges { platform } => write!(
+                f,
+                "platform {} mirrors both of its edges, one edge must be defined",
+                platform
+            ),
+            Self::NonPositiveMovementDuration { evade, duration } => write!(
+                f,
+                "evade note {} moves for {}s, the duration must be positive",
+                evade, duration
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationIssue {
+    /// Time in the chart the issue happens at, if any.
+    pub time: Option<Time>,
+    pub kind: IssueKind,
+}
+
+impl ValidationIssue {
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.time {
+            Some(time) => write!(f, "{}s: {}", time.0, self.kind),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+struct Validator<'a> {
+    chart: &'a Chart,
+    issues: Vec<ValidationIssue>,
+}
+
+impl Validator<'_> {
+    fn push(&mut self, time: Option<Time>, kind: IssueKind) {
+        self.issues.push(ValidationIssue { time, kind });
+    }
+
+    /// Index of the first point that is earlier than the one before it.
+    fn first_unsorted(times: impl Iterator<Item = Time>) -> Option<(usize, Time)> {
+        let mut previous: Option<Time> = None;
+        for (index, time) in times.enumerate() {
+            if previous.is_some_and(|p| time.0 < p.0) {
+                return Some((index, time));
+            }
+            previous = Some(time);
+        }
+        None
+    }
+
+    fn validate_composition(&mut self) {
+        let composition = &self.chart.data.composition;
+        let lists: [(&'static str, Vec<Time>); 3] = [
+            (
+                "bpm changes",
+                composition.bpm_changes.iter().map(|c| c.time).collect(),
+            ),
+            (
+                "time signature changes",
+                composition
+                    .time_signature_changes
+                    .iter()
+                    .map(|c| c.time)
+                    .collect(),
+            ),
+            (
+                "soflans",
+                composition.soflans.iter().map(|s| s.time).collect(),
+            ),
+        ];
+
+        for (list, times) in lists {
+            if let Some((index, time)) = Self::first_unsorted(times.into_iter()) {
+                self.push(Some(time), IssueKind::UnsortedComposition { list, index });
+            }
+        }
+    }
+
+    fn validate_track(&mut self) {
+        let track = &self.chart.data.track;
+        for (platform_index, platform) in track.platforms.iter().enumerate() {
+            if let (PlatformEdge::Mirror { .. }, PlatformEdge::Mirror { .. }) =
+                (&platform.left, &platform.right)
+            {
+                self.push(
+                    None,
+                    IssueKind::MirroredPlatformEdges {
+                        platform: platform_index,
+                    },
+                );
+            }
+
+            for edge in [platform.points_left(), platform.points_right()] {
+                if let Some((point, time)) = Self::first_unsorted(edge.iter().map(|p| p.time)) {
+                    self.push(
+                        Some(time),
+                        IssueKind::NonMonotonicPlatformEdge {
+                            platform: platform_index,
+                            point,
+                        },
+                    );
+                }
+            }
+        }
+
+        for lane in track.lanes.iter() {
+            if let Some((point, time)) = Self::first_unsorted(lane.points.iter().map(|p| p.time)) {
+                self.push(
+                    Some(time),
+                    IssueKind::NonMonotonicLane {
+                        lane_type: lane.ty,
+                        lane: lane.id,
+                        point,
+                    },
+                );
+            }
+        }
+    }
+
+    fn is_on_platform(&self, position: &TrackPosition) -> bool {
+        self.chart.data.track.platforms.iter().any(|platform| {
+            platform
+                .sample_at(position.time)
+                .is_some_and(|(left, right)| {
+                    left.x.min(right.x) - X_EPSILON <= position.x
+                        && position.x <= left.x.max(right.x) + X_EPSILON
+                })
+        })
+    }
+
+    /// Positions where a note touches the hit bar, evade notes are excluded as they are not placed
+    /// on platforms.
+    fn note_positions(&self) -> Vec<(NoteRef, TrackPosition)> {
+        let notes = &self.chart.data.notes;
+        notes
+            .hits
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (NoteRef::Hit(i), n.position))
+            .chain(
+                notes
+                    .holds
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, n)| n.points.first().map(|p| (NoteRef::Hold(i), *p))),
+            )
+            .chain(
+                notes
+                    .contacts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, n)| (NoteRef::Contact(i), n.position)),
+            )
+            .chain(
+                notes
+                    .flicks
+                    .iter()
+                    .enumerate()
+                    .map(|(i, n)| (NoteRef::Flick(i), n.position)),
+            )
+            .chain(
+                notes
+                    .targets
+                    .iter()
+                    .enumerate()
+                    .map(|(i, n)| (NoteRef::Target(i), n.position)),
+            )
+            .chain(
+                notes
+                    .target_holds
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, n)| n.points.first().map(|p| (NoteRef::TargetHold(i), *p))),
+            )
+            .collect()
+    }
+
+    fn validate_notes(&mut self) {
+        let notes = &self.chart.data.notes;
+
+        let holds = notes
+            .holds
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (NoteRef::Hold(i), &n.points))
+            .chain(
+                notes
+                    .target_holds
+                    .iter()
+                    .enumerate()
+                    .map(|(i, n)| (NoteRef::TargetHold(i), &n.points)),
+            );
+        for (hold, points) in holds {
+            if points.len() < 2 {
+                self.push(
+                    points.first().map(|p| p.time),
+                    IssueKind::HoldTooShort {
+                        hold,
+                        num_points: points.len(),
+                    },
+                );
+            }
+        }
+
+        for (index, evade) in notes.evades.iter().enumerate() {
+            let movement = &evade.movement;
+            if !movement.is_static() && movement.duration <= 0.0 {
+                self.push(
+                    Some(movement.end.time),
+                    IssueKind::NonPositiveMovementDuration {
+                        evade: index,
+                        duration: movement.duration,
+                    },
+                );
+            }
+        }
+
+        let mut positions = self.note_positions();
+
+        if !self.chart.data.track.platforms.is_empty() {
+            for (note, position) in positions.iter() {
+                if !self.is_on_platform(position) {
+                    self.push(
+                        Some(position.time),
+                        IssueKind::NoteOutsidePlatform {
+                            note: *note,
+                            x: position.x,
+                        },
+                    );
+                }
+            }
+        }
+
+        // Contact notes can share a position with other kinds of notes.
+        positions.sort_by(|a, b| a.1.time.0.total_cmp(&b.1.time.0));
+        for (index, (first, first_position)) in positions.iter().enumerate() {
+            for (second, second_position) in positions[index + 1..]
+                .iter()
+                .take_while(|(_, p)| p.time.0 - first_position.time.0 < TIME_EPSILON)
+            {
+                let is_contact = |note: &NoteRef| matches!(note, NoteRef::Contact(_));
+                if is_contact(first) == is_contact(second)
+                    && (first_position.x - second_position.x).abs() < X_EPSILON
+                {
+                    self.push(
+                        Some(first_position.time),
+                        IssueKind::OverlappingNotes {
+                            first: *first,
+                            second: *second,
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Runs every check on `chart`. Issues are sorted by time, issues without a time come first.
+pub fn validate(chart: &Chart) -> Vec<ValidationIssue> {
+    let mut validator = Validator {
+        chart,
+        issues: Vec::new(),
+    };
+    validator.validate_composition();
+    validator.validate_track();
+    validator.validate_notes();
+
+    let mut issues = validator.issues;
+    issues.sort_by(|a, b| {
+        a.time
+            .map(|t| t.0)
+            .unwrap_or(f32::MIN)
+            .total_cmp(&b.time.map(|t| t.0).unwrap_or(f32::MIN))
+    });
+    issues
+}
+
+pub fn has_errors(issues: &[ValidationIssue]) -> bool {
+    issues.iter().any(|i| i.severity() == Severity::Error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ecl::create_chart_from_ecl_source;
+
+    const CHART: &str = "\
+<header>
+default_tempo=120
+default_time_signature=4/4
+
+<chart_body>
+[PRS] (1) |-1;1|
+[B1] (2) |0|
+[B2] (2) |0.5|
+[HB1] (3;1,1) |0|
+--
+[B1] (1) |0.5|
+";
+
+    fn issues(source: &str) -> Vec<ValidationIssue> {
+        validate(&create_chart_from_ecl_source(source).unwrap())
+    }
+
+    #[test]
+    fn valid_chart_has_no_issues() {
+        assert_eq!(issues(CHART), Vec::new());
+    }
+
+    #[test]
+    fn notes_off_the_platforms_are_warnings() {
+        let issues = issues(&CHART.replace("[B2] (2) |0.5|", "[B2] (2) |2|"));
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(
+            issues[0].kind,
+            IssueKind::NoteOutsidePlatform {
+                note: NoteRef::Hit(1),
+                ..
+            }
+        ));
+        assert_eq!(issues[0].severity(), Severity::Warning);
+        assert!(!has_errors(&issues));
+    }
+
+    #[test]
+    fn overlapping_notes_are_warnings() {
+        let issues = issues(&CHART.replace("[B2] (2) |0.5|", "[B2] (2) |0|"));
+        assert_eq!(issues.len(), 1);
+        assert!(matches!(issues[0].kind, IssueKind::OverlappingNotes { .. }));
+        assert!(!has_errors(&issues));
+    }
+
+    #[test]
+    fn holds_need_two_points() {
+        let mut chart = create_chart_from_ecl_source(CHART).unwrap();
+        chart.data.notes.holds[0].points.truncate(1);
+
+        let issues = validate(&chart);
+        assert_eq!(
+            issues[0].kind,
+            IssueKind::HoldTooShort {
+                hold: NoteRef::Hold(0),
+                num_points: 1
+            }
+        );
+        assert!(has_errors(&issues));
+        assert_eq!(
+            issues[0].to_string(),
+            "1s: Hold(0) has 1 point(s), at least 2 are required"
+        );
+    }
+}
@@ -0,0 +1,976 @@
+//! Writer for the native `.ecl` chart format. Charts imported from other formats can hold data ECL
+//! cannot express, the writer logs a warning for each and writes:
+//! - no lanes, ECL tracks only have platforms.
+//! - critical hit and hold notes as regular notes.
+//! - platforms with more than one segment per edge as one `PR`/`PQ` platform per segment, bezier
+//!   edges are flattened.
+//! - evade notes without their player target and speed, large needles and squares at normal size.
+
+use std::{fmt::Write as _, fs};
+
+use crate::{
+    animation::{Animation, AnimationKind, AnimationLibrary, Vec3},
+    error::{ChartError, SourceLocation},
+    timing::{BeatPosition, TimingMap},
+    util::x_at,
+    Chart, ContactNoteType, EdgeSegment, EvadeNoteSize, EvadeNoteType, FlickDirection, HitNoteType,
+    MovementTarget, NoteStyleKey, Platform, PlatformEdge, Time, TimeSignature, TrackPosition,
+};
+
+/// Times closer than this are treated as the same point in seconds.
+const TIME_EPSILON: f32 = 1e-3;
+
+/// Beats closer than this to a whole number are written as whole numbers.
+const BEAT_EPSILON: f32 = 1e-3;
+
+/// Highest subdivision tried when searching for a measure subdivision where every beat is a whole
+/// number.
+const MAX_SUBDIVISION: u32 = 192;
+
+type Result<T> = std::result::Result<T, ChartError>;
+
+fn format_beat(value: f32) -> String {
+    let rounded = value.round();
+    if (value - rounded).abs() < BEAT_EPSILON {
+        format!("{}", rounded as i64)
+    } else {
+        format!("{}", value)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct MeasureLayout {
+    index: usize,
+    /// Tempo at the start of the measure.
+    bpm: u32,
+    time_signature: TimeSignature,
+    /// Notes are written in units of `1 / subdivision` notes.
+    subdivision: u32,
+}
+
+impl MeasureLayout {
+    /// Zero-based offset from the start of the measure, in subdivision units.
+    fn units_at(&self, timing_map: &TimingMap, time: Time, subdivision: u32) -> f32 {
+        timing_map.beat_at(self.index as _, time) * subdivision as f32
+            / self.time_signature.note_value as f32
+    }
+
+    fn is_whole_beat(&self, timing_map: &TimingMap, time: Time, subdivision: u32) -> bool {
+        let units = self.units_at(timing_map, time, subdivision);
+        let rounded_time = timing_map.time_at(BeatPosition::new(
+            self.index as _,
+            units.round() * self.time_signature.note_value as f32 / subdivision as f32,
+        ));
+        (units - units.round()).abs() < BEAT_EPSILON
+            && (time.0 - rounded_time.0).abs() < TIME_EPSILON
+    }
+
+    /// Picks the time signature's note value if it puts every time on a whole beat, otherwise the
+    /// smallest subdivision that puts the most times on a whole beat. The remaining times are
+    /// written as fractional beats.
+    fn fit_subdivision(&mut self, timing_map: &TimingMap, times: &[Time]) {
+        let note_value = self.time_signature.note_value;
+        if times
+            .iter()
+            .all(|t| self.is_whole_beat(timing_map, *t, note_value))
+        {
+            self.subdivision = note_value;
+            return;
+        }
+
+        let mut best = (note_value, 0);
+        for subdivision in 1..MAX_SUBDIVISION + 1 {
+            let count = times
+                .iter()
+                .filter(|t| self.is_whole_beat(timing_map, **t, subdivision))
+                .count();
+            if count > best.1 {
+                best = (subdivision, count);
+            }
+        }
+        self.subdivision = best.0;
+    }
+}
+
+/// Measure layout taken from the chart's timing map.
+struct Measures<'a> {
+    timing_map: &'a TimingMap,
+    layouts: Vec<MeasureLayout>,
+}
+
+impl<'a> Measures<'a> {
+    fn from_chart(chart: &'a Chart, last_time: Time) -> Result<Self> {
+        let timing_map = chart.utils.z_position_calculator.timing_map();
+
+        let mut measures = Self {
+            timing_map,
+            layouts: Vec::new(),
+        };
+        // The measure containing the last time, or starting at it, must exist.
+        let num_measures = measures.index_at(last_time)? + 1;
+
+        measures.layouts = (0..num_measures)
+            .map(|index| {
+                let time_signature = timing_map.time_signature_at(index as _);
+                let bpm = timing_map.bpm_at(timing_map.measure_start(index as _));
+                if bpm.fract() != 0.0 {
+                    log::warn!(
+                        "Rounding tempo {} at measure {} as the format only supports integers",
+                        bpm,
+                        index + 1
+                    );
+                }
+
+                MeasureLayout {
+                    index,
+                    bpm: bpm.round() as _,
+                    time_signature,
+                    subdivision: time_signature.note_value,
+                }
+            })
+            .collect();
+
+        Ok(measures)
+    }
+
+    fn index_at(&self, time: Time) -> Result<usize> {
+        // Times right before a bar line belong to the next measure.
+        let measure = self
+            .timing_map
+            .position_at(Time(time.0 + TIME_EPSILON))
+            .measure;
+        usize::try_from(measure).map_err(|_| {
+            ChartError::write(format!(
+                "time {}s is before the start of the first measure at {}s",
+                time.0,
+                self.timing_map.measure_start(0).0
+            ))
+        })
+    }
+}
+
+struct EclChartWriter<'a> {
+    chart: &'a Chart,
+    measures: Measures<'a>,
+    /// Animations of the chart followed by translations created for moving evade notes without
+    /// animations.
+    animations: AnimationLibrary,
+}
+
+impl<'a> EclChartWriter<'a> {
+    fn new(chart: &'a Chart) -> Result<Self> {
+        let times = Self::all_times(chart);
+        let last_time = times
+            .iter()
+            .copied()
+            .fold(Time(chart.header.audio_offset), |a, b| {
+                if b.0 > a.0 {
+                    b
+                } else {
+                    a
+                }
+            });
+
+        let mut measures = Measures::from_chart(chart, last_time)?;
+
+        let mut measure_times = vec![Vec::new(); measures.layouts.len()];
+        for time in times {
+            measure_times[measures.index_at(time)?].push(time);
+        }
+        let timing_map = measures.timing_map;
+        for (layout, times) in measures.layouts.iter_mut().zip(measure_times.iter()) {
+            layout.fit_subdivision(timing_map, times);
+        }
+
+        Ok(Self {
+            chart,
+            measures,
+            animations: chart.data.animations.clone(),
+        })
+    }
+
+    /// Every time that is written as a beat.
+    fn all_times(chart: &Chart) -> Vec<Time> {
+        let data = &chart.data;
+        let notes = &data.notes;
+
+        // Bezier control points are written as beats too.
+        let platform_positions = data
+            .track
+            .platforms
+            .iter()
+            .flat_map(|p| p.left_segments().into_iter().chain(p.right_segments()))
+            .flat_map(|segment| match segment {
+                EdgeSegment::Straight { start, end } => vec![start, end],
+                EdgeSegment::CubicBezier {
+                    start,
+                    control_points,
+                    end,
+                } => vec![start, control_points.0, control_points.1, end],
+            })
+            .collect::<Vec<_>>();
+
+        platform_positions
+            .iter()
+            .chain(notes.hits.iter().map(|n| &n.position))
+            .chain(notes.holds.iter().flat_map(|n| n.points.iter()))
+            .chain(notes.targets.iter().map(|n| &n.position))
+            .chain(notes.target_holds.iter().flat_map(|n| n.points.iter()))
+            .chain(notes.contacts.iter().map(|n| &n.position))
+            .chain(notes.flicks.iter().map(|n| &n.position))
+            .chain(notes.evades.iter().map(|n| &n.movement.end))
+            .map(|p| p.time)
+            .chain(data.composition.bpm_changes.iter().map(|c| c.time))
+            .chain(
+                data.composition
+                    .soflans
+                    .iter()
+                    .flat_map(|s| [s.time, Time(s.time.0 + s.duration)]),
+            )
+            .collect()
+    }
+
+    /// Beat of `time` written relative to the measure at `measure_index`.
+    fn beat(&self, measure_index: usize, time: Time) -> Result<String> {
+        let time_measure_index = self.measures.index_at(time)?;
+        let layout = &self.measures.layouts[time_measure_index];
+        let beat =
+            format_beat(layout.units_at(self.measures.timing_map, time, layout.subdivision) + 1.0);
+
+        match time_measure_index.checked_sub(measure_index) {
+            Some(0) => Ok(beat),
+            Some(bars_ahead) => Ok(format!("{},{}", beat, bars_ahead)),
+            None => Err(ChartError::write(format!(
+                "time {}s is before its object's starting measure",
+                time.0
+            ))),
+        }
+    }
+
+    fn beats(&self, measure_index: usize, times: impl Iterator<Item = Time>) -> Result<String> {
+        Ok(times
+            .map(|t| self.beat(measure_index, t))
+            .collect::<Result<Vec<_>>>()?
+            .join(";"))
+    }
+
+    /// Position vector, the z component is the offset from the z position of the time.
+    fn position(&self, position: &TrackPosition) -> String {
+        let z_offset = position.z - self.chart.utils.z_position_calculator.z_at(position.time);
+        if position.y != 0.0 {
+            format!("{},{},{}", position.x, position.y, z_offset)
+        } else if z_offset.abs() < 1e-4 {
+            format!("{}", position.x)
+        } else {
+            format!("{},{}", position.x, z_offset)
+        }
+    }
+
+    fn positions<'b>(&self, positions: impl Iterator<Item = &'b TrackPosition>) -> String {
+        positions
+            .map(|p| self.position(p))
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+
+    fn hit_note_tag(ty: HitNoteType) -> &'static str {
+        match ty {
+            HitNoteType::Left => "B1",
+            HitNoteType::Center => "B2",
+            HitNoteType::Right => "B3",
+            HitNoteType::WallLeft => "B4",
+            HitNoteType::WallRight => "B5",
+        }
+    }
+
+    fn contact_note_tag(ty: ContactNoteType) -> &'static str {
+        match ty {
+            ContactNoteType::Primary => "C1",
+            ContactNoteType::Secondary => "C2",
+        }
+    }
+
+    /// Only circles have a large evade note, other large bullets are written at normal size.
+    fn evade_note_tag(ty: EvadeNoteType, size: EvadeNoteSize) -> &'static str {
+        match (ty, size) {
+            (EvadeNoteType::Circle, EvadeNoteSize::Normal) => "E1",
+            (EvadeNoteType::Needle, _) => "E2",
+            (EvadeNoteType::Square, _) => "E3",
+            (EvadeNoteType::Circle, EvadeNoteSize::Large) => "E4",
+        }
+    }
+
+    /// Heading of a note kind in the notes section, `None` for large needles and squares which
+    /// have no evade note number.
+    fn note_style_name(key: NoteStyleKey) -> Option<&'static str> {
+        let name = match key {
+            NoteStyleKey::Hit(HitNoteType::Left) => "basic_1",
+            NoteStyleKey::Hit(HitNoteType::Center) => "basic_2",
+            NoteStyleKey::Hit(HitNoteType::Right) => "basic_3",
+            NoteStyleKey::Hit(HitNoteType::WallLeft) => "basic_4",
+            NoteStyleKey::Hit(HitNoteType::WallRight) => "basic_5",
+            NoteStyleKey::Target => "target",
+            NoteStyleKey::Flick => "flick",
+            NoteStyleKey::Evade(EvadeNoteType::Circle, EvadeNoteSize::Normal) => "evade_1",
+            NoteStyleKey::Evade(EvadeNoteType::Needle, EvadeNoteSize::Normal) => "evade_2",
+            NoteStyleKey::Evade(EvadeNoteType::Square, EvadeNoteSize::Normal) => "evade_3",
+            NoteStyleKey::Evade(EvadeNoteType::Circle, EvadeNoteSize::Large) => "evade_4",
+            NoteStyleKey::Evade(_, EvadeNoteSize::Large) => return None,
+            NoteStyleKey::Contact(ContactNoteType::Primary) => "contact_1",
+            NoteStyleKey::Contact(ContactNoteType::Secondary) => "contact_2",
+        };
+        Some(name)
+    }
+
+    /// Animations section with one line per animation, rotation angles are written in degrees and
+    /// durations in milliseconds.
+    fn write_animations(&self, output: &mut String) -> Result<()> {
+        if self.animations.is_empty() {
+            return Ok(());
+        }
+
+        let vec3 = |v: Vec3| format!("{},{},{}", v.x, v.y, v.z);
+        writeln!(output, "\n<animations>")?;
+        for (_, animation) in self.animations.iter() {
+            let (ty, v0, v1) = match animation.kind {
+                AnimationKind::Translation { from, to } => ("t", from, to),
+                AnimationKind::Rotation { angles, center } => (
+                    "r",
+                    Vec3::new(
+                        angles.x.to_degrees(),
+                        angles.y.to_degrees(),
+                        angles.z.to_degrees(),
+                    ),
+                    center,
+                ),
+                AnimationKind::Scale { from, to } => ("s", from, to),
+            };
+            writeln!(
+                output,
+                "[{}] ({}) |{}| {{{};{}}}",
+                animation.name,
+                ty,
+                animation.duration * 1000.0,
+                vec3(v0),
+                vec3(v1)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Note kinds are sorted by name.
+    fn write_note_styles(&self, output: &mut String) -> Result<()> {
+        let mut styles = self
+            .chart
+            .note_styles
+            .iter()
+            .filter_map(|(key, style)| {
+                let color = style.color?;
+                let name = Self::note_style_name(key);
+                if name.is_none() {
+                    log::warn!(
+                        "Dropping the style of {:?} as ECL has no note type for it",
+                        key
+                    );
+                }
+                Some((name?, color))
+            })
+            .collect::<Vec<_>>();
+        styles.sort_by_key(|(name, _)| *name);
+        if styles.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(output, "\n<notes>")?;
+        for (name, color) in styles {
+            writeln!(output, "[{}]", name)?;
+            writeln!(output, "color={:06X}", color.to_rgb_hex())?;
+        }
+        Ok(())
+    }
+
+    fn single_note_line(&self, tag: &str, position: &TrackPosition) -> Result<String> {
+        let measure_index = self.measures.index_at(position.time)?;
+        Ok(format!(
+            "[{}] ({}) |{}|",
+            tag,
+            self.beat(measure_index, position.time)?,
+            self.position(position)
+        ))
+    }
+
+    /// Curved holds are written as their flattened points.
+    fn hold_line(&self, tag: &str, points: &[TrackPosition]) -> Result<(Time, String)> {
+        let start = points
+            .first()
+            .ok_or_else(|| ChartError::write("hold note has no points"))?;
+        let measure_index = self.measures.index_at(start.time)?;
+        let line = format!(
+            "[{}] ({}) |{}|",
+            tag,
+            self.beats(measure_index, points.iter().map(|p| p.time))?,
+            self.positions(points.iter())
+        );
+        Ok((start.time, line))
+    }
+
+    /// Bezier control points as `x,beat : x,beat`.
+    fn control_points(
+        &self,
+        measure_index: usize,
+        control_points: (TrackPosition, TrackPosition),
+    ) -> Result<String> {
+        let (c0, c1) = control_points;
+        Ok(format!(
+            "{},{} : {},{}",
+            c0.x,
+            self.beat(measure_index, c0.time)?,
+            c1.x,
+            self.beat(measure_index, c1.time)?
+        ))
+    }
+
+    /// Platforms with a single segment per edge are written as one `PR`, `PQ` or `PC` line.
+    fn single_platform_line(&self, platform: &Platform) -> Result<Option<(Time, String)>> {
+        let (left, right) = match (
+            &platform.left_segments()[..],
+            &platform.right_segments()[..],
+        ) {
+            ([left], [right]) => (*left, *right),
+            _ => return Ok(None),
+        };
+        let (start, end) = (left.start().time, left.end().time);
+        if (right.start().time.0 - start.0).abs() > TIME_EPSILON
+            || (right.end().time.0 - end.0).abs() > TIME_EPSILON
+        {
+            return Ok(None);
+        }
+
+        let measure_index = self.measures.index_at(start)?;
+        let beats = self.beats(measure_index, [start, end].into_iter())?;
+        let (bottom_left, top_left) = (left.start().x, left.end().x);
+        let (bottom_right, top_right) = (right.start().x, right.end().x);
+
+        let edge = |edge: &PlatformEdge, segment: EdgeSegment| -> Result<String> {
+            match (edge, segment) {
+                (PlatformEdge::Mirror { .. }, _) => Ok("m".to_string()),
+                (_, EdgeSegment::Straight { .. }) => Ok(String::new()),
+                (_, EdgeSegment::CubicBezier { control_points, .. }) => {
+                    self.control_points(measure_index, control_points)
+                }
+            }
+        };
+        let options = (edge(&platform.left, left)?, edge(&platform.right, right)?);
+
+        let line = match options {
+            (l, r) if l.is_empty() && r.is_empty() => {
+                if bottom_left == top_left && bottom_right == top_right {
+                    format!("[PR] ({}) |{};{}|", beats, bottom_left, bottom_right)
+                } else {
+                    format!(
+                        "[PQ] ({}) |{};{};{};{}|",
+                        beats, bottom_left, bottom_right, top_left, top_right
+                    )
+                }
+            }
+            (l, r) => format!(
+                "[PC] ({}) |{};{};{};{}| {{{} ; {}}}",
+                beats, bottom_left, bottom_right, top_left, top_right, l, r
+            ),
+        };
+
+        Ok(Some((start, line)))
+    }
+
+    /// Platforms that do not fit in a single line are written as `PR`/`PQ` quads between every
+    /// point of either edge, bezier edges are flattened.
+    fn platform_lines(&self, platform: &Platform) -> Result<Vec<(Time, String)>> {
+        if let Some(line) = self.single_platform_line(platform)? {
+            return Ok(vec![line]);
+        }
+
+        let (left, right) = (platform.points_left(), platform.points_right());
+        if left.len() < 2 || right.len() < 2 {
+            return Err(ChartError::write("platform edges need at least two points"));
+        }
+        log::warn!(
+            "Splitting platform at {}s into one platform per segment as ECL platforms have a \
+             single segment per edge",
+            left[0].time.0
+        );
+
+        let mut times = left
+            .iter()
+            .chain(right.iter())
+            .map(|p| p.time)
+            .collect::<Vec<_>>();
+        times.sort_by(|a, b| a.0.total_cmp(&b.0));
+        times.dedup_by(|a, b| (a.0 - b.0).abs() < TIME_EPSILON);
+
+        times
+            .windows(2)
+            .map(|window| {
+                let (start, end) = (window[0], window[1]);
+                let measure_index = self.measures.index_at(start)?;
+
+                let (bottom_left, top_left) = (x_at(&left, start), x_at(&left, end));
+                let (bottom_right, top_right) = (x_at(&right, start), x_at(&right, end));
+
+                let beats = self.beats(measure_index, [start, end].into_iter())?;
+                let line = if bottom_left == top_left && bottom_right == top_right {
+                    format!("[PR] ({}) |{};{}|", beats, bottom_left, bottom_right)
+                } else {
+                    format!(
+                        "[PQ] ({}) |{};{};{};{}|",
+                        beats, bottom_left, bottom_right, top_left, top_right
+                    )
+                };
+
+                Ok((start, line))
+            })
+            .collect()
+    }
+
+    /// Name of a translation from the offset back to the note's written position, animations
+    /// with the same values are shared.
+    fn translation_name(&mut self, offset: Vec3, duration: f32) -> String {
+        let kind = AnimationKind::Translation {
+            from: offset,
+            to: Vec3::ZERO,
+        };
+        if let Some((_, animation)) = self
+            .animations
+            .iter()
+            .find(|(_, a)| a.kind == kind && a.duration == duration)
+        {
+            return animation.name.clone();
+        }
+
+        let name = (1..)
+            .map(|n| format!("move_{}", n))
+            .find(|name| self.animations.find(name).is_none())
+            .unwrap();
+        self.animations.add(Animation {
+            name: name.clone(),
+            kind,
+            duration,
+        });
+        name
+    }
+
+    /// Body lines of every measure, sorted by time.
+    fn body_lines(&mut self) -> Result<Vec<Vec<String>>> {
+        let mut lines: Vec<Vec<(Time, String)>> = vec![Vec::new(); self.measures.layouts.len()];
+        let mut push = |time: Time, line: String| -> Result<()> {
+            lines[self.measures.index_at(time)?].push((time, line));
+            Ok(())
+        };
+
+        let data = &self.chart.data;
+        let timing_map = self.measures.timing_map;
+        for change in data.composition.bpm_changes.iter() {
+            let measure_index = self.measures.index_at(change.time)?;
+            // Changes at the start of a measure are written as measure options.
+            if (change.time.0 - timing_map.measure_start(measure_index as _).0).abs() < TIME_EPSILON
+            {
+                continue;
+            }
+            let line = format!(
+                "[BPM] ({}) {{{}}}",
+                self.beat(measure_index, change.time)?,
+                change.bpm
+            );
+            push(change.time, line)?;
+        }
+
+        for soflan in data.composition.soflans.iter() {
+            let measure_index = self.measures.index_at(soflan.time)?;
+            let end = Time(soflan.time.0 + soflan.duration);
+            let line = format!(
+                "[SPD] ({}) {{{}}}",
+                self.beats(measure_index, [soflan.time, end].into_iter())?,
+                soflan.speed_multiplier
+            );
+            push(soflan.time, line)?;
+        }
+
+        for platform in data.track.platforms.iter() {
+            for (time, line) in self.platform_lines(platform)? {
+                push(time, line)?;
+            }
+        }
+
+        for note in data.notes.hits.iter() {
+            if note.is_critical {
+                log::warn!(
+                    "Writing critical hit note at {}s as a regular note",
+                    note.position.time.0
+                );
+            }
+            push(
+                note.position.time,
+                self.single_note_line(Self::hit_note_tag(note.ty), &note.position)?,
+            )?;
+        }
+
+        for note in data.notes.targets.iter() {
+            push(
+                note.position.time,
+                self.single_note_line("T", &note.position)?,
+            )?;
+        }
+
+        for note in data.notes.holds.iter() {
+            if note.is_critical {
+                log::warn!(
+                    "Writing critical hold note at {}s as a regular note",
+                    note.points.first().map_or(0.0, |p| p.time.0)
+                );
+            }
+            let tag = format!("H{}", Self::hit_note_tag(note.ty));
+            let (time, line) = self.hold_line(&tag, &note.points)?;
+            push(time, line)?;
+        }
+
+        for note in data.notes.target_holds.iter() {
+            let (time, line) = self.hold_line("HT", &note.points)?;
+            push(time, line)?;
+        }
+
+        for note in data.notes.flicks.iter() {
+            let measure_index = self.measures.index_at(note.position.time)?;
+            let tag = match note.direction {
+                FlickDirection::Left => "FL",
+                FlickDirection::Right => "FR",
+            };
+            let line = format!(
+                "[{}] ({}) |{};{}|",
+                tag,
+                self.beat(measure_index, note.position.time)?,
+                self.position(&note.position),
+                note.end_x
+            );
+            push(note.position.time, line)?;
+        }
+
+        for note in data.notes.contacts.iter() {
+            let measure_index = self.measures.index_at(note.position.time)?;
+            let line = format!(
+                "[{}] ({}) |{}|",
+                Self::contact_note_tag(note.ty),
+                self.beat(measure_index, note.position.time)?,
+                self.position(&note.position)
+            );
+            push(note.position.time, line)?;
+        }
+
+        for note in data.notes.evades.iter() {
+            let movement = &note.movement;
+            let end = &movement.end;
+            // XXX TODO: Extend the format with these.
+            let is_lossy_size =
+                note.size == EvadeNoteSize::Large && note.ty != EvadeNoteType::Circle;
+            if movement.target != MovementTarget::Fixed || is_lossy_size || note.speed != 1.0 {
+                log::warn!(
+                    "Writing evade note at {}s without its target, size and speed",
+                    end.time.0
+                );
+            }
+            let measure_index = self.measures.index_at(end.time)?;
+            let mut line = format!(
+                "[{}] ({}) |{}|",
+                Self::evade_note_tag(note.ty, note.size),
+                self.beat(measure_index, end.time)?,
+                self.position(end)
+            );
+            if !note.animations.is_empty() {
+                let names = note
+                    .animations
+                    .iter()
+                    .filter_map(|id| self.animations.get(*id))
+                    .map(|a| a.name.as_str())
+                    .collect::<Vec<_>>();
+                write!(line, " {{{}}}", names.join(", "))?;
+            } else if !movement.is_static() {
+                let offset = Vec3::new(
+                    movement.start.x - end.x,
+                    movement.start.y - end.y,
+                    movement.start.z - end.z,
+                );
+                let name = self.translation_name(offset, movement.duration);
+                write!(line, " {{{}}}", name)?;
+            }
+            lines[self.measures.index_at(end.time)?].push((end.time, line));
+        }
+
+        Ok(lines
+            .into_iter()
+            .map(|mut measure_lines| {
+                measure_lines.sort_by(|a, b| a.0 .0.total_cmp(&b.0 .0));
+                measure_lines.into_iter().map(|(_, line)| line).collect()
+            })
+            .collect())
+    }
+
+    fn write(mut self) -> Result<String> {
+        let lanes = &self.chart.data.track.lanes;
+        if !lanes.is_empty() {
+            log::warn!(
+                "Dropping {} lane segment(s) as ECL has no lanes",
+                lanes.len()
+            );
+        }
+        let body_lines = self.body_lines()?;
+        let mut output = String::new();
+
+        let header = &self.chart.header;
+        let first_measure = self.measures.layouts[0];
+        writeln!(output, "<header>")?;
+        for (key, value) in [
+            ("title", &header.title),
+            ("artist", &header.artist),
+            ("chart_designer", &header.chart_designer),
+            ("difficulty", &header.difficulty),
+        ] {
+            if !value.is_empty() {
+                writeln!(output, "{}={}", key, value)?;
+            }
+        }
+        if let Some(level) = header.level {
+            writeln!(output, "level={}", level)?;
+        }
+        if let Some(bpm_range) = header.bpm_range {
+            writeln!(output, "bpm_range={}-{}", bpm_range.min, bpm_range.max)?;
+        }
+        if header.preview_length > 0.0 {
+            writeln!(output, "preview_start={}", header.preview_start * 1000.0)?;
+            writeln!(output, "preview_length={}", header.preview_length * 1000.0)?;
+        }
+        writeln!(output, "audio_filename={}", header.audio_filename)?;
+        writeln!(output, "default_tempo={}", first_measure.bpm)?;
+        writeln!(
+            output,
+            "default_time_signature={}/{}",
+            first_measure.time_signature.num_beats, first_measure.time_signature.note_value
+        )?;
+        writeln!(output, "offset={}", header.audio_offset * 1000.0)?;
+
+        self.write_note_styles(&mut output)?;
+
+        self.write_animations(&mut output)?;
+
+        writeln!(output, "\n<chart_body>")?;
+        let timing_map = self.measures.timing_map;
+        let mut previous_layout: Option<&MeasureLayout> = None;
+        for (index, (layout, lines)) in self
+            .measures
+            .layouts
+            .iter()
+            .zip(body_lines.iter())
+            .enumerate()
+        {
+            if index > 0 {
+                writeln!(output, "--")?;
+            }
+            // Measures keep the tempo the previous measure ended with.
+            let measure_start = timing_map.measure_start(index as _);
+            let previous_bpm = timing_map.bpm_at(Time(measure_start.0 - TIME_EPSILON));
+            if index > 0 && previous_bpm.round() as u32 != layout.bpm {
+                writeln!(output, "tempo={}", layout.bpm)?;
+            }
+            if previous_layout.is_some_and(|m| m.time_signature != layout.time_signature) {
+                writeln!(
+                    output,
+                    "time_signature={}/{}",
+                    layout.time_signature.num_beats, layout.time_signature.note_value
+                )?;
+            }
+            if layout.subdivision != layout.time_signature.note_value {
+                writeln!(output, "subdivision={}", layout.subdivision)?;
+            }
+            for line in lines {
+                writeln!(output, "{}", line)?;
+            }
+
+            previous_layout = Some(layout);
+        }
+
+        Ok(output)
+    }
+}
+
+pub fn write_chart_to_ecl(chart: &Chart) -> Result<String> {
+    EclChartWriter::new(chart)?.write()
+}
+
+pub fn write_chart_to_ecl_file(chart: &Chart, file_name: &str) -> Result<()> {
+    let output = write_chart_to_ecl(chart).map_err(|e| e.with_file(file_name))?;
+    fs::write(file_name, output).map_err(|source| ChartError::Io {
+        location: SourceLocation::file(file_name),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ecl::create_chart_from_ecl_source;
+
+    const CHART: &str = "\
+<header>
+title=Round trip
+default_tempo=120
+default_time_signature=4/4
+offset=250
+
+<notes>
+[basic_1]
+color=FF0000
+
+<animations>
+[approach] (t) |500| {0,1,2;0,0,0}
+[spin] (r) |250| {0,90,0;0,0,0}
+
+<chart_body>
+[PR] (1;5,1) |-1;1|
+[B1] (1) |0|
+[B2] (2) |0.5,0.25,0|
+[HB3] (3;1,1) |0.5|
+[SPD] (2;4) {0.5}
+[BPM] (3.5) {180}
+--
+[FL] (1) |0;-1|
+[FR] (2) |0.25;1|
+[E1] (3) |0.2| {approach, spin}
+[C2] (4) |0.1,0,0.3|
+[T] (4) |-0.5|
+";
+
+    fn midpoint(a: TrackPosition, b: TrackPosition) -> TrackPosition {
+        TrackPosition {
+            time: Time((a.time.0 + b.time.0) / 2.0),
+            z: (a.z + b.z) / 2.0,
+            x: (a.x + b.x) / 2.0,
+            y: 0.0,
+        }
+    }
+
+    fn round_trip(source: &str) -> (Chart, Chart, String) {
+        let chart = create_chart_from_ecl_source(source).unwrap();
+        let written = write_chart_to_ecl(&chart).unwrap();
+        let reparsed = create_chart_from_ecl_source(&written).unwrap();
+        (chart, reparsed, written)
+    }
+
+    #[test]
+    fn round_trip_keeps_chart_content() {
+        let (chart, reparsed, written) = round_trip(CHART);
+        assert_eq!(chart.content_hash(), reparsed.content_hash(), "{}", written);
+        assert_eq!(chart.header.title, reparsed.header.title);
+        assert_eq!(chart.header.audio_offset, reparsed.header.audio_offset);
+        assert_eq!(
+            format!("{:?}", chart.note_styles),
+            format!("{:?}", reparsed.note_styles)
+        );
+
+        // Writing the re-parsed chart gives the same source.
+        assert_eq!(written, write_chart_to_ecl(&reparsed).unwrap());
+    }
+
+    #[test]
+    fn round_trip_keeps_flick_ends_and_soflans() {
+        let (chart, reparsed, _) = round_trip(CHART);
+
+        let end_xs = |c: &Chart| {
+            c.data
+                .notes
+                .flicks
+                .iter()
+                .map(|f| f.end_x)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(end_xs(&chart), vec![-1.0, 1.0]);
+        assert_eq!(end_xs(&chart), end_xs(&reparsed));
+
+        let soflans = &reparsed.data.composition.soflans;
+        assert_eq!(soflans.len(), 1);
+        assert_eq!(soflans[0].speed_multiplier, 0.5);
+        assert!((soflans[0].time.0 - 0.75).abs() < 1e-4);
+        // Ends after the tempo change to 180 BPM at beat 3.5.
+        assert!((soflans[0].duration - (0.75 + 0.5 / 3.0)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn round_trip_keeps_wall_sides() {
+        let source = CHART
+            .replace("[B1] (1) |0|", "[B4] (1) |0|")
+            .replace("[B2] (2)", "[B5] (2)")
+            .replace("[basic_1]", "[basic_5]");
+        let (chart, reparsed, written) = round_trip(&source);
+        assert!(
+            written.contains("[B5]") && written.contains("[basic_5]"),
+            "{}",
+            written
+        );
+
+        let types = |c: &Chart| c.data.notes.hits.iter().map(|n| n.ty).collect::<Vec<_>>();
+        assert_eq!(
+            types(&reparsed),
+            vec![HitNoteType::WallLeft, HitNoteType::WallRight]
+        );
+        assert_eq!(types(&chart), types(&reparsed));
+        assert!(reparsed
+            .note_styles
+            .color(NoteStyleKey::Hit(HitNoteType::WallRight))
+            .is_some());
+    }
+
+    #[test]
+    fn round_trip_keeps_large_circles() {
+        let (_, reparsed, written) = round_trip(&CHART.replace("[E1]", "[E4]"));
+        assert!(written.contains("[E4]"), "{}", written);
+        assert_eq!(reparsed.data.notes.evades[0].size, EvadeNoteSize::Large);
+    }
+
+    #[test]
+    fn multi_segment_platforms_are_split() {
+        let mut chart = create_chart_from_ecl_source(CHART).unwrap();
+        let platform = &mut chart.data.track.platforms[0];
+        let (left, right) = (platform.points_left(), platform.points_right());
+        let middle = |points: &[TrackPosition], x_offset: f32| TrackPosition {
+            x: points[0].x + x_offset,
+            ..midpoint(points[0], points[1])
+        };
+        platform.left = PlatformEdge::from_points(&[left[0], middle(&left, -1.0), left[1]]);
+        platform.right = PlatformEdge::from_points(&[right[0], middle(&right, 1.0), right[1]]);
+
+        let written = write_chart_to_ecl(&chart).unwrap();
+        let reparsed = create_chart_from_ecl_source(&written).unwrap();
+        assert_eq!(reparsed.data.track.platforms.len(), 2, "{}", written);
+    }
+
+    #[test]
+    fn times_before_the_first_measure_are_an_error() {
+        let mut chart = create_chart_from_ecl_source(CHART).unwrap();
+        chart.data.notes.hits[0].position.time = Time(-1.0);
+        let error = write_chart_to_ecl(&chart).unwrap_err();
+        assert!(matches!(error, ChartError::Write { .. }), "{}", error);
+    }
+
+    #[test]
+    fn moving_evades_without_animations_keep_their_height() {
+        let mut chart = create_chart_from_ecl_source(CHART).unwrap();
+        let evade = &mut chart.data.notes.evades[0];
+        evade.animations.clear();
+        evade.movement.start.y = evade.movement.end.y + 1.5;
+
+        let written = write_chart_to_ecl(&chart).unwrap();
+        let reparsed = create_chart_from_ecl_source(&written).unwrap();
+        let movement = &reparsed.data.notes.evades[0].movement;
+        assert!(
+            (movement.start.y - movement.end.y - 1.5).abs() < 1e-4,
+            "{}",
+            written
+        );
+    }
+}
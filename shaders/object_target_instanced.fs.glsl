@@ -0,0 +1,15 @@
+#version 460 core
+
+#pragma shader_stage(fragment)
+
+layout(location = 0) in vec4 color;
+layout(location = 1) in float height;
+
+layout(location = 0) out vec4 outFragColor;
+
+void main()
+{
+    // Lighter towards the tip so targets read as solid objects instead of flat notes.
+    float brightness = mix(0.6, 1.4, clamp(height / 0.25, 0.0, 1.0));
+    outFragColor = vec4(min(color.rgb * brightness, vec3(1.0)), color.a);
+}
@@ -0,0 +1,41 @@
+#version 460 core
+
+#pragma shader_stage(vertex)
+
+layout(location = 0) in vec3 position;
+
+layout(location = 0) out vec4 color;
+layout(location = 1) out float height;
+
+struct TargetInstanceData
+{
+    mat4 model;
+    vec4 color;
+    uint applyRunnerTransform;
+};
+
+layout(std140, binding = 0) uniform GlobalSceneUbo
+{
+    mat4 viewProj;
+    mat4 runnerTransform;
+}
+global;
+
+layout(std430, binding = 1) readonly buffer TargetInstanceDataSbo
+{
+    TargetInstanceData instances[];
+};
+
+void main()
+{
+    TargetInstanceData instanceData = instances[gl_InstanceIndex];
+
+    mat4 appliedRunnerTransform = instanceData.applyRunnerTransform * global.runnerTransform +
+                            (1 - instanceData.applyRunnerTransform) * mat4(1.0);
+
+    gl_Position = global.viewProj * appliedRunnerTransform * instanceData.model * vec4(position, 1.0);
+
+    color = instanceData.color;
+    // Mesh space height, the target mesh goes from the track up.
+    height = position.y;
+}